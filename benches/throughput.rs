@@ -0,0 +1,165 @@
+//! Throughput of `JsonStreamReader::read` and `read_into`, reading a
+//! document in 8 KiB chunks as from a file. `read` returns owned tokens,
+//! `read_into` reuses one `TokenBuffer`.
+//!
+//! `cargo bench --bench throughput [-- FILE | MEGABYTES]` prints the best of
+//! five runs of each. The document is `FILE`, else `data/large-file.json`
+//! if there is one, else generated: an event log of `MEGABYTES` (50) that
+//! is the same on every run and machine. Both ways of reading are checked
+//! to give the same tokens first.
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use json_stream_reader::json_stream_reader::JsonStreamReader;
+use json_stream_reader::token_buffer::TokenBuffer;
+
+const CHUNK_SIZE: usize = 8 * 1024;
+const LARGE_FILE: &str = "data/large-file.json";
+const RUNS: usize = 5;
+
+// xorshift64, seeded the same way every time
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn word(&mut self, out: &mut String, len: u64) {
+        for _ in 0..len {
+            out.push((b'a' + self.below(26) as u8) as char);
+        }
+    }
+}
+
+fn event(rng: &mut Rng, id: u64, out: &mut String) {
+    const TYPES: [&str; 4] = ["PushEvent", "WatchEvent", "IssuesEvent", "CreateEvent"];
+    out.push_str(&format!(
+        r#"{{"id":"{}","type":"{}","public":{},"actor":{{"id":{},"login":""#,
+        id,
+        TYPES[rng.below(4) as usize],
+        rng.below(2) == 0,
+        rng.below(10_000_000),
+    ));
+    let len = 4 + rng.below(12);
+    rng.word(out, len);
+    out.push_str(r#"","gravatar_id":"","url":"https://api.github.com/users/"#);
+    rng.word(out, len);
+    out.push_str(r#""},"repo":{"id":"#);
+    out.push_str(&rng.below(100_000_000).to_string());
+    out.push_str(r#","name":""#);
+    rng.word(out, 10);
+    out.push_str(r#""},"payload":{"size":"#);
+    out.push_str(&rng.below(10).to_string());
+    out.push_str(r#","score":"#);
+    out.push_str(&format!("{:.3}", rng.below(1_000_000) as f64 / 997.0));
+    out.push_str(r#","commits":["#);
+    for n in 0..rng.below(4) {
+        if n > 0 {
+            out.push(',');
+        }
+        out.push_str(r#"{"sha":""#);
+        out.push_str(&format!("{:016x}{:016x}", rng.next(), rng.next()));
+        out.push_str(r#"","message":""#);
+        for _ in 0..1 + rng.below(12) {
+            let len = 1 + rng.below(9);
+            rng.word(out, len);
+            out.push_str(match rng.below(10) {
+                0 => "\\n",
+                1 => "\\\"",
+                _ => " ",
+            });
+        }
+        out.push_str(r#"","distinct":true,"parent":null}"#);
+    }
+    out.push_str(r#"]},"created_at":"2015-01-01T15:00:00Z"}"#);
+}
+
+fn document(megabytes: usize) -> Vec<u8> {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut out = String::from("[\n");
+    let mut id = 2_489_651_045;
+    while out.len() < megabytes << 20 {
+        if id > 2_489_651_045 {
+            out.push_str(",\n");
+        }
+        event(&mut rng, id, &mut out);
+        id += 1;
+    }
+    out.push_str("\n]\n");
+    out.into_bytes()
+}
+
+fn read(doc: &[u8]) -> usize {
+    let mut reader = JsonStreamReader::new();
+    let mut tokens = 0;
+    for chunk in doc.chunks(CHUNK_SIZE) {
+        tokens += black_box(reader.read(chunk).unwrap()).len();
+    }
+    tokens
+}
+
+fn read_into(doc: &[u8]) -> usize {
+    let mut reader = JsonStreamReader::new();
+    let mut out = TokenBuffer::new();
+    let mut tokens = 0;
+    for chunk in doc.chunks(CHUNK_SIZE) {
+        reader.read_into(chunk, &mut out).unwrap();
+        tokens += black_box(&out).len();
+    }
+    tokens
+}
+
+fn bench(name: &str, doc: &[u8], run: fn(&[u8]) -> usize) {
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = run(doc);
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{:<9} {} bytes, {} tokens, best of {}: {:.1?}, {:.1} MB/s",
+        name,
+        doc.len(),
+        tokens,
+        RUNS,
+        best,
+        doc.len() as f64 / best.as_secs_f64() / 1e6
+    );
+}
+
+fn check(doc: &[u8]) {
+    let mut reader = JsonStreamReader::new();
+    let mut reader_into = JsonStreamReader::new();
+    let mut out = TokenBuffer::new();
+    for chunk in doc.chunks(CHUNK_SIZE) {
+        let tokens = reader.read(chunk).unwrap();
+        reader_into.read_into(chunk, &mut out).unwrap();
+        assert!(tokens
+            .into_iter()
+            .eq(out.iter().map(|token| token.to_token())));
+    }
+}
+
+fn main() {
+    let arg = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let doc = match arg.as_deref().map(str::parse) {
+        Some(Ok(megabytes)) => document(megabytes),
+        Some(Err(_)) => fs::read(arg.unwrap()).unwrap(),
+        None if Path::new(LARGE_FILE).exists() => fs::read(LARGE_FILE).unwrap(),
+        None => document(50),
+    };
+    check(&doc);
+    bench("read", &doc, read);
+    bench("read_into", &doc, read_into);
+}
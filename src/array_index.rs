@@ -0,0 +1,399 @@
+//! Random access to the elements of huge arrays.
+//!
+//! `ArrayIndexer` reads a document once and records where the elements of
+//! the arrays at chosen paths start, every element or every `stride`-th
+//! one. The resulting `ArrayIndex` is saved next to the document as a
+//! compact blob. Later, `IndexedArray::open_element` seeks the document to
+//! the closest recorded element and resumes reading from there, so that
+//! element N is reached w/o reading what comes before it.
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::checkpoint::{put_varint, Bytes, Checkpoint};
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_pointer::{JsonPointer, PathPattern, PathTracker};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::token::{Lexeme, Nesting, State, Token};
+use crate::token_reader::TokenReader;
+
+const MAGIC: &[u8; 4] = b"JSRI";
+const VERSION: u8 = 1;
+
+/// Builds an `ArrayIndex` in one pass over a document.
+#[derive(Debug, Clone)]
+pub struct ArrayIndexer {
+    patterns: Vec<PathPattern>,
+    stride: usize,
+}
+
+impl ArrayIndexer {
+    /// Indexes the arrays at `pattern`, e.g. `""` for a top level array.
+    pub fn new(pattern: PathPattern) -> Self {
+        ArrayIndexer {
+            patterns: vec![pattern],
+            stride: 1,
+        }
+    }
+
+    /// Also indexes the arrays at `pattern`.
+    pub fn with_path(mut self, pattern: PathPattern) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Records the start of one element out of `stride`, the others being
+    /// skipped when opened. A larger stride makes a smaller index.
+    pub fn with_stride(mut self, stride: usize) -> Self {
+        self.stride = stride.max(1);
+        self
+    }
+
+    pub fn build<R: Read>(&self, source: R) -> Result<ArrayIndex> {
+        let mut tokens = TokenReader::new(source);
+        let mut tracker = PathTracker::new();
+        let mut arrays: Vec<IndexedArray> = vec![];
+        // indexed arrays open, innermost last
+        let mut open: Vec<usize> = vec![];
+        while let Some(token) = tokens.next_token()? {
+            tracker.push(&token);
+            match token {
+                JsonToken::ArrBeg
+                    if self
+                        .patterns
+                        .iter()
+                        .any(|pattern| pattern.matches(tracker.segments())) =>
+                {
+                    open.push(arrays.len());
+                    arrays.push(IndexedArray {
+                        pointer: tracker.pointer(),
+                        stack: tokens.checkpoint().map(|at| at.stack).unwrap_or_default(),
+                        stride: self.stride,
+                        len: 0,
+                        offsets: vec![],
+                    });
+                }
+                JsonToken::ArrEnd
+                    if open
+                        .last()
+                        .is_some_and(|&i| arrays[i].stack.depth() > tokens.depth()) =>
+                {
+                    open.pop();
+                }
+                // in the middle of an element
+                JsonToken::StrBegin | JsonToken::StrChunk(_) => continue,
+                _ => {}
+            }
+            let Some(&i) = open.last() else {
+                continue;
+            };
+            if arrays[i].stack.depth() != tokens.depth() {
+                continue;
+            }
+            // an element ends, or the array starts
+            if matches!(tokens.peek_token()?, None | Some(JsonToken::ArrEnd)) {
+                continue;
+            }
+            let array = &mut arrays[i];
+            if array.len % array.stride == 0 {
+                array.offsets.push(tokens.resume_offset());
+            }
+            array.len += 1;
+        }
+        Ok(ArrayIndex { arrays })
+    }
+}
+
+/// Where the elements of the indexed arrays of a document start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayIndex {
+    arrays: Vec<IndexedArray>,
+}
+
+impl ArrayIndex {
+    /// The indexed arrays, in the order they start in the document.
+    pub fn arrays(&self) -> &[IndexedArray] {
+        &self.arrays
+    }
+
+    pub fn array(&self, pointer: &JsonPointer) -> Option<&IndexedArray> {
+        self.arrays.iter().find(|array| array.pointer == *pointer)
+    }
+
+    /// The index as a blob, starting with a magic number and a format
+    /// version. Offsets are stored as LEB128 differences.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        put_varint(&mut out, self.arrays.len());
+        for array in &self.arrays {
+            let pointer = array.pointer.to_string();
+            put_varint(&mut out, pointer.len());
+            out.extend(pointer.as_bytes());
+            put_varint(&mut out, array.stack.depth());
+            for word in array.stack.words() {
+                out.extend(word.to_le_bytes());
+            }
+            put_varint(&mut out, array.stride);
+            put_varint(&mut out, array.len);
+            let mut last = 0;
+            for &offset in &array.offsets {
+                put_varint(&mut out, offset - last);
+                last = offset;
+            }
+        }
+        out
+    }
+
+    /// Reads a blob made by `to_bytes`. Fails with `InvalidIndex` located in
+    /// the blob if it is damaged or of another version.
+    pub fn from_bytes(blob: &[u8]) -> Result<ArrayIndex> {
+        let mut bytes = Bytes {
+            blob,
+            at: 0,
+            code: ErrorCode::InvalidIndex,
+        };
+        if bytes.take(MAGIC.len())? != MAGIC || bytes.u8()? != VERSION {
+            return Err(bytes.error());
+        }
+        let count = bytes.varint()?;
+        let mut arrays = vec![];
+        for _ in 0..count {
+            let len = bytes.varint()?;
+            let pointer = std::str::from_utf8(bytes.take(len)?)
+                .ok()
+                .and_then(|pointer| JsonPointer::parse(pointer).ok())
+                .ok_or_else(|| bytes.error())?;
+            let depth = bytes.varint()?;
+            let stack = bytes.nesting(depth)?;
+            let stride = bytes.varint()?;
+            let len = bytes.varint()?;
+            // checkpoints resume inside the array, see `is_consistent`
+            if stack.last() != Some(Token::Arr) || stride == 0 {
+                return Err(bytes.error());
+            }
+            let mut offsets = vec![];
+            let mut last = 0usize;
+            for _ in 0..len.div_ceil(stride) {
+                last = last
+                    .checked_add(bytes.varint()?)
+                    .ok_or_else(|| bytes.error())?;
+                offsets.push(last);
+            }
+            arrays.push(IndexedArray {
+                pointer,
+                stack,
+                stride,
+                len,
+                offsets,
+            });
+        }
+        if bytes.at < blob.len() {
+            return Err(bytes.error());
+        }
+        Ok(ArrayIndex { arrays })
+    }
+}
+
+/// An array of the document with where its elements start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedArray {
+    pointer: JsonPointer,
+    // containers open inside the array, itself included
+    stack: Nesting,
+    stride: usize,
+    len: usize,
+    // offsets of one element out of `stride`
+    offsets: Vec<usize>,
+}
+
+impl IndexedArray {
+    pub fn pointer(&self) -> &JsonPointer {
+        &self.pointer
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// A checkpoint right before the closest recorded element at or before
+    /// element `n`, and the index of that element.
+    pub fn checkpoint(&self, n: usize) -> Option<(usize, Checkpoint)> {
+        if n >= self.len {
+            return None;
+        }
+        let entry = n / self.stride;
+        let checkpoint = Checkpoint {
+            offset: self.offsets[entry],
+            stack: self.stack.clone(),
+            // the first element follows `[`, the others the previous element
+            state: if entry == 0 {
+                State::ArrFirst
+            } else {
+                State::AfterValue
+            },
+            lexeme: Lexeme::None,
+            scratch: vec![],
+            capture: false,
+        };
+        Some((entry * self.stride, checkpoint))
+    }
+
+    /// Seeks `source`, the indexed document, and returns a reader whose next
+    /// token starts element `n`. Fails with `PointerNotFound` if there is no
+    /// such element.
+    pub fn open_element<R: Read + Seek>(&self, mut source: R, n: usize) -> Result<TokenReader<R>> {
+        let (first, checkpoint) = self.checkpoint(n).ok_or(Error {
+            code: ErrorCode::PointerNotFound,
+            column: 0,
+        })?;
+        source
+            .seek(SeekFrom::Start(checkpoint.offset() as u64))
+            .map_err(|err| Error {
+                code: ErrorCode::Io(err.kind()),
+                column: checkpoint.offset(),
+            })?;
+        let mut tokens = TokenReader::with_reader(source, JsonStreamReader::resume(&checkpoint));
+        for _ in first..n {
+            tokens.skip_value()?;
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod array_index_tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::json_node::JsonNode;
+    use crate::tree_builder::TreeBuilder;
+
+    fn pattern(pattern: &str) -> PathPattern {
+        PathPattern::parse(pattern).unwrap()
+    }
+
+    fn element_at(tokens: &mut TokenReader<Cursor<&[u8]>>) -> JsonNode {
+        TreeBuilder::new().build_next(tokens).unwrap()
+    }
+
+    #[test]
+    fn should_open_any_element_of_top_level_array() {
+        let doc = br#" [ {"id": 0, "tags": ["a"]}, 1.5 , "two", [3, [3]], null, {}, 6 ] "#;
+        let expected = match JsonNode::from_reader(&doc[..]).unwrap() {
+            JsonNode::Array(items) => items,
+            _ => unreachable!(),
+        };
+        for stride in 1..=8 {
+            let index = ArrayIndexer::new(pattern(""))
+                .with_stride(stride)
+                .build(&doc[..])
+                .unwrap();
+            let index = ArrayIndex::from_bytes(&index.to_bytes()).unwrap();
+            let array = index.array(&JsonPointer::root()).unwrap();
+            assert_eq!(array.len(), expected.len());
+            for (n, item) in expected.iter().enumerate() {
+                let mut tokens = array.open_element(Cursor::new(&doc[..]), n).unwrap();
+                assert_eq!(&element_at(&mut tokens), item, "stride {}", stride);
+            }
+            let res = array.open_element(Cursor::new(&doc[..]), expected.len());
+            assert_eq!(res.err().unwrap().code, ErrorCode::PointerNotFound);
+        }
+    }
+
+    #[test]
+    fn should_index_arrays_at_paths() {
+        let doc =
+            br#"{"meta": [9], "rows": [{"items": [1, 2]}, {"items": []}, {"items": [[4], "5"]}]}"#;
+        let index = ArrayIndexer::new(pattern("/rows/*/items"))
+            .with_path(pattern("/rows"))
+            .build(&doc[..])
+            .unwrap();
+        let arrays: Vec<_> = index
+            .arrays()
+            .iter()
+            .map(|array| (array.pointer().to_string(), array.len()))
+            .collect();
+        assert_eq!(
+            arrays,
+            [
+                ("/rows".to_string(), 3),
+                ("/rows/0/items".to_string(), 2),
+                ("/rows/1/items".to_string(), 0),
+                ("/rows/2/items".to_string(), 2),
+            ]
+        );
+        let items = index
+            .array(&JsonPointer::parse("/rows/2/items").unwrap())
+            .unwrap();
+        let mut tokens = items.open_element(Cursor::new(&doc[..]), 1).unwrap();
+        assert_eq!(element_at(&mut tokens), JsonNode::String("5".to_string()));
+        // the reader carries on with the rest of the document
+        assert_eq!(tokens.next_token().unwrap(), Some(JsonToken::ArrEnd));
+        assert_eq!(tokens.depth(), 3);
+        tokens.skip_to_depth(0).unwrap();
+        assert_eq!(tokens.next_token().unwrap(), None);
+    }
+
+    #[test]
+    fn should_keep_index_compact() {
+        let doc = format!(
+            "[{}]",
+            (0..10_000)
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let index = ArrayIndexer::new(pattern(""))
+            .with_stride(10)
+            .build(doc.as_bytes())
+            .unwrap();
+        let blob = index.to_bytes();
+        // one byte per recorded element, the offsets being close
+        assert!(blob.len() < 1_100, "{} bytes", blob.len());
+        let array = &index.arrays()[0];
+        let mut tokens = array
+            .open_element(Cursor::new(doc.as_bytes()), 9_999)
+            .unwrap();
+        assert_eq!(
+            tokens.next_token().unwrap(),
+            Some(JsonToken::Val(crate::json_value::JsonValue::Number(
+                "9999".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn should_reject_damaged_index() {
+        let index = ArrayIndexer::new(pattern(""))
+            .build(&b"[1, 2, 3]"[..])
+            .unwrap();
+        let blob = index.to_bytes();
+        let res = ArrayIndex::from_bytes(&blob[..blob.len() - 1]);
+        assert_eq!(
+            res,
+            Err(Error {
+                code: ErrorCode::InvalidIndex,
+                column: blob.len() - 1
+            })
+        );
+        let mut other = blob.clone();
+        other[..4].copy_from_slice(b"JSRC");
+        assert_eq!(
+            ArrayIndex::from_bytes(&other).unwrap_err().code,
+            ErrorCode::InvalidIndex
+        );
+        // the array restored as an object, after the pointer and the depth
+        let mut object = blob.clone();
+        object[8] |= 1;
+        assert_eq!(
+            ArrayIndex::from_bytes(&object).unwrap_err().code,
+            ErrorCode::InvalidIndex
+        );
+        assert!(ArrayIndex::from_bytes(&blob).is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+//! Typed iteration over the elements of a (huge) array.
+//!
+//! Elements are deserialized one at a time, so memory stays constant no
+//! matter how long the array is.
+use std::io::Read;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use crate::de::from_element;
+use crate::error::{ErrorCode, Result};
+use crate::json_pointer::JsonPointer;
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::token_reader::TokenReader;
+
+pub struct ArrayIter<'a, T, R> {
+    tokens: TokenReader<R, &'a mut JsonStreamReader>,
+    // the pointer is resolved on the first call to `next`
+    pointer: Option<Result<JsonPointer>>,
+    // nesting level of the array elements
+    depth: usize,
+    done: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl JsonStreamReader {
+    /// Iterates over the elements of the top-level array read from `source`.
+    pub fn iter_array<T, R>(&mut self, source: R) -> ArrayIter<'_, T, R>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        ArrayIter::new(self, source, Ok(JsonPointer::root()))
+    }
+
+    /// Iterates over the elements of the array at `pointer`, e.g. `/data/rows`.
+    pub fn iter_array_at<T, R>(&mut self, source: R, pointer: &str) -> ArrayIter<'_, T, R>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        ArrayIter::new(self, source, JsonPointer::parse(pointer))
+    }
+}
+
+impl<'a, T: DeserializeOwned, R: Read> ArrayIter<'a, T, R> {
+    fn new(reader: &'a mut JsonStreamReader, source: R, pointer: Result<JsonPointer>) -> Self {
+        reader.clear();
+        ArrayIter {
+            tokens: TokenReader::with_reader(source, reader).with_raw_values(),
+            pointer: Some(pointer),
+            depth: 0,
+            done: false,
+            marker: PhantomData,
+        }
+    }
+
+    // Moves to the first element of the array.
+    fn start(&mut self, pointer: Result<JsonPointer>) -> Result<()> {
+        self.tokens.seek(&pointer?)?;
+        match self.tokens.next_token()? {
+            Some(JsonToken::ArrBeg) => {
+                self.depth = self.tokens.depth();
+                Ok(())
+            }
+            _ => Err(self.tokens.error(ErrorCode::ExpectedArray)),
+        }
+    }
+
+    fn next_element(&mut self) -> Result<Option<T>> {
+        if let Some(pointer) = self.pointer.take() {
+            self.start(pointer)?;
+        }
+        match self.tokens.peek_token()? {
+            Some(JsonToken::ArrEnd) => {
+                self.tokens.next_token()?;
+                return Ok(None);
+            }
+            Some(_) => {}
+            None => return Err(self.tokens.error(ErrorCode::UnexpectedEof)),
+        }
+        from_element(&mut self.tokens, self.depth).map(Some)
+    }
+}
+
+impl<T: DeserializeOwned, R: Read> Iterator for ArrayIter<'_, T, R> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_element() {
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Ok(Some(item)) => Some(Ok(item)),
+            Err(err) => {
+                // there is nothing to resume from if the array wasn't reached
+                if self.depth == 0 || self.tokens.is_failed() {
+                    self.done = true;
+                }
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod array_iter_tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Order {
+        id: u32,
+        qty: u32,
+    }
+
+    #[test]
+    fn should_iterate_top_level_array() {
+        let buf = r#"[{"id": 1, "qty": 10}, {"qty": 20, "id": 2}]"#;
+        let mut reader = JsonStreamReader::new();
+        let orders = reader
+            .iter_array::<Order, _>(buf.as_bytes())
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            orders.unwrap(),
+            vec![Order { id: 1, qty: 10 }, Order { id: 2, qty: 20 }]
+        );
+    }
+
+    #[test]
+    fn should_iterate_nested_array() {
+        let buf = r#"{"meta": {"count": 2}, "data": {"rows": [1, 2, 3]}}"#;
+        let mut reader = JsonStreamReader::new();
+        let rows = reader
+            .iter_array_at::<u8, _>(buf.as_bytes(), "/data/rows")
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(rows.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn should_skip_malformed_elements() {
+        let buf =
+            r#"[{"id": 1, "qty": 10}, {"id": "x", "qty": {"a": [1]}}, 7, {"id": 3, "qty": 30}]"#;
+        let mut reader = JsonStreamReader::new();
+        let res = reader
+            .iter_array::<Order, _>(buf.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(res.len(), 4);
+        assert_eq!(res[0], Ok(Order { id: 1, qty: 10 }));
+        assert!(res[1].is_err());
+        assert!(res[2].is_err());
+        assert_eq!(res[3], Ok(Order { id: 3, qty: 30 }));
+    }
+
+    #[test]
+    fn should_stop_on_syntax_error() {
+        let buf = r#"[1, 2 3, 4]"#;
+        let mut reader = JsonStreamReader::new();
+        let res = reader
+            .iter_array::<u8, _>(buf.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(res.len(), 1);
+        assert_eq!(
+            res[0].as_ref().unwrap_err().code,
+            ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd
+        );
+    }
+
+    #[test]
+    fn should_return_expected_array() {
+        let buf = r#"{"data": {"rows": 1}}"#;
+        let mut reader = JsonStreamReader::new();
+        let res = reader
+            .iter_array_at::<u8, _>(buf.as_bytes(), "/data/rows")
+            .collect::<Vec<_>>();
+        assert_eq!(res[0].as_ref().unwrap_err().code, ErrorCode::ExpectedArray);
+        assert_eq!(res.len(), 1);
+    }
+}
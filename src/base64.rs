@@ -0,0 +1,464 @@
+//! Streaming base64 decoding of string values.
+//!
+//! `decode_base64` looks for string values at a path such as
+//! `/attachments/*/data` and decodes them into a `Write` as their text is
+//! read. Long strings are read in fragments, so neither the text nor the
+//! decoded bytes are ever held in memory as a whole.
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_pointer::{JsonPointer, PathPattern, PathTracker};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+use crate::token_reader::TokenReader;
+
+// Strings longer than this are read in fragments.
+const FRAGMENT_THRESHOLD: usize = 1024;
+// Decoded bytes are written out in blocks of this size.
+const BLOCK_SIZE: usize = 8 * 1024;
+// Marks bytes out of the alphabet.
+const INVALID: u8 = 0xff;
+
+/// The alphabet and padding of base64 text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Base64 {
+    /// `+` and `/`, padded with `=` to a multiple of four characters.
+    #[default]
+    Standard,
+    /// `+` and `/`, w/o padding.
+    StandardNoPad,
+    /// `-` and `_`, padded with `=`.
+    UrlSafe,
+    /// `-` and `_`, w/o padding.
+    UrlSafeNoPad,
+}
+
+impl Base64 {
+    fn table(self) -> &'static [u8; 256] {
+        match self {
+            Base64::Standard | Base64::StandardNoPad => &STANDARD,
+            Base64::UrlSafe | Base64::UrlSafeNoPad => &URL_SAFE,
+        }
+    }
+
+    fn padded(self) -> bool {
+        matches!(self, Base64::Standard | Base64::UrlSafe)
+    }
+}
+
+static STANDARD: [u8; 256] = table(b'+', b'/');
+static URL_SAFE: [u8; 256] = table(b'-', b'_');
+
+const fn table(ch62: u8, ch63: u8) -> [u8; 256] {
+    let mut table = [INVALID; 256];
+    let mut i = 0;
+    while i < 26 {
+        table[(b'A' + i) as usize] = i;
+        table[(b'a' + i) as usize] = i + 26;
+        i += 1;
+    }
+    i = 0;
+    while i < 10 {
+        table[(b'0' + i) as usize] = i + 52;
+        i += 1;
+    }
+    table[ch62 as usize] = 62;
+    table[ch63 as usize] = 63;
+    table
+}
+
+/// Decodes base64 text pushed in pieces of any size into a `Write`.
+///
+/// Error columns are offsets in the base64 text. Bits left over by the
+/// last character are ignored.
+pub struct Base64Decoder<W> {
+    out: W,
+    alphabet: Base64,
+    // sextets of the current group of four characters
+    bits: u32,
+    len: usize,
+    // `=` read so far, only more of them may follow
+    padding: usize,
+    // characters read so far
+    read: usize,
+    block: Vec<u8>,
+}
+
+impl<W: Write> Base64Decoder<W> {
+    pub fn new(out: W, alphabet: Base64) -> Self {
+        Base64Decoder {
+            out,
+            alphabet,
+            bits: 0,
+            len: 0,
+            padding: 0,
+            read: 0,
+            block: Vec::with_capacity(BLOCK_SIZE),
+        }
+    }
+
+    /// Number of characters decoded so far.
+    pub fn position(&self) -> usize {
+        self.read
+    }
+
+    pub fn push(&mut self, text: &[u8]) -> Result<()> {
+        let table = self.alphabet.table();
+        for (i, &ch) in text.iter().enumerate() {
+            let sextet = table[ch as usize];
+            if sextet == INVALID || self.padding > 0 {
+                // `=` may only end a group of two or three characters
+                if ch != b'=' || !self.alphabet.padded() || self.len + self.padding < 2 {
+                    return Err(self.error(ErrorCode::InvalidBase64, self.read + i));
+                }
+                self.padding += 1;
+                if self.len + self.padding == 4 {
+                    self.flush_group();
+                    self.len = 4;
+                } else if self.len + self.padding > 4 {
+                    return Err(self.error(ErrorCode::InvalidBase64, self.read + i));
+                }
+                continue;
+            }
+            self.bits = self.bits << 6 | sextet as u32;
+            self.len += 1;
+            if self.len == 4 {
+                self.block.extend_from_slice(&self.bits.to_be_bytes()[1..]);
+                self.bits = 0;
+                self.len = 0;
+                if self.block.len() >= BLOCK_SIZE {
+                    self.write_block(self.read + i)?;
+                }
+            }
+        }
+        self.read += text.len();
+        Ok(())
+    }
+
+    /// Checks that the text is complete and returns the writer.
+    pub fn finish(mut self) -> Result<W> {
+        let complete = match self.len {
+            0 => true,
+            // a group ended by padding
+            4 => self.padding > 0,
+            1 => false,
+            _ => !self.alphabet.padded(),
+        };
+        if !complete {
+            return Err(self.error(ErrorCode::InvalidBase64, self.read));
+        }
+        if self.padding == 0 {
+            self.flush_group();
+        }
+        self.write_block(self.read)?;
+        self.out.flush().map_err(|err| self.io_error(err))?;
+        Ok(self.out)
+    }
+
+    // Decodes the characters of a group cut short.
+    fn flush_group(&mut self) {
+        let bytes = (self.len * 6 / 8).min(3);
+        let bits = self.bits << (6 * (4 - self.len));
+        self.block
+            .extend_from_slice(&bits.to_be_bytes()[1..1 + bytes]);
+        self.bits = 0;
+    }
+
+    fn write_block(&mut self, column: usize) -> Result<()> {
+        let res = self.out.write_all(&self.block);
+        self.block.clear();
+        res.map_err(|err| self.error(ErrorCode::Io(err.kind()), column))
+    }
+
+    fn io_error(&self, err: io::Error) -> Error {
+        self.error(ErrorCode::Io(err.kind()), self.read)
+    }
+
+    fn error(&self, code: ErrorCode, column: usize) -> Error {
+        Error { code, column }
+    }
+}
+
+/// Decodes every string value matching `pattern` into `out`, one after the
+/// other. Errors are located in the stream read from `source`.
+pub fn decode_base64<R: Read, W: Write>(
+    source: R,
+    pattern: &PathPattern,
+    alphabet: Base64,
+    out: W,
+) -> Result<W> {
+    let out = RefCell::new(out);
+    decode_base64_each(source, pattern, alphabet, |_| Ok(Shared(&out)))?;
+    Ok(out.into_inner())
+}
+
+/// Decodes every string value matching `pattern` into a writer of its own,
+/// created by `open` with the path of the value. Returns the number of
+/// values decoded.
+pub fn decode_base64_each<R, W, F>(
+    source: R,
+    pattern: &PathPattern,
+    alphabet: Base64,
+    mut open: F,
+) -> Result<usize>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&JsonPointer) -> io::Result<W>,
+{
+    let reader = JsonStreamReader::new().with_string_fragments(FRAGMENT_THRESHOLD);
+    let mut tokens = TokenReader::with_reader(source, reader);
+    let mut tracker = PathTracker::new();
+    let mut decoder = None;
+    let mut count = 0;
+    while let Some(token) = tokens.next_token()? {
+        if let Some(current) = &mut decoder {
+            match token {
+                JsonToken::StrChunk(text) => decode_text(&mut tokens, current, &text, false)?,
+                _ => {
+                    let current = decoder.take().unwrap();
+                    current.finish().map_err(|err| tokens.error(err.code))?;
+                    count += 1;
+                }
+            }
+            continue;
+        }
+        if !tracker.push(&token) || !pattern.matches(tracker.segments()) {
+            continue;
+        }
+        let out =
+            open(&tracker.pointer()).map_err(|err| tokens.error(ErrorCode::Io(err.kind())))?;
+        let mut current = Base64Decoder::new(out, alphabet);
+        match token {
+            JsonToken::StrBegin => decoder = Some(current),
+            JsonToken::Val(JsonValue::String(text)) => {
+                decode_text(&mut tokens, &mut current, &text, true)?;
+                current.finish().map_err(|err| tokens.error(err.code))?;
+                count += 1;
+            }
+            _ => return Err(tokens.error(ErrorCode::ExpectedString)),
+        }
+    }
+    Ok(count)
+}
+
+// Decodes the text of the last token read, a whole string if `whole` is
+// set and a fragment otherwise.
+fn decode_text<R, W>(
+    tokens: &mut TokenReader<R>,
+    decoder: &mut Base64Decoder<W>,
+    text: &str,
+    whole: bool,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let start = decoder.position();
+    let Err(err) = decoder.push(text.as_bytes()) else {
+        return Ok(());
+    };
+    // strings and their last fragment are located at the closing quote,
+    // other fragments at their last byte, escapes are not accounted for
+    let closed = whole || matches!(tokens.peek_token(), Ok(Some(JsonToken::StrEnd)));
+    let end = tokens.token_offset() + usize::from(!closed);
+    Err(Error {
+        code: err.code,
+        column: end - text.len() + (err.column - start),
+    })
+}
+
+// Lets every value be decoded into the same writer.
+struct Shared<'a, W>(&'a RefCell<W>);
+
+impl<W: Write> Write for Shared<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod base64_tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn encode(data: &[u8], alphabet: Base64) -> String {
+        let chars: Vec<u8> = (0..=255u8)
+            .filter(|&ch| alphabet.table()[ch as usize] != INVALID)
+            .collect();
+        let mut chars = chars;
+        chars.sort_by_key(|&ch| alphabet.table()[ch as usize]);
+        let mut out = String::new();
+        for group in data.chunks(3) {
+            let mut bits = [0; 3];
+            bits[..group.len()].copy_from_slice(group);
+            let bits = u32::from_be_bytes([0, bits[0], bits[1], bits[2]]);
+            for i in 0..=group.len() {
+                out.push(chars[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            }
+            if alphabet.padded() {
+                out.push_str(&"=="[group.len() - 1..]);
+            }
+        }
+        out
+    }
+
+    fn decode(text: &str, alphabet: Base64, piece: usize) -> Result<Vec<u8>> {
+        let mut decoder = Base64Decoder::new(vec![], alphabet);
+        for piece in text.as_bytes().chunks(piece) {
+            decoder.push(piece)?;
+        }
+        decoder.finish()
+    }
+
+    // Hands out the source a few bytes at a time.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.1.min(buf.len()).min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn should_decode_every_alphabet() {
+        let alphabets = [
+            Base64::Standard,
+            Base64::StandardNoPad,
+            Base64::UrlSafe,
+            Base64::UrlSafeNoPad,
+        ];
+        for alphabet in alphabets {
+            for len in [0, 1, 2, 3, 4, 5, 100, 20_000] {
+                let data = bytes(len);
+                let text = encode(&data, alphabet);
+                for piece in [1, 2, 3, 5, 4096] {
+                    assert_eq!(decode(&text, alphabet, piece), Ok(data.clone()));
+                }
+            }
+        }
+        assert_eq!(decode("+/8=", Base64::Standard, 1), Ok(vec![0xfb, 0xff]));
+        assert_eq!(decode("-_8", Base64::UrlSafeNoPad, 1), Ok(vec![0xfb, 0xff]));
+    }
+
+    #[test]
+    fn should_fail_on_invalid_text() {
+        let error = |column| {
+            Err(Error {
+                code: ErrorCode::InvalidBase64,
+                column,
+            })
+        };
+        assert_eq!(decode("QUJD*EVG", Base64::Standard, 3), error(4));
+        assert_eq!(decode("QUJD-_", Base64::Standard, 3), error(4));
+        assert_eq!(decode("QUI=", Base64::StandardNoPad, 3), error(3));
+        assert_eq!(decode("Q===", Base64::Standard, 1), error(1));
+        assert_eq!(decode("QU=A", Base64::Standard, 1), error(3));
+        assert_eq!(decode("QUI=QUJD", Base64::Standard, 2), error(4));
+        assert_eq!(decode("QUJDR", Base64::UrlSafeNoPad, 2), error(5));
+        assert_eq!(decode("QUI", Base64::Standard, 2), error(3));
+        assert_eq!(decode("QU=", Base64::Standard, 2), error(3));
+    }
+
+    #[test]
+    fn should_decode_values_at_path() {
+        let data = [bytes(5000), bytes(10), bytes(3000)];
+        let doc = format!(
+            r#"{{"attachments": [{{"name": "a", "data": "{}"}}, {{"data": "{}"}}],
+                "data": "QUJD", "more": [{{"data": "{}"}}], "attachments2": []}}"#,
+            encode(&data[0], Base64::Standard),
+            encode(&data[1], Base64::Standard),
+            encode(&data[2], Base64::Standard),
+        );
+        let pattern = PathPattern::parse("/attachments/*/data").unwrap();
+        let expected = [&data[0][..], &data[1]].concat();
+        for piece in [1, 7, 1000, doc.len()] {
+            let source = Trickle(doc.as_bytes(), piece);
+            let out = decode_base64(source, &pattern, Base64::Standard, vec![]);
+            assert_eq!(out, Ok(expected.clone()));
+        }
+
+        let pattern = PathPattern::parse("/*/*/data").unwrap();
+        let mut decoded = vec![];
+        let source = Trickle(doc.as_bytes(), 64);
+        let count = decode_base64_each(source, &pattern, Base64::Standard, |path| {
+            decoded.push((path.to_string(), Rc::default()));
+            Ok(Sink(Rc::clone(&decoded.last().unwrap().1)))
+        });
+        assert_eq!(count, Ok(3));
+        let decoded: Vec<_> = decoded
+            .into_iter()
+            .map(|(path, out)| (path, out.take()))
+            .collect();
+        assert_eq!(
+            decoded,
+            [
+                ("/attachments/0/data".to_string(), data[0].clone()),
+                ("/attachments/1/data".to_string(), data[1].clone()),
+                ("/more/0/data".to_string(), data[2].clone()),
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_locate_errors_in_document() {
+        let pattern = PathPattern::parse("/data").unwrap();
+        let long = encode(&bytes(3000), Base64::Standard);
+        let docs = [
+            (r#"{"data": "QUJD*EVG"}"#.to_string(), 14),
+            (r#"{"data": "QUI"}"#.to_string(), 13),
+            (format!(r#"{{"data": "{}*{}"}}"#, &long[..2000], long), 2010),
+            (
+                format!(r#"{{"data": "{}"}}"#, &long[1..]),
+                10 + long.len() - 1,
+            ),
+        ];
+        for (doc, column) in docs {
+            for piece in [1, 5, 64, 1000, doc.len()] {
+                let source = Trickle(doc.as_bytes(), piece);
+                let res = decode_base64(source, &pattern, Base64::Standard, io::sink());
+                assert_eq!(
+                    res.map(|_| ()),
+                    Err(Error {
+                        code: ErrorCode::InvalidBase64,
+                        column
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_expect_string_at_path() {
+        let pattern = PathPattern::parse("/data").unwrap();
+        let doc = br#"{"data": [1]}"#;
+        let res = decode_base64(&doc[..], &pattern, Base64::Standard, io::sink());
+        assert_eq!(res.unwrap_err().code, ErrorCode::ExpectedString);
+    }
+}
@@ -0,0 +1,379 @@
+//! Snapshots of a reader, for continuing a long parse after a restart.
+//!
+//! A `Checkpoint` holds everything `JsonStreamReader` carries from one
+//! buffer to the next: the open containers, the state in between tokens and
+//! the bytes of a token cut short, along with the absolute offset in the
+//! input where reading continues. `to_bytes` turns it into a versioned blob
+//! to be stored with the progress of a job. Options such as known keys or
+//! string fragments are not part of it and are set again on the resumed
+//! reader, spans are not kept.
+use crate::constants::NUM_CHAR;
+use crate::error::{Error, ErrorCode, Result};
+use crate::token::{Lexeme, Nesting, RawScan, State, Token};
+
+const MAGIC: &[u8; 4] = b"JSRC";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub(crate) offset: usize,
+    pub(crate) stack: Nesting,
+    pub(crate) state: State,
+    pub(crate) lexeme: Lexeme,
+    pub(crate) scratch: Vec<u8>,
+    pub(crate) capture: bool,
+}
+
+impl Checkpoint {
+    /// Absolute position in the input where reading continues.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Number of containers open at the checkpoint.
+    pub fn depth(&self) -> usize {
+        self.stack.depth()
+    }
+
+    /// Whether the checkpoint falls in between tokens.
+    pub fn is_between_tokens(&self) -> bool {
+        self.lexeme == Lexeme::None
+    }
+
+    /// The checkpoint as a blob, starting with a magic number and a format
+    /// version. Integers are little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        put_u64(&mut out, self.offset);
+        out.push(self.state as u8);
+        out.push(u8::from(self.capture));
+        put_u64(&mut out, self.stack.depth());
+        for word in self.stack.words() {
+            out.extend(word.to_le_bytes());
+        }
+        match self.lexeme {
+            Lexeme::None => out.push(0),
+            Lexeme::Key { escaped } => out.extend([1, u8::from(escaped)]),
+            Lexeme::Str { escaped } => out.extend([2, u8::from(escaped)]),
+            Lexeme::Fragments { escaped } => out.extend([3, u8::from(escaped)]),
+            Lexeme::Number { dot } => out.extend([4, u8::from(dot)]),
+            Lexeme::Literal { word, pos } => out.extend([5, word[0], pos as u8]),
+            Lexeme::Raw(scan) => {
+                out.push(6);
+                put_u64(&mut out, scan.depth);
+                out.extend([u8::from(scan.string), u8::from(scan.escaped)]);
+            }
+        }
+        put_u64(&mut out, self.scratch.len());
+        out.extend(&self.scratch);
+        out
+    }
+
+    /// Reads a blob made by `to_bytes`. Fails with `InvalidCheckpoint`
+    /// located in the blob if it is damaged or of another version.
+    pub fn from_bytes(blob: &[u8]) -> Result<Checkpoint> {
+        let mut bytes = Bytes {
+            blob,
+            at: 0,
+            code: ErrorCode::InvalidCheckpoint,
+        };
+        if bytes.take(MAGIC.len())? != MAGIC || bytes.u8()? != VERSION {
+            return Err(bytes.error());
+        }
+        let offset = bytes.usize()?;
+        let state = state(bytes.u8()?).ok_or_else(|| bytes.error())?;
+        let capture = bytes.bool()?;
+        let depth = bytes.usize()?;
+        let stack = bytes.nesting(depth)?;
+        let lexeme = match bytes.u8()? {
+            0 => Lexeme::None,
+            1 => Lexeme::Key {
+                escaped: bytes.bool()?,
+            },
+            2 => Lexeme::Str {
+                escaped: bytes.bool()?,
+            },
+            3 => Lexeme::Fragments {
+                escaped: bytes.bool()?,
+            },
+            4 => Lexeme::Number { dot: bytes.bool()? },
+            5 => {
+                let word: &'static [u8] = match bytes.u8()? {
+                    b'n' => b"null",
+                    b't' => b"true",
+                    b'f' => b"false",
+                    _ => return Err(bytes.error()),
+                };
+                // the first letter is always read along with the action
+                let pos = usize::from(bytes.u8()?);
+                if pos == 0 || pos >= word.len() {
+                    return Err(bytes.error());
+                }
+                Lexeme::Literal { word, pos }
+            }
+            6 => Lexeme::Raw(RawScan {
+                depth: bytes.usize()?,
+                string: bytes.bool()?,
+                escaped: bytes.bool()?,
+            }),
+            _ => return Err(bytes.error()),
+        };
+        let len = bytes.usize()?;
+        let scratch = bytes.take(len)?.to_vec();
+        if bytes.at < blob.len() {
+            return Err(bytes.error());
+        }
+        let checkpoint = Checkpoint {
+            offset,
+            stack,
+            state,
+            lexeme,
+            scratch,
+            capture,
+        };
+        if !checkpoint.is_consistent() {
+            return Err(bytes.error());
+        }
+        Ok(checkpoint)
+    }
+
+    // Whether the fields agree with each other as they do in a reader, which
+    // relies on it.
+    fn is_consistent(&self) -> bool {
+        let top = self.stack.last();
+        let state = match (self.state, top) {
+            (State::Start | State::End, None) => true,
+            (State::ObjFirst | State::ObjNext | State::AfterKey | State::Value, top) => {
+                top == Some(Token::Obj)
+            }
+            (State::ArrFirst | State::ArrNext, top) => top == Some(Token::Arr),
+            (State::AfterValue, top) => top.is_some(),
+            _ => false,
+        };
+        let scratch = &self.scratch;
+        // the state is the one after the token being read
+        let lexeme = match self.lexeme {
+            Lexeme::None => scratch.is_empty(),
+            Lexeme::Key { escaped } => {
+                self.state == State::AfterKey && (!escaped || scratch.last() == Some(&b'\\'))
+            }
+            Lexeme::Str { escaped } => {
+                self.state == State::AfterValue && (!escaped || scratch.last() == Some(&b'\\'))
+            }
+            Lexeme::Fragments { .. } => self.state == State::AfterValue,
+            Lexeme::Number { dot } => {
+                self.state == State::AfterValue
+                    && !scratch.is_empty()
+                    && scratch.iter().all(|&ch| NUM_CHAR[ch as usize])
+                    && scratch.iter().filter(|&&ch| ch == b'.').count() == usize::from(dot)
+            }
+            Lexeme::Literal { .. } => self.state == State::AfterValue && scratch.is_empty(),
+            Lexeme::Raw(scan) => {
+                let done = match top {
+                    Some(_) => State::AfterValue,
+                    None => State::End,
+                };
+                let scan = match scratch.first() {
+                    Some(b'{' | b'[') => scan.depth > 0,
+                    Some(b'"') => scan.depth == 0 && scan.string,
+                    Some(_) => scan.depth == 0 && !scan.string,
+                    None => false,
+                } && (scan.string || !scan.escaped);
+                self.state == done && scan
+            }
+        };
+        state && lexeme && (!self.capture || self.lexeme == Lexeme::None)
+    }
+}
+
+fn state(tag: u8) -> Option<State> {
+    let state = match tag {
+        0 => State::Start,
+        1 => State::ObjFirst,
+        2 => State::ObjNext,
+        3 => State::AfterKey,
+        4 => State::Value,
+        5 => State::ArrFirst,
+        6 => State::ArrNext,
+        7 => State::AfterValue,
+        8 => State::End,
+        _ => return None,
+    };
+    debug_assert_eq!(state as u8, tag);
+    Some(state)
+}
+
+fn put_u64(out: &mut Vec<u8>, n: usize) {
+    out.extend((n as u64).to_le_bytes());
+}
+
+// Writes `n` in LEB128, seven bits per byte.
+pub(crate) fn put_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+// Reads a blob from the front, failing with `code` where it is damaged.
+pub(crate) struct Bytes<'a> {
+    pub(crate) blob: &'a [u8],
+    pub(crate) at: usize,
+    pub(crate) code: ErrorCode,
+}
+
+impl<'a> Bytes<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.blob.len() - self.at < len {
+            return Err(self.error());
+        }
+        self.at += len;
+        Ok(&self.blob[self.at - len..self.at])
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.error()),
+        }
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize> {
+        let n = self.u64()?;
+        usize::try_from(n).map_err(|_| self.error())
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<usize> {
+        let mut n = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.u8()?;
+            let bits = usize::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                return Err(self.error());
+            }
+            n |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(self.error())
+    }
+
+    // The containers open at a point, see `Checkpoint::to_bytes`.
+    pub(crate) fn nesting(&mut self, depth: usize) -> Result<Nesting> {
+        let words = (0..depth.div_ceil(64))
+            .map(|_| self.u64())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Nesting::from_words(words, depth))
+    }
+
+    pub(crate) fn error(&self) -> Error {
+        Error {
+            code: self.code.clone(),
+            column: self.at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use super::*;
+    use crate::json_stream_reader::JsonStreamReader;
+
+    fn checkpoint_after(doc: &[u8]) -> Checkpoint {
+        let mut reader = JsonStreamReader::new();
+        reader.read(doc).unwrap();
+        reader.checkpoint()
+    }
+
+    #[test]
+    fn should_round_trip_through_bytes() {
+        let docs: [&[u8]; 7] = [
+            b"",
+            b"[\"",
+            br#"{"a": [1, {"b"#,
+            br#"[["x\"#,
+            b"[12.",
+            b"[tr",
+            &[b'['; 200],
+        ];
+        for doc in docs {
+            let checkpoint = checkpoint_after(doc);
+            let blob = checkpoint.to_bytes();
+            assert_eq!(&blob[..5], b"JSRC\x01");
+            assert_eq!(Checkpoint::from_bytes(&blob), Ok(checkpoint));
+        }
+        let checkpoint = checkpoint_after(br#"{"a": "b""#);
+        assert!(checkpoint.is_between_tokens());
+        assert_eq!(checkpoint.offset(), 9);
+        assert_eq!(checkpoint.depth(), 1);
+    }
+
+    #[test]
+    fn should_reject_damaged_blob() {
+        let blob = checkpoint_after(br#"{"a": [tr"#).to_bytes();
+        let error = |column| Error {
+            code: ErrorCode::InvalidCheckpoint,
+            column,
+        };
+        let mut other_version = blob.clone();
+        other_version[4] = 2;
+        assert_eq!(Checkpoint::from_bytes(&other_version), Err(error(5)));
+        assert_eq!(
+            Checkpoint::from_bytes(&blob[..blob.len() - 1]),
+            Err(error(blob.len() - 8))
+        );
+        let mut trailing = blob.clone();
+        trailing.push(0);
+        assert_eq!(Checkpoint::from_bytes(&trailing), Err(error(blob.len())));
+        let mut literal = blob.clone();
+        // the position in `true`, before the length of the empty scratch
+        let pos = blob.len() - 9;
+        literal[pos] = 4;
+        assert_eq!(Checkpoint::from_bytes(&literal), Err(error(pos + 1)));
+    }
+
+    #[test]
+    fn should_reject_contradicting_fields_without_panicking() {
+        let docs: [&[u8]; 5] = [br#"{"a": [1, {"b"#, br#"[["x\"#, b"[12.", b"[tr", b"[\""];
+        let mut blobs = docs.map(|doc| checkpoint_after(doc).to_bytes()).to_vec();
+        let mut raw = JsonStreamReader::new();
+        raw.read(b"[").unwrap();
+        raw.capture_next_value();
+        raw.read(b"{\"a").unwrap();
+        blobs.push(raw.checkpoint().to_bytes());
+        for blob in &blobs {
+            // every byte from the state on
+            for at in 13..blob.len() {
+                for byte in [0, 1, 2, 5, 6, 7, 8, b'"', b'{', b'1', 0xff] {
+                    let mut damaged = blob.clone();
+                    damaged[at] = byte;
+                    let Ok(checkpoint) = Checkpoint::from_bytes(&damaged) else {
+                        continue;
+                    };
+                    for input in [&b"]"[..], b"}", b"\"", b"1 ", b",", b"x\\", b" :"] {
+                        let mut reader = JsonStreamReader::resume(&checkpoint);
+                        reader.read(input).unwrap_or_default();
+                    }
+                }
+            }
+        }
+        // a value captured as is, cut before its first byte was kept
+        let mut blob = checkpoint_after(b"[").to_bytes();
+        blob[13] = 7;
+        let lexeme = blob.len() - 9;
+        blob.splice(lexeme..lexeme + 1, [6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(Checkpoint::from_bytes(&blob).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+//! Rust type definitions out of a JSON Schema.
+//!
+//! `RustCodegen` turns a schema, typically one made by `SchemaInferrer`,
+//! into structs and enums deriving serde's `Serialize` and `Deserialize`.
+//! Objects with properties become structs, whose fields are `Option`s
+//! unless required and not nullable, and renamed where the key is not a
+//! valid identifier. Arrays become `Vec`s, strings with an `enum` become
+//! enums and values of several types untagged enums. Values of unknown shape
+//! are typed `serde_json::Value`.
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use crate::json_node::JsonNode;
+
+const DERIVES: &str = "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]";
+
+const ANY: &str = "serde_json::Value";
+
+const KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+// Names that a definition or a variant must not take: `Self` is a keyword,
+// and the others would shadow the types used by the generated code.
+const RESERVED: [&str; 25] = [
+    "Self",
+    "String",
+    "Option",
+    "Vec",
+    "Box",
+    "Result",
+    "Serialize",
+    "Deserialize",
+    "bool",
+    "char",
+    "str",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "isize",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "usize",
+    "f32",
+    "f64",
+];
+
+/// Generates Rust types, see the module docs.
+#[derive(Debug, Clone)]
+pub struct RustCodegen {
+    root: String,
+}
+
+// The definitions made so far, the root first.
+struct Output {
+    definitions: Vec<String>,
+    names: HashSet<String>,
+    // the name of the root, taken from `names` by the first definition if
+    // that is the root's own
+    root: Option<String>,
+}
+
+impl RustCodegen {
+    /// Names the type of the whole document `root`, made a valid type name.
+    pub fn new(root: &str) -> Self {
+        RustCodegen {
+            root: unique(type_name(root), &mut reserved()),
+        }
+    }
+
+    /// Returns the source of a module defining the types, starting with the
+    /// `use` of serde.
+    pub fn generate(&self, schema: &JsonNode) -> String {
+        let mut names = reserved();
+        names.insert(self.root.clone());
+        let mut out = Output {
+            definitions: vec![],
+            names,
+            root: Some(self.root.clone()),
+        };
+        let (ty, nullable) = out.rust_type(schema, &self.root);
+        // an array or a scalar at the top level is given an alias
+        if ty != self.root {
+            let ty = if nullable {
+                format!("Option<{}>", ty)
+            } else {
+                ty
+            };
+            let alias = format!("pub type {} = {};\n", self.root, ty);
+            out.definitions.insert(0, alias);
+        }
+        let mut source = "use serde::{Deserialize, Serialize};\n".to_string();
+        for definition in out.definitions {
+            source.push('\n');
+            source.push_str(&definition);
+        }
+        source
+    }
+}
+
+impl Output {
+    // The type of values matching `schema`, named after `hint` if it needs a
+    // definition, and whether they can be null.
+    fn rust_type(&mut self, schema: &JsonNode, hint: &str) -> (String, bool) {
+        let all = types(schema);
+        let nullable = all.contains(&"null");
+        let types = all
+            .into_iter()
+            .filter(|&ty| ty != "null")
+            .collect::<Vec<_>>();
+        let ty = match types.as_slice() {
+            [] if schema.get("properties").is_some() => self.define_struct(hint, schema),
+            [] => ANY.to_string(),
+            [ty] => self.single_type(ty, schema, hint),
+            types => self.define_union(hint, types, schema),
+        };
+        (ty, nullable)
+    }
+
+    fn single_type(&mut self, ty: &str, schema: &JsonNode, hint: &str) -> String {
+        match ty {
+            "object" if schema.get("properties").is_some() => self.define_struct(hint, schema),
+            "object" => format!("std::collections::BTreeMap<String, {}>", ANY),
+            "array" => match schema.get("items") {
+                Some(items) => {
+                    let (item, nullable) = self.rust_type(items, &format!("{}Item", hint));
+                    match nullable {
+                        true => format!("Vec<Option<{}>>", item),
+                        false => format!("Vec<{}>", item),
+                    }
+                }
+                None => format!("Vec<{}>", ANY),
+            },
+            "string" => match string_values(schema) {
+                Some(values) => self.define_enum(hint, &values),
+                None => "String".to_string(),
+            },
+            "integer" => integer_type(schema).to_string(),
+            "number" => "f64".to_string(),
+            "boolean" => "bool".to_string(),
+            _ => ANY.to_string(),
+        }
+    }
+
+    fn define_struct(&mut self, hint: &str, schema: &JsonNode) -> String {
+        let name = self.reserve(hint);
+        let slot = self.definitions.len();
+        self.definitions.push(String::new());
+        self.definitions[slot] = self.structure(&name, schema);
+        name
+    }
+
+    fn structure(&mut self, name: &str, schema: &JsonNode) -> String {
+        let required = schema
+            .get("required")
+            .and_then(JsonNode::as_array)
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(JsonNode::as_str)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let properties = schema
+            .get("properties")
+            .and_then(JsonNode::as_object)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let mut fields = HashSet::new();
+        let mut text = format!("{}\npub struct {} {{\n", DERIVES, name);
+        for (key, property) in properties {
+            let (ty, nullable) = self.rust_type(property, &type_name(key));
+            let optional = nullable || !required.contains(&key.as_str());
+            let field = unique(field_name(key), &mut fields);
+            if field.strip_prefix("r#").unwrap_or(&field) != key {
+                writeln!(text, "    #[serde(rename = {:?})]", key).unwrap();
+            }
+            match optional {
+                true => writeln!(text, "    pub {}: Option<{}>,", field, ty).unwrap(),
+                false => writeln!(text, "    pub {}: {},", field, ty).unwrap(),
+            }
+        }
+        text.push_str("}\n");
+        text
+    }
+
+    fn define_enum(&mut self, hint: &str, values: &[&str]) -> String {
+        let name = self.reserve(hint);
+        let mut variants = reserved();
+        let mut text = format!("{}\npub enum {} {{\n", DERIVES, name);
+        for value in values {
+            let variant = unique(type_name(value), &mut variants);
+            writeln!(text, "    #[serde(rename = {:?})]", value).unwrap();
+            writeln!(text, "    {},", variant).unwrap();
+        }
+        text.push_str("}\n");
+        self.definitions.push(text);
+        name
+    }
+
+    // An untagged enum with a variant per type, tried in order.
+    fn define_union(&mut self, hint: &str, types: &[&str], schema: &JsonNode) -> String {
+        let name = self.reserve(&format!("{}Value", hint));
+        let slot = self.definitions.len();
+        self.definitions.push(String::new());
+        let mut text = format!("{}\n#[serde(untagged)]\npub enum {} {{\n", DERIVES, name);
+        for ty in types {
+            let variant = type_name(ty);
+            let inner = self.single_type(ty, schema, &format!("{}{}", hint, variant));
+            writeln!(text, "    {}({}),", variant, inner).unwrap();
+        }
+        text.push_str("}\n");
+        self.definitions[slot] = text;
+        name
+    }
+
+    fn reserve(&mut self, hint: &str) -> String {
+        match self.root.take() {
+            Some(root) if root == hint => root,
+            _ => unique(hint.to_string(), &mut self.names),
+        }
+    }
+}
+
+// The types named by `type`, in their order.
+fn types(schema: &JsonNode) -> Vec<&str> {
+    match schema.get("type") {
+        Some(JsonNode::String(ty)) => vec![ty.as_str()],
+        Some(JsonNode::Array(types)) => types.iter().filter_map(JsonNode::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+// `i64`, unless the `minimum` or the `maximum` is out of its range.
+fn integer_type(schema: &JsonNode) -> &'static str {
+    let bound = |keyword| match schema.get(keyword) {
+        Some(JsonNode::Number(n)) => n.parse::<i128>().ok(),
+        Some(_) => None,
+        None => Some(0),
+    };
+    let (Some(min), Some(max)) = (bound("minimum"), bound("maximum")) else {
+        return "f64";
+    };
+    if min >= i64::MIN.into() && max <= i64::MAX.into() {
+        "i64"
+    } else if min >= 0 && max <= u64::MAX.into() {
+        "u64"
+    } else {
+        "f64"
+    }
+}
+
+// The `enum` of a string schema, if it only lists strings, nulls aside.
+fn string_values(schema: &JsonNode) -> Option<Vec<&str>> {
+    let values = schema.get("enum")?.as_array()?;
+    let values = values.iter().filter(|value| !value.is_null());
+    let strings = values.map(JsonNode::as_str).collect::<Option<Vec<_>>>()?;
+    (!strings.is_empty()).then_some(strings)
+}
+
+fn reserved() -> HashSet<String> {
+    RESERVED.iter().map(|name| name.to_string()).collect()
+}
+
+// `name`, or `name` followed by the first number making it unique.
+fn unique(name: String, taken: &mut HashSet<String>) -> String {
+    let name = (1..)
+        .map(|n| match n {
+            1 => name.clone(),
+            n => format!("{}{}", name, n),
+        })
+        .find(|name| !taken.contains(name))
+        .unwrap();
+    taken.insert(name.clone());
+    name
+}
+
+// The words of a key: runs of letters and digits, split before an upper
+// case letter following a lower case one.
+fn words(key: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut lower = false;
+    for ch in key.chars() {
+        if !ch.is_alphanumeric() {
+            lower = false;
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if ch.is_uppercase() && lower && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        lower = ch.is_lowercase() || ch.is_ascii_digit();
+        word.push(ch);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn type_name(key: &str) -> String {
+    let mut name = words(key)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|ch| ch.to_uppercase().collect::<String>());
+            first.unwrap_or_default() + &chars.as_str().to_lowercase()
+        })
+        .collect::<String>();
+    if !name.starts_with(|ch: char| ch.is_alphabetic()) {
+        name.insert(0, 'T');
+    }
+    name
+}
+
+fn field_name(key: &str) -> String {
+    let name = words(key)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    match name.as_str() {
+        "" => "field".to_string(),
+        "self" | "super" | "crate" => format!("{}_", name),
+        _ if KEYWORDS.contains(&name.as_str()) => format!("r#{}", name),
+        _ if name.starts_with(|ch: char| ch.is_ascii_digit()) => format!("_{}", name),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod codegen_tests {
+    use super::*;
+    use crate::infer::SchemaInferrer;
+
+    fn generate(root: &str, docs: &[&str]) -> String {
+        let mut inferrer = SchemaInferrer::new();
+        for doc in docs {
+            inferrer.read(doc.as_bytes()).unwrap();
+        }
+        RustCodegen::new(root).generate(&inferrer.schema())
+    }
+
+    #[test]
+    fn should_generate_structs_from_inferred_schema() {
+        let docs = [
+            r#"{"id": 1, "user-name": "a", "type": "admin", "tags": ["x"], "geo": {"lat": 1.5}, "self": true}"#,
+            r#"{"id": 2, "user-name": null, "type": "user", "tags": [], "extra": [1, "one"]}"#,
+            r#"{"id": 3, "user-name": "c", "type": "user", "tags": ["y", "z"], "geo": null}"#,
+        ];
+        let expected = r#"use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Feed {
+    pub id: i64,
+    #[serde(rename = "user-name")]
+    pub user_name: Option<String>,
+    pub r#type: Type,
+    pub tags: Vec<String>,
+    pub geo: Option<Geo>,
+    #[serde(rename = "self")]
+    pub self_: Option<bool>,
+    pub extra: Option<Vec<ExtraItemValue>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Type {
+    #[serde(rename = "admin")]
+    Admin,
+    #[serde(rename = "user")]
+    User,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Geo {
+    pub lat: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExtraItemValue {
+    String(String),
+    Integer(i64),
+}
+"#;
+        assert_eq!(generate("feed", &docs), expected);
+    }
+
+    #[test]
+    fn should_alias_top_level_arrays() {
+        let source = generate("events", &[r#"[{"At": 1}, {"At": 2, "kind": {}}]"#]);
+        let expected = r#"use serde::{Deserialize, Serialize};
+
+pub type Events = Vec<EventsItem>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventsItem {
+    #[serde(rename = "At")]
+    pub at: i64,
+    pub kind: Option<std::collections::BTreeMap<String, serde_json::Value>>,
+}
+"#;
+        assert_eq!(source, expected);
+        // a member named like the root is not given the alias's name
+        let source = generate("events", &[r#"[{"events": {"a": 1}}]"#]);
+        let expected = r#"use serde::{Deserialize, Serialize};
+
+pub type Events = Vec<EventsItem>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventsItem {
+    pub events: Events2,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Events2 {
+    pub a: i64,
+}
+"#;
+        assert_eq!(source, expected);
+        assert_eq!(field_name("2fa"), "_2fa");
+        assert_eq!(type_name("HTTPStatus code"), "HttpstatusCode");
+    }
+
+    #[test]
+    fn should_not_shadow_std_types() {
+        let docs = [
+            r#"{"kind": "self", "string": {"a": 1}, "option": {"b": true}, "name": "x"}"#,
+            r#"{"kind": "other", "string": {"a": 2}, "option": {"b": false}, "name": "y"}"#,
+            r#"{"kind": "self", "string": {"a": 3}, "option": {"b": true}, "name": "z"}"#,
+        ];
+        let expected = r#"use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct String2 {
+    pub kind: Kind,
+    pub string: String3,
+    pub option: Option2,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Kind {
+    #[serde(rename = "self")]
+    Self2,
+    #[serde(rename = "other")]
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct String3 {
+    pub a: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Option2 {
+    pub b: bool,
+}
+"#;
+        assert_eq!(generate("string", &docs), expected);
+    }
+
+    #[test]
+    fn should_pick_number_types_fitting_the_values() {
+        let docs = [
+            r#"{"price": 1.0, "id": 10000000000000000000, "n": -1, "big": -1e3, "huge": 1}"#,
+            r#"{"price": 2, "id": 1, "n": 5, "big": 2, "huge": -10000000000000000000}"#,
+        ];
+        let source = generate("row", &docs);
+        assert!(source.contains("    pub price: f64,\n"));
+        assert!(source.contains("    pub id: u64,\n"));
+        assert!(source.contains("    pub n: i64,\n"));
+        assert!(source.contains("    pub big: f64,\n"));
+        assert!(source.contains("    pub huge: f64,\n"));
+    }
+}
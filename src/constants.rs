@@ -1,10 +1,130 @@
-use std::collections::HashSet;
-
-lazy_static! {
-    pub static ref EMPTY_CHAR_SET: HashSet<u8> =
-        vec![b' ', b'\n', b'\t', b'\r'].into_iter().collect();
-    pub static ref DIGIT_CHAR_SET: HashSet<u8> =
-        vec![b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'+', b'-', b'e']
-            .into_iter()
-            .collect();
+//! Byte classes and the transition table of the reader's state machine.
+use crate::token::State;
+
+/// The maximum length of an object key, escapes included.
+pub(crate) const KEY_MAX_LEN: usize = 100;
+
+/// The scratch buffer gives back memory above this capacity once the long
+/// token that needed it has been read.
+pub(crate) const SCRATCH_CAPACITY: usize = 4096;
+
+/// `read` reserves room for a token every 8 bytes of input, up to this many
+/// tokens, so that a typical chunk needs no reallocation.
+pub(crate) const RESERVED_TOKENS: usize = 2048;
+
+// Byte classes, every byte of the input falls into exactly one of them.
+pub(crate) const C_OTHER: u8 = 0;
+pub(crate) const C_WS: u8 = 1;
+pub(crate) const C_OBJ_BEG: u8 = 2;
+pub(crate) const C_OBJ_END: u8 = 3;
+pub(crate) const C_ARR_BEG: u8 = 4;
+pub(crate) const C_ARR_END: u8 = 5;
+pub(crate) const C_COLON: u8 = 6;
+pub(crate) const C_COMMA: u8 = 7;
+pub(crate) const C_QUOTE: u8 = 8;
+pub(crate) const C_NUM: u8 = 9;
+pub(crate) const C_LIT: u8 = 10;
+const CLASSES: usize = 11;
+
+pub(crate) static CLASS: [u8; 256] = classes();
+
+const fn classes() -> [u8; 256] {
+    let mut table = [C_OTHER; 256];
+    table[b' ' as usize] = C_WS;
+    table[b'\n' as usize] = C_WS;
+    table[b'\t' as usize] = C_WS;
+    table[b'\r' as usize] = C_WS;
+    table[b'{' as usize] = C_OBJ_BEG;
+    table[b'}' as usize] = C_OBJ_END;
+    table[b'[' as usize] = C_ARR_BEG;
+    table[b']' as usize] = C_ARR_END;
+    table[b':' as usize] = C_COLON;
+    table[b',' as usize] = C_COMMA;
+    table[b'"' as usize] = C_QUOTE;
+    table[b'-' as usize] = C_NUM;
+    let mut ch = b'0';
+    while ch <= b'9' {
+        table[ch as usize] = C_NUM;
+        ch += 1;
+    }
+    table[b'n' as usize] = C_LIT;
+    table[b't' as usize] = C_LIT;
+    table[b'f' as usize] = C_LIT;
+    table
+}
+
+/// Bytes that may continue a number.
+pub(crate) static NUM_CHAR: [bool; 256] = num_chars();
+
+const fn num_chars() -> [bool; 256] {
+    let mut table = [false; 256];
+    let mut ch = b'0';
+    while ch <= b'9' {
+        table[ch as usize] = true;
+        ch += 1;
+    }
+    table[b'-' as usize] = true;
+    table[b'+' as usize] = true;
+    table[b'e' as usize] = true;
+    table[b'E' as usize] = true;
+    table[b'.' as usize] = true;
+    table
+}
+
+/// What the reader does with a byte in between tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    Skip,
+    ObjBeg,
+    ObjEnd,
+    ArrBeg,
+    ArrEnd,
+    Key,
+    Str,
+    Num,
+    Lit,
+    Colon,
+    Comma,
+    Fail,
+}
+
+pub(crate) static ACTIONS: [[Action; CLASSES]; State::STRUCTURAL] = actions();
+
+const fn actions() -> [[Action; CLASSES]; State::STRUCTURAL] {
+    let mut table = [[Action::Fail; CLASSES]; State::STRUCTURAL];
+    let mut state = 0;
+    while state < State::STRUCTURAL {
+        table[state][C_WS as usize] = Action::Skip;
+        state += 1;
+    }
+
+    let start = State::Start as usize;
+    table[start][C_OBJ_BEG as usize] = Action::ObjBeg;
+    table[start][C_ARR_BEG as usize] = Action::ArrBeg;
+
+    let obj_first = State::ObjFirst as usize;
+    table[obj_first][C_QUOTE as usize] = Action::Key;
+    table[obj_first][C_OBJ_END as usize] = Action::ObjEnd;
+
+    table[State::ObjNext as usize][C_QUOTE as usize] = Action::Key;
+    table[State::AfterKey as usize][C_COLON as usize] = Action::Colon;
+
+    let values = [State::Value, State::ArrFirst, State::ArrNext];
+    let mut i = 0;
+    while i < values.len() {
+        let state = values[i] as usize;
+        table[state][C_OBJ_BEG as usize] = Action::ObjBeg;
+        table[state][C_ARR_BEG as usize] = Action::ArrBeg;
+        table[state][C_QUOTE as usize] = Action::Str;
+        table[state][C_NUM as usize] = Action::Num;
+        table[state][C_LIT as usize] = Action::Lit;
+        i += 1;
+    }
+    table[State::ArrFirst as usize][C_ARR_END as usize] = Action::ArrEnd;
+
+    let after_value = State::AfterValue as usize;
+    table[after_value][C_COMMA as usize] = Action::Comma;
+    table[after_value][C_OBJ_END as usize] = Action::ObjEnd;
+    table[after_value][C_ARR_END as usize] = Action::ArrEnd;
+    table
 }
@@ -0,0 +1,451 @@
+//! Lossless tokens, for tools that rewrite a document and leave the rest of
+//! it untouched.
+//!
+//! Besides keys, values and brackets, `CstToken`s hold the whitespace and
+//! the punctuation in between, and keys and values keep their text as
+//! written, quotes and escapes included. Writing out the text of every
+//! token gives back the input byte for byte. Documents are checked by
+//! `JsonStreamReader`, whose spans locate the lexemes in the input.
+//!
+//! JSONC documents may also have `//` and `/* */` comments wherever
+//! whitespace is allowed. The reader is given a copy of the input with the
+//! comments blanked out, and they come back as `Comment` tokens.
+use std::borrow::Cow;
+
+use memchr::memchr;
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::{JsonToken, Span};
+use crate::json_value::JsonValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CstToken<'a> {
+    Whitespace(&'a [u8]),
+    /// A comment with its delimiters, the newline ending a `//` comment
+    /// aside.
+    Comment(&'a [u8]),
+    ObjBeg,
+    ObjEnd,
+    ArrBeg,
+    ArrEnd,
+    Colon,
+    Comma,
+    /// A key with its quotes, escapes are not decoded.
+    Key(&'a [u8]),
+    /// A string value with its quotes, escapes are not decoded.
+    String(&'a [u8]),
+    Number(&'a [u8]),
+    /// `null`, `true` or `false`.
+    Literal(&'a [u8]),
+}
+
+impl CstToken<'_> {
+    /// The text of the token in the document.
+    pub fn text(&self) -> &[u8] {
+        match self {
+            CstToken::ObjBeg => b"{",
+            CstToken::ObjEnd => b"}",
+            CstToken::ArrBeg => b"[",
+            CstToken::ArrEnd => b"]",
+            CstToken::Colon => b":",
+            CstToken::Comma => b",",
+            CstToken::Whitespace(text)
+            | CstToken::Comment(text)
+            | CstToken::Key(text)
+            | CstToken::String(text)
+            | CstToken::Number(text)
+            | CstToken::Literal(text) => text,
+        }
+    }
+}
+
+/// Splits a whole document into lossless tokens.
+pub fn parse_cst(doc: &[u8]) -> Result<Vec<CstToken<'_>>> {
+    split_document(doc, doc)
+}
+
+/// Splits a whole JSONC document into lossless tokens.
+pub fn parse_cst_with_comments(doc: &[u8]) -> Result<Vec<CstToken<'_>>> {
+    let mut masked = vec![];
+    let mut mask = CommentMask::default();
+    mask.apply(doc, 0, &mut masked)?;
+    mask.finish(doc.len())?;
+    split_document(doc, &masked)
+}
+
+// Splits `doc`, read by the reader as `masked`.
+fn split_document<'a>(doc: &'a [u8], masked: &[u8]) -> Result<Vec<CstToken<'a>>> {
+    let mut reader = JsonStreamReader::new().with_spans();
+    let located = reader.read_spanned(masked)?;
+    if reader.depth() > 0 {
+        return Err(Error {
+            code: ErrorCode::UnexpectedEof,
+            column: doc.len(),
+        });
+    }
+    let mut tokens = vec![];
+    let mut at = 0;
+    for (token, span) in &located {
+        split_gap(&doc[at..span.start_byte], |token| tokens.push(token));
+        tokens.push(lexeme(token, &doc[span.start_byte..span.end_byte]));
+        at = span.end_byte;
+    }
+    split_gap(&doc[at..], |token| tokens.push(token));
+    Ok(tokens)
+}
+
+/// Splits a document read in chunks into lossless tokens.
+///
+/// A token split between chunks is emitted whole once complete. The
+/// whitespace and punctuation after the last token of a chunk are held
+/// until the next token or `finish`.
+#[derive(Debug)]
+pub struct CstReader {
+    reader: JsonStreamReader,
+    // bytes of the previous chunks not emitted yet
+    pending: Vec<u8>,
+    // position of the first byte of `pending`
+    pending_start: usize,
+    // set in JSONC mode
+    comments: Option<CommentMask>,
+    // the chunk with its comments blanked out
+    masked: Vec<u8>,
+}
+
+impl CstReader {
+    pub fn new() -> Self {
+        CstReader {
+            reader: JsonStreamReader::new().with_spans(),
+            pending: vec![],
+            pending_start: 0,
+            comments: None,
+            masked: vec![],
+        }
+    }
+
+    /// Reads JSONC, with comments.
+    pub fn with_comments(mut self) -> Self {
+        self.comments = Some(CommentMask::default());
+        self
+    }
+
+    pub fn read<F>(&mut self, buf: &[u8], mut emit: F) -> Result<()>
+    where
+        F: FnMut(CstToken<'_>),
+    {
+        let base = self.pending_start + self.pending.len();
+        let located = match &mut self.comments {
+            Some(mask) => {
+                mask.apply(buf, base, &mut self.masked)?;
+                self.reader.read_spanned(&self.masked)?
+            }
+            None => self.reader.read_spanned(buf)?,
+        };
+        let mut at = self.pending_start;
+        for (token, span) in &located {
+            let Span {
+                start_byte,
+                end_byte,
+                ..
+            } = *span;
+            split_gap(&self.slice(buf, at, start_byte), &mut emit);
+            emit(lexeme(token, &self.slice(buf, start_byte, end_byte)));
+            at = end_byte;
+        }
+        if at >= base {
+            self.pending.clear();
+            self.pending.extend_from_slice(&buf[at - base..]);
+        } else {
+            self.pending.drain(..at - self.pending_start);
+            self.pending.extend_from_slice(buf);
+        }
+        self.pending_start = at;
+        Ok(())
+    }
+
+    /// Emits what follows the last token, the document has to be complete.
+    pub fn finish<F>(&mut self, emit: F) -> Result<()>
+    where
+        F: FnMut(CstToken<'_>),
+    {
+        let end = self.pending_start + self.pending.len();
+        if let Some(mask) = &self.comments {
+            mask.finish(end)?;
+        }
+        if self.reader.depth() > 0 {
+            return Err(Error {
+                code: ErrorCode::UnexpectedEof,
+                column: end,
+            });
+        }
+        split_gap(&self.pending, emit);
+        self.pending_start += self.pending.len();
+        self.pending.clear();
+        Ok(())
+    }
+
+    // The bytes from `start` to `end`, in `pending`, `buf` or both.
+    fn slice<'a>(&'a self, buf: &'a [u8], start: usize, end: usize) -> Cow<'a, [u8]> {
+        let base = self.pending_start + self.pending.len();
+        if start >= base {
+            Cow::Borrowed(&buf[start - base..end - base])
+        } else if end <= base {
+            Cow::Borrowed(&self.pending[start - self.pending_start..end - self.pending_start])
+        } else {
+            let mut joined = self.pending[start - self.pending_start..].to_vec();
+            joined.extend_from_slice(&buf[..end - base]);
+            Cow::Owned(joined)
+        }
+    }
+}
+
+impl Default for CstReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lexeme<'a>(token: &JsonToken, text: &'a [u8]) -> CstToken<'a> {
+    match token {
+        JsonToken::ObjBeg => CstToken::ObjBeg,
+        JsonToken::ObjEnd => CstToken::ObjEnd,
+        JsonToken::ArrBeg => CstToken::ArrBeg,
+        JsonToken::ArrEnd => CstToken::ArrEnd,
+        JsonToken::Key(_) => CstToken::Key(text),
+        JsonToken::Val(JsonValue::String(_)) => CstToken::String(text),
+        JsonToken::Val(JsonValue::Number(_)) => CstToken::Number(text),
+        // the reader is not set up for the other tokens
+        _ => CstToken::Literal(text),
+    }
+}
+
+// Where comments are, for blanking them out as the input comes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum CommentMask {
+    #[default]
+    Code,
+    Str,
+    // after a backslash in a string
+    Escape,
+    // after a `/` outside of strings
+    Slash,
+    Line,
+    Block,
+    // after a `*` in a block comment
+    BlockStar,
+}
+
+impl CommentMask {
+    // Copies `buf` to `masked`, comments replaced by spaces. Newlines are
+    // kept for the lines of spans to stay right.
+    fn apply(&mut self, buf: &[u8], base: usize, masked: &mut Vec<u8>) -> Result<()> {
+        masked.clear();
+        masked.reserve(buf.len());
+        for (i, &ch) in buf.iter().enumerate() {
+            let (state, out) = match (*self, ch) {
+                (CommentMask::Code, b'"') => (CommentMask::Str, ch),
+                (CommentMask::Code, b'/') => (CommentMask::Slash, b' '),
+                (CommentMask::Code, _) => (CommentMask::Code, ch),
+                (CommentMask::Str, b'\\') => (CommentMask::Escape, ch),
+                (CommentMask::Str, b'"') => (CommentMask::Code, ch),
+                (CommentMask::Str, _) | (CommentMask::Escape, _) => (CommentMask::Str, ch),
+                (CommentMask::Slash, b'/') => (CommentMask::Line, b' '),
+                (CommentMask::Slash, b'*') => (CommentMask::Block, b' '),
+                (CommentMask::Slash, _) => {
+                    return Err(Error {
+                        code: ErrorCode::InvalidComment,
+                        column: (base + i).saturating_sub(1),
+                    })
+                }
+                (CommentMask::Line, b'\n') => (CommentMask::Code, ch),
+                (CommentMask::Line, _) => (CommentMask::Line, b' '),
+                (CommentMask::Block | CommentMask::BlockStar, b'*') => {
+                    (CommentMask::BlockStar, b' ')
+                }
+                (CommentMask::BlockStar, b'/') => (CommentMask::Code, b' '),
+                (CommentMask::Block | CommentMask::BlockStar, b'\n') => (CommentMask::Block, ch),
+                (CommentMask::Block | CommentMask::BlockStar, _) => (CommentMask::Block, b' '),
+            };
+            *self = state;
+            masked.push(out);
+        }
+        Ok(())
+    }
+
+    // Checks that no comment is left open at the end of the input.
+    fn finish(&self, end: usize) -> Result<()> {
+        let code = match self {
+            CommentMask::Slash => ErrorCode::InvalidComment,
+            CommentMask::Block | CommentMask::BlockStar => ErrorCode::UnexpectedEof,
+            _ => return Ok(()),
+        };
+        Err(Error { code, column: end })
+    }
+}
+
+// Emits the whitespace, comments, colons and commas in between two lexemes.
+fn split_gap<'a, F>(gap: &'a [u8], mut emit: F)
+where
+    F: FnMut(CstToken<'a>),
+{
+    let mut rest = gap;
+    while let Some(&ch) = rest.first() {
+        let len = match ch {
+            b':' => {
+                emit(CstToken::Colon);
+                1
+            }
+            b',' => {
+                emit(CstToken::Comma);
+                1
+            }
+            b'/' => {
+                let len = if rest.get(1) == Some(&b'*') {
+                    rest[2..]
+                        .windows(2)
+                        .position(|pair| pair == b"*/")
+                        .map_or(rest.len(), |i| i + 4)
+                } else {
+                    memchr(b'\n', rest).unwrap_or(rest.len())
+                };
+                emit(CstToken::Comment(&rest[..len]));
+                len
+            }
+            _ => {
+                let len = rest
+                    .iter()
+                    .position(|&ch| ch == b':' || ch == b',' || ch == b'/')
+                    .unwrap_or(rest.len());
+                emit(CstToken::Whitespace(&rest[..len]));
+                len
+            }
+        };
+        rest = &rest[len..];
+    }
+}
+
+#[cfg(test)]
+mod cst_tests {
+    use super::*;
+
+    const DOC: &[u8] =
+        b" {\n  \"a\\u0041\" :\t[1.50 , -2e3,true],\r\n  \"b\": {\"c\":null} ,\"d\":\"x\\\"y\"\n}\n";
+
+    fn texts(tokens: &[CstToken<'_>]) -> Vec<Vec<u8>> {
+        tokens.iter().map(|token| token.text().to_vec()).collect()
+    }
+
+    #[test]
+    fn should_split_document_losslessly() {
+        let tokens = parse_cst(DOC).unwrap();
+        assert_eq!(texts(&tokens).concat(), DOC);
+        assert_eq!(
+            tokens[..12],
+            [
+                CstToken::Whitespace(b" "),
+                CstToken::ObjBeg,
+                CstToken::Whitespace(b"\n  "),
+                CstToken::Key(b"\"a\\u0041\""),
+                CstToken::Whitespace(b" "),
+                CstToken::Colon,
+                CstToken::Whitespace(b"\t"),
+                CstToken::ArrBeg,
+                CstToken::Number(b"1.50"),
+                CstToken::Whitespace(b" "),
+                CstToken::Comma,
+                CstToken::Whitespace(b" "),
+            ]
+        );
+        assert!(tokens.contains(&CstToken::String(b"\"x\\\"y\"")));
+        assert!(tokens.contains(&CstToken::Literal(b"null")));
+    }
+
+    #[test]
+    fn should_emit_same_tokens_whatever_the_chunk_size() {
+        let expected = texts(&parse_cst(DOC).unwrap());
+        for size in 1..=DOC.len() {
+            let mut reader = CstReader::new();
+            let mut tokens = vec![];
+            for chunk in DOC.chunks(size) {
+                reader
+                    .read(chunk, |token| tokens.push(token.text().to_vec()))
+                    .unwrap();
+            }
+            reader
+                .finish(|token| tokens.push(token.text().to_vec()))
+                .unwrap();
+            // whitespace may be cut where chunks end
+            assert_eq!(tokens.concat(), DOC, "chunk size {}", size);
+            let lexemes = |tokens: &[Vec<u8>]| -> Vec<Vec<u8>> {
+                tokens
+                    .iter()
+                    .filter(|text| !text[0].is_ascii_whitespace())
+                    .cloned()
+                    .collect()
+            };
+            assert_eq!(lexemes(&tokens), lexemes(&expected), "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn should_keep_comments_in_jsonc() {
+        let doc = b"// settings\n{\"a\": /* \"x\" */ 1, // one\n \"b//\": \"/*\"}\n/**/";
+        assert_eq!(
+            parse_cst(doc).unwrap_err().code,
+            ErrorCode::ExpectedObjectOrArray
+        );
+        let tokens = parse_cst_with_comments(doc).unwrap();
+        assert_eq!(texts(&tokens).concat(), doc);
+        let comments: Vec<_> = tokens
+            .iter()
+            .filter(|token| matches!(token, CstToken::Comment(_)))
+            .map(|token| String::from_utf8_lossy(token.text()))
+            .collect();
+        assert_eq!(comments, ["// settings", "/* \"x\" */", "// one", "/**/"]);
+        assert!(tokens.contains(&CstToken::Key(b"\"b//\"")));
+        for size in 1..=doc.len() {
+            let mut reader = CstReader::new().with_comments();
+            let mut texts = vec![];
+            for chunk in doc.chunks(size) {
+                reader
+                    .read(chunk, |token| texts.push(token.text().to_vec()))
+                    .unwrap();
+            }
+            reader
+                .finish(|token| texts.push(token.text().to_vec()))
+                .unwrap();
+            assert_eq!(texts.concat(), doc, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn should_fail_on_invalid_comment() {
+        let err = parse_cst_with_comments(b"[1, / 2]").unwrap_err();
+        assert_eq!(
+            err,
+            Error {
+                code: ErrorCode::InvalidComment,
+                column: 4
+            }
+        );
+        let err = parse_cst_with_comments(b"[1] /* open").unwrap_err();
+        assert_eq!(err.code, ErrorCode::UnexpectedEof);
+    }
+
+    #[test]
+    fn should_fail_on_incomplete_document() {
+        let err = parse_cst(b"{\"a\": [1").unwrap_err();
+        assert_eq!(err.code, ErrorCode::UnexpectedEof);
+        let mut reader = CstReader::new();
+        reader.read(b"[1, ", |_| {}).unwrap();
+        let err = reader.finish(|_| {}).unwrap_err();
+        assert_eq!(
+            err,
+            Error {
+                code: ErrorCode::UnexpectedEof,
+                column: 4
+            }
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! Deserialize typed values straight from the token stream.
+//!
+//! Only the tokens of the value being deserialized are held in memory, the
+//! document is never materialized as a whole.
+use std::borrow::BorrowMut;
+use std::fmt::Display;
+use std::io::Read;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+use crate::raw_value;
+use crate::token_reader::TokenReader;
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error {
+            code: ErrorCode::Message(msg.to_string()),
+            column: 0,
+        }
+    }
+}
+
+/// Deserializes an instance of `T` from a JSON document read from `source`.
+pub fn from_reader<R: Read, T: DeserializeOwned>(source: R) -> Result<T> {
+    let mut tokens = TokenReader::new(source).with_raw_values();
+    let value = T::deserialize(&mut Deserializer::new(&mut tokens))?;
+    match tokens.next_token()? {
+        None => Ok(value),
+        Some(_) => Err(tokens.error(ErrorCode::InvalidFormat)),
+    }
+}
+
+/// Deserializes an instance of `T` from a JSON document held in `buf`.
+pub fn from_slice<T: DeserializeOwned>(buf: &[u8]) -> Result<T> {
+    from_reader(buf)
+}
+
+// Deserializes the next element of a container whose members are at
+// nesting level `depth`. If the element is well-formed JSON of the wrong
+// shape, the rest of it is skipped so that the caller can carry on with the
+// next one.
+pub(crate) fn from_element<R, J, T>(tokens: &mut TokenReader<R, J>, depth: usize) -> Result<T>
+where
+    R: Read,
+    J: BorrowMut<JsonStreamReader>,
+    T: DeserializeOwned,
+{
+    let count = tokens.token_count();
+    let res = T::deserialize(&mut Deserializer::new(tokens));
+    if res.is_err() && !tokens.is_failed() {
+        if tokens.token_count() == count {
+            tokens.skip_value()?;
+        } else {
+            tokens.skip_to_depth(depth)?;
+        }
+    }
+    res
+}
+
+/// A serde `Deserializer` pulling tokens from a `TokenReader`.
+///
+/// Each call deserializes the next value of the stream, so one token reader
+/// can feed several values, e.g. the elements of an array. `RawValue`s need
+/// a token reader made `with_raw_values`.
+pub struct Deserializer<'a, R, J = JsonStreamReader> {
+    tokens: &'a mut TokenReader<R, J>,
+}
+
+impl<'a, R: Read, J: BorrowMut<JsonStreamReader>> Deserializer<'a, R, J> {
+    pub fn new(tokens: &'a mut TokenReader<R, J>) -> Self {
+        Deserializer { tokens }
+    }
+
+    fn next(&mut self) -> Result<JsonToken> {
+        match self.tokens.next_token()? {
+            Some(token) => self.tokens.whole(token),
+            None => Err(self.tokens.error(ErrorCode::UnexpectedEof)),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&JsonToken> {
+        if self.tokens.peek_token()?.is_none() {
+            return Err(self.tokens.error(ErrorCode::UnexpectedEof));
+        }
+        Ok(self.tokens.peek_token()?.unwrap())
+    }
+
+    // Errors raised by visitors don't know where they happened.
+    fn locate(&self, mut err: Error) -> Error {
+        if err.column == 0 {
+            err.column = self.tokens.token_offset();
+        }
+        err
+    }
+
+    fn visit_number<'de, V: Visitor<'de>>(&self, number: &str, visitor: V) -> Result<V::Value> {
+        let invalid = || self.tokens.error(ErrorCode::InvalidNumber);
+        if number.contains(['.', 'e', 'E']) {
+            visitor.visit_f64(number.parse().map_err(|_| invalid())?)
+        } else if number.starts_with('-') {
+            match number.parse() {
+                Ok(n) => visitor.visit_i64(n),
+                Err(_) => visitor.visit_f64(number.parse().map_err(|_| invalid())?),
+            }
+        } else {
+            match number.parse() {
+                Ok(n) => visitor.visit_u64(n),
+                Err(_) => visitor.visit_f64(number.parse().map_err(|_| invalid())?),
+            }
+        }
+    }
+}
+
+impl<'de, R: Read, J: BorrowMut<JsonStreamReader>> de::Deserializer<'de>
+    for &mut Deserializer<'_, R, J>
+{
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let res = match self.next()? {
+            JsonToken::ObjBeg => {
+                let mut access = Access::new(self);
+                let value = visitor.visit_map(&mut access)?;
+                access.end(JsonToken::ObjEnd).map(|_| value)
+            }
+            JsonToken::ArrBeg => {
+                let mut access = Access::new(self);
+                let value = visitor.visit_seq(&mut access)?;
+                access.end(JsonToken::ArrEnd).map(|_| value)
+            }
+            JsonToken::Val(JsonValue::Null) => visitor.visit_unit(),
+            JsonToken::Val(JsonValue::Bool(b)) => visitor.visit_bool(b),
+            JsonToken::Val(JsonValue::String(s)) => visitor.visit_string(s.into_string()),
+            JsonToken::Val(JsonValue::Number(n)) => self.visit_number(&n, visitor),
+            _ => Err(self.tokens.error(ErrorCode::InvalidFormat)),
+        };
+        res.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if let JsonToken::Val(JsonValue::Null) = self.peek()? {
+            self.next()?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        if name == raw_value::TOKEN {
+            return match self.tokens.next_raw()? {
+                Some(raw) => visitor.visit_byte_buf(raw),
+                None => Err(self.tokens.error(ErrorCode::ExpectedAnyTerm)),
+            };
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let res = match self.next()? {
+            // "Variant"
+            JsonToken::Val(JsonValue::String(variant)) => {
+                visitor.visit_enum(variant.into_deserializer())
+            }
+            // {"Variant": ...}
+            JsonToken::ObjBeg => {
+                let value = visitor.visit_enum(&mut *self)?;
+                match self.next()? {
+                    JsonToken::ObjEnd => Ok(value),
+                    _ => Err(self.tokens.error(ErrorCode::ExpectedObjectCommaOrEnd)),
+                }
+            }
+            _ => Err(self.tokens.error(ErrorCode::ExpectedString)),
+        };
+        res.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.tokens.skip_value()?;
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier
+    }
+}
+
+// Walks through the entries of an object or the elements of an array.
+struct Access<'a, 'b, R, J> {
+    de: &'a mut Deserializer<'b, R, J>,
+    done: bool,
+}
+
+impl<'a, 'b, R: Read, J: BorrowMut<JsonStreamReader>> Access<'a, 'b, R, J> {
+    fn new(de: &'a mut Deserializer<'b, R, J>) -> Self {
+        Access { de, done: false }
+    }
+
+    // Checks whether the next token closes the container.
+    fn at_end(&mut self, end: &JsonToken) -> Result<bool> {
+        if !self.done && self.de.peek()? == end {
+            self.de.next()?;
+            self.done = true;
+        }
+        Ok(self.done)
+    }
+
+    // The visitor may stop before the container is closed.
+    fn end(&mut self, end: JsonToken) -> Result<()> {
+        let code = match end {
+            JsonToken::ObjEnd => ErrorCode::ExpectedObjectCommaOrEnd,
+            _ => ErrorCode::ExpectedListCommaOrEnd,
+        };
+        if self.at_end(&end)? {
+            Ok(())
+        } else {
+            self.de.next()?;
+            Err(self.de.tokens.error(code))
+        }
+    }
+}
+
+impl<'de, R: Read, J: BorrowMut<JsonStreamReader>> de::MapAccess<'de> for Access<'_, '_, R, J> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.at_end(&JsonToken::ObjEnd)? {
+            return Ok(None);
+        }
+        match self.de.next()? {
+            JsonToken::Key(key) => seed.deserialize(key.into_deserializer()).map(Some),
+            _ => Err(self.de.tokens.error(ErrorCode::ExpectedKey)),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+impl<'de, R: Read, J: BorrowMut<JsonStreamReader>> de::SeqAccess<'de> for Access<'_, '_, R, J> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.at_end(&JsonToken::ArrEnd)? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de, R: Read, J: BorrowMut<JsonStreamReader>> de::EnumAccess<'de>
+    for &mut Deserializer<'_, R, J>
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        match self.next()? {
+            JsonToken::Key(variant) => {
+                let value = seed.deserialize(variant.into_deserializer())?;
+                Ok((value, self))
+            }
+            _ => Err(self.tokens.error(ErrorCode::ExpectedKey)),
+        }
+    }
+}
+
+impl<'de, R: Read, J: BorrowMut<JsonStreamReader>> de::VariantAccess<'de>
+    for &mut Deserializer<'_, R, J>
+{
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod de_tests {
+    use super::*;
+    use crate::raw_value::RawValue;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Order {
+        id: u64,
+        price: f64,
+        tags: Vec<String>,
+        note: Option<String>,
+        kind: Kind,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Kind {
+        Buy,
+        Sell { limit: i32 },
+    }
+
+    #[test]
+    fn should_deserialize_struct() {
+        let buf = r#"{"id": 42, "price": 1.5e1, "tags": ["a", "b\n"], "note": null,
+            "kind": "Buy", "extra": {"ignored": [1, 2]}}"#;
+        let order: Order = from_slice(buf.as_bytes()).unwrap();
+        assert_eq!(
+            order,
+            Order {
+                id: 42,
+                price: 15.0,
+                tags: vec!["a".to_string(), "b\n".to_string()],
+                note: None,
+                kind: Kind::Buy,
+            }
+        );
+    }
+
+    #[test]
+    fn should_deserialize_struct_variant_and_map() {
+        let buf = r#"{"sell": {"Sell": {"limit": -3}}}"#;
+        let kinds: HashMap<String, Kind> = from_slice(buf.as_bytes()).unwrap();
+        assert_eq!(kinds["sell"], Kind::Sell { limit: -3 });
+    }
+
+    #[test]
+    fn should_deserialize_tuple() {
+        let buf = r#"[[1, "one"], [2, "two"]]"#;
+        let pairs: Vec<(u8, String)> = from_slice(buf.as_bytes()).unwrap();
+        assert_eq!(pairs, vec![(1, "one".to_string()), (2, "two".to_string())]);
+    }
+
+    #[test]
+    fn should_deserialize_raw_value() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Envelope {
+            id: u64,
+            payload: RawValue,
+            extra: Option<RawValue>,
+        }
+        let buf = r#"{"id": 7, "payload": {"a": [1, "]"] }, "extra": null}"#;
+        let envelope: Envelope = from_slice(buf.as_bytes()).unwrap();
+        assert_eq!(envelope.id, 7);
+        assert_eq!(envelope.payload.get(), br#"{"a": [1, "]"] }"#);
+        assert_eq!(envelope.extra, None);
+        let list: Vec<RawValue> = from_slice(br#"[1, "two", [3]]"#).unwrap();
+        let list: Vec<_> = list.iter().map(RawValue::get).collect();
+        assert_eq!(list, [&b"1"[..], b"\"two\"", b"[3]"]);
+    }
+
+    #[test]
+    fn should_report_type_error_position() {
+        let buf = r#"{"id": "42"}"#;
+        //           01234567890
+        let res: Result<HashMap<String, u64>> = from_slice(buf.as_bytes());
+        let err = res.unwrap_err();
+        assert_eq!(err.column, 10);
+        assert!(matches!(err.code, ErrorCode::Message(_)));
+    }
+
+    #[test]
+    fn should_report_syntax_error_position() {
+        let buf = r#"{"id": 42 "price": 1}"#;
+        let res: Result<HashMap<String, u64>> = from_slice(buf.as_bytes());
+        assert_eq!(
+            res,
+            Err(Error {
+                column: 10,
+                code: ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd
+            })
+        );
+    }
+}
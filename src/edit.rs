@@ -0,0 +1,852 @@
+//! Edits of a document that leave the rest of it as it was written.
+//!
+//! `Editor` copies its input to its output token by token and only rewrites
+//! the values its edits point to. Indentation and, in JSONC mode, comments
+//! around them are kept, so that the diff of a hand-written settings file
+//! shows the edited lines only. Removed members take their comma and the
+//! comments on their line with them, added ones copy the indentation of
+//! their siblings.
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use memchr::memchr;
+
+use crate::cst::{CstReader, CstToken};
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_pointer::{JsonPointer, PathTracker};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_stream_writer::write_escaped;
+use crate::utils::unescape_into;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Set(Vec<u8>),
+    Delete,
+    Insert(Vec<u8>),
+}
+
+/// A change at a JSON Pointer path, e.g. `set /server/port = 8080`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pointer: JsonPointer,
+    op: Op,
+}
+
+impl Edit {
+    /// Replaces the value at `pointer`, or adds the member to its object.
+    pub fn set(pointer: &str, value: &str) -> Result<Edit> {
+        Ok(Edit {
+            pointer: JsonPointer::parse(pointer)?,
+            op: Op::Set(value_text(value)?),
+        })
+    }
+
+    /// Removes the member or the element at `pointer`.
+    pub fn delete(pointer: &str) -> Result<Edit> {
+        Ok(Edit {
+            pointer: parent_pointer(pointer)?,
+            op: Op::Delete,
+        })
+    }
+
+    /// Inserts an element before the index `pointer` ends with, `-`
+    /// appending it to the array.
+    pub fn insert(pointer: &str, value: &str) -> Result<Edit> {
+        Ok(Edit {
+            pointer: parent_pointer(pointer)?,
+            op: Op::Insert(value_text(value)?),
+        })
+    }
+
+    pub fn pointer(&self) -> &JsonPointer {
+        &self.pointer
+    }
+
+    // Whether the edit is about a member of the container at `path`.
+    fn is_in(&self, path: &[String]) -> bool {
+        let segments = self.pointer.segments();
+        segments.len() == path.len() + 1 && segments[..path.len()] == *path
+    }
+
+    fn last_segment(&self) -> &str {
+        self.pointer.segments().last().map_or("", String::as_str)
+    }
+}
+
+impl FromStr for Edit {
+    type Err = Error;
+
+    /// Parses `set <pointer> = <json>`, `insert <pointer> = <json>` or
+    /// `delete <pointer>`.
+    fn from_str(edit: &str) -> Result<Edit> {
+        let edit = edit.trim();
+        let (op, rest) = edit.split_once(' ').unwrap_or((edit, ""));
+        let rest = rest.trim();
+        match op {
+            "set" | "insert" => {
+                let (pointer, value) = rest.split_once('=').ok_or_else(invalid_edit)?;
+                if op == "set" {
+                    Edit::set(pointer.trim(), value)
+                } else {
+                    Edit::insert(pointer.trim(), value)
+                }
+            }
+            "delete" => Edit::delete(rest),
+            _ => Err(invalid_edit()),
+        }
+    }
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.op {
+            Op::Set(value) => write!(
+                f,
+                "set {} = {}",
+                self.pointer,
+                String::from_utf8_lossy(value)
+            ),
+            Op::Delete => write!(f, "delete {}", self.pointer),
+            Op::Insert(value) => write!(
+                f,
+                "insert {} = {}",
+                self.pointer,
+                String::from_utf8_lossy(value)
+            ),
+        }
+    }
+}
+
+// A pointer to a member or an element, not the whole document.
+fn parent_pointer(pointer: &str) -> Result<JsonPointer> {
+    let pointer = JsonPointer::parse(pointer)?;
+    if pointer.is_root() {
+        return Err(invalid_edit());
+    }
+    Ok(pointer)
+}
+
+// Checks that `value` is a single JSON value.
+fn value_text(value: &str) -> Result<Vec<u8>> {
+    let value = value.trim();
+    let wrapped = format!("[{}]", value);
+    let mut reader = JsonStreamReader::new();
+    let tokens = reader
+        .read(wrapped.as_bytes())
+        .map_err(|_| invalid_edit())?;
+    let mut tracker = PathTracker::new();
+    let mut starts = [0; 2];
+    for token in &tokens {
+        if tracker.push(token) && tracker.depth() < 2 {
+            starts[tracker.depth()] += 1;
+        }
+    }
+    if reader.depth() > 0 || starts != [1, 1] {
+        return Err(invalid_edit());
+    }
+    Ok(value.as_bytes().to_vec())
+}
+
+fn invalid_edit() -> Error {
+    Error {
+        code: ErrorCode::InvalidEdit,
+        column: 0,
+    }
+}
+
+/// Makes edits while copying a document.
+#[derive(Debug, Clone, Default)]
+pub struct Editor {
+    edits: Vec<Edit>,
+    comments: bool,
+}
+
+impl Editor {
+    /// Edits are made in order, at the paths of the input document.
+    pub fn new(edits: Vec<Edit>) -> Self {
+        Editor {
+            edits,
+            comments: false,
+        }
+    }
+
+    /// Reads JSONC, keeping its comments.
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Copies `source` to `out` with the edits made. Fails with
+    /// `PointerNotFound` once the document is written if an edit found no
+    /// place to be made.
+    pub fn apply<R: Read, W: Write>(&self, mut source: R, out: W) -> Result<W> {
+        let mut cst = CstReader::new();
+        if self.comments {
+            cst = cst.with_comments();
+        }
+        let mut pass = Pass::new(&self.edits, out);
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let size = match source.read(&mut buf) {
+                Ok(0) => break,
+                Ok(size) => size,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(pass.error(ErrorCode::Io(err.kind()))),
+            };
+            cst.read(&buf[..size], |token| pass.push(token))?;
+            if let Some(err) = pass.failed.take() {
+                return Err(err);
+            }
+        }
+        cst.finish(|token| pass.push(token))?;
+        pass.finish()
+    }
+
+    /// Edits a document held in memory.
+    pub fn apply_to_vec(&self, doc: &[u8]) -> Result<Vec<u8>> {
+        self.apply(doc, Vec::with_capacity(doc.len()))
+    }
+}
+
+// What is in between two lexemes, held until it is known whether it stays.
+#[derive(Debug)]
+enum Held {
+    Space(Vec<u8>),
+    Comment(Vec<u8>),
+    Comma,
+    Key(Vec<u8>),
+    Colon,
+}
+
+impl Held {
+    fn text(&self) -> &[u8] {
+        match self {
+            Held::Space(text) | Held::Comment(text) | Held::Key(text) => text,
+            Held::Comma => b",",
+            Held::Colon => b":",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Frame {
+    object: bool,
+    // items read so far
+    count: usize,
+    // items written so far
+    written: usize,
+    // key of the current member
+    key: String,
+    // whitespace before the last item
+    lead: Vec<u8>,
+    // whitespace and colon in between the last key and its value
+    colon: Vec<u8>,
+}
+
+// The state of one `Editor::apply` call.
+struct Pass<'e, W> {
+    edits: &'e [Edit],
+    applied: Vec<bool>,
+    out: W,
+    // position of the next token in the input
+    at: usize,
+    frames: Vec<Frame>,
+    // path of the innermost container
+    path: Vec<String>,
+    held: Vec<Held>,
+    // depth in a container left out
+    skip: usize,
+    // a deleted item has not been followed by a comma yet
+    deleted: bool,
+    // still on the line of a deleted item
+    same_line: bool,
+    failed: Option<Error>,
+}
+
+impl<'e, W: Write> Pass<'e, W> {
+    fn new(edits: &'e [Edit], out: W) -> Self {
+        Pass {
+            edits,
+            applied: vec![false; edits.len()],
+            out,
+            at: 0,
+            frames: vec![],
+            path: vec![],
+            held: vec![],
+            skip: 0,
+            deleted: false,
+            same_line: false,
+            failed: None,
+        }
+    }
+
+    fn push(&mut self, token: CstToken<'_>) {
+        if self.failed.is_some() {
+            return;
+        }
+        if let Err(err) = self.take(token) {
+            self.failed = Some(err);
+        }
+        self.at += token.text().len();
+    }
+
+    fn finish(mut self) -> Result<W> {
+        if let Some(err) = self.failed.take() {
+            return Err(err);
+        }
+        self.flush_held()?;
+        if self.applied.contains(&false) {
+            return Err(self.error(ErrorCode::PointerNotFound));
+        }
+        Ok(self.out)
+    }
+
+    fn take(&mut self, token: CstToken<'_>) -> Result<()> {
+        if self.skip > 0 {
+            match token {
+                CstToken::ObjBeg | CstToken::ArrBeg => self.skip += 1,
+                CstToken::ObjEnd | CstToken::ArrEnd => {
+                    self.skip -= 1;
+                    if self.skip == 0 {
+                        self.end_item();
+                    }
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        match token {
+            CstToken::Whitespace(mut text) => {
+                if self.same_line {
+                    match memchr(b'\n', text) {
+                        Some(newline) => {
+                            self.same_line = false;
+                            text = &text[newline..];
+                        }
+                        // before the comma of the deleted item
+                        None if self.deleted => return Ok(()),
+                        // kept if the next item is on the same line
+                        None => {}
+                    }
+                }
+                self.held.push(Held::Space(text.to_vec()));
+            }
+            CstToken::Comment(text) => {
+                if self.same_line && memchr(b'\n', text).is_none() {
+                    while let Some(Held::Space(_)) = self.held.last() {
+                        self.held.pop();
+                    }
+                    return Ok(());
+                }
+                self.same_line = false;
+                self.held.push(Held::Comment(text.to_vec()));
+            }
+            CstToken::Comma if self.deleted => self.deleted = false,
+            CstToken::Comma => self.held.push(Held::Comma),
+            CstToken::Colon => self.held.push(Held::Colon),
+            CstToken::Key(text) => {
+                self.same_line = false;
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.key = decode_key(text);
+                }
+                self.held.push(Held::Key(text.to_vec()));
+            }
+            CstToken::ObjEnd | CstToken::ArrEnd => self.end_container(token)?,
+            _ => self.start_value(token)?,
+        }
+        Ok(())
+    }
+
+    fn start_value(&mut self, token: CstToken<'_>) -> Result<()> {
+        self.same_line = false;
+        let segment = self.frames.last().map(|frame| match frame.object {
+            true => frame.key.clone(),
+            false => frame.count.to_string(),
+        });
+        self.remember_format();
+        if let Some(segment) = &segment {
+            self.insert_before(segment)?;
+        }
+        let edits = self.edits;
+        let found = (0..edits.len()).find(|&i| {
+            !self.applied[i]
+                && !matches!(edits[i].op, Op::Insert(_))
+                && self.targets(&edits[i], segment.as_deref())
+        });
+        let container = matches!(token, CstToken::ObjBeg | CstToken::ArrBeg);
+        match found {
+            Some(i) => {
+                self.applied[i] = true;
+                match &edits[i].op {
+                    Op::Set(value) => {
+                        self.flush_held()?;
+                        self.write(value)?;
+                        self.count_written();
+                    }
+                    _ => {
+                        self.cut_leading();
+                        self.deleted = true;
+                        self.same_line = true;
+                    }
+                }
+                if container {
+                    self.skip = 1;
+                } else {
+                    self.end_item();
+                }
+            }
+            None => {
+                self.flush_held()?;
+                self.write(token.text())?;
+                self.count_written();
+                if container {
+                    self.frames.push(Frame {
+                        object: token == CstToken::ObjBeg,
+                        ..Frame::default()
+                    });
+                    self.path.extend(segment);
+                } else {
+                    self.end_item();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Drops what is held before a deleted item, the comments at the end of
+    // the line of the item before aside.
+    fn cut_leading(&mut self) {
+        let start = self
+            .held
+            .iter()
+            .rposition(|held| matches!(held, Held::Comma))
+            .map_or(0, |i| i + 1);
+        for i in start..self.held.len() {
+            if let Held::Space(text) = &mut self.held[i] {
+                if let Some(newline) = memchr(b'\n', text) {
+                    text.truncate(newline);
+                    self.held.truncate(i + 1);
+                    return;
+                }
+            }
+        }
+        self.held.truncate(start);
+    }
+
+    // Writes the elements to insert before the one at `segment`.
+    fn insert_before(&mut self, segment: &str) -> Result<()> {
+        match self.frames.last() {
+            Some(frame) if !frame.object => {}
+            _ => return Ok(()),
+        }
+        let edits = self.edits;
+        for (i, edit) in edits.iter().enumerate() {
+            let Op::Insert(value) = &edit.op else {
+                continue;
+            };
+            if self.applied[i] || !edit.is_in(&self.path) || edit.last_segment() != segment {
+                continue;
+            }
+            self.applied[i] = true;
+            let lead = self.separator();
+            self.flush_held()?;
+            self.write(value)?;
+            // held, to be dropped if the item after is deleted and last
+            self.held.push(Held::Comma);
+            self.held.push(Held::Space(lead));
+            self.count_written();
+        }
+        Ok(())
+    }
+
+    fn end_container(&mut self, token: CstToken<'_>) -> Result<()> {
+        self.same_line = false;
+        if self.deleted {
+            // the deleted item was the last one, its comma was before it
+            self.deleted = false;
+            if let Some(i) = self
+                .held
+                .iter()
+                .rposition(|held| matches!(held, Held::Comma))
+            {
+                self.held.remove(i);
+            }
+        }
+        let Some(frame) = self.frames.last() else {
+            return Err(self.error(ErrorCode::InvalidFormat));
+        };
+        let edits = self.edits;
+        let mut added = vec![];
+        for (i, edit) in edits.iter().enumerate() {
+            if self.applied[i] || !edit.is_in(&self.path) {
+                continue;
+            }
+            match (&edit.op, frame.object) {
+                (Op::Insert(_), true) => return Err(self.error(ErrorCode::ExpectedArray)),
+                (Op::Insert(value), false)
+                    if edit.last_segment() == "-"
+                        || edit.last_segment() == frame.count.to_string() =>
+                {
+                    added.push((i, None, value));
+                }
+                (Op::Set(value), true) => added.push((i, Some(edit.last_segment()), value)),
+                _ => {}
+            }
+        }
+        if !added.is_empty() {
+            // the new items go after the comments on the line of the last one
+            let rest = self.split_line();
+            let frame = &self.frames[self.frames.len() - 1];
+            let mut text = vec![];
+            for (n, (i, key, value)) in added.into_iter().enumerate() {
+                self.applied[i] = true;
+                let first = frame.written + n == 0;
+                if !first {
+                    text.push(b',');
+                }
+                if n == 0 {
+                    self.held
+                        .drain(..)
+                        .for_each(|held| text.extend(held.text()));
+                }
+                if first {
+                    text.extend(&frame.lead);
+                } else {
+                    text.extend(self.separator());
+                }
+                if let Some(key) = key {
+                    write_escaped(&mut text, key).unwrap_or_default();
+                    if frame.colon.is_empty() {
+                        text.extend(b": ");
+                    } else {
+                        text.extend(&frame.colon);
+                    }
+                }
+                text.extend(value);
+            }
+            self.held = rest;
+            self.write(&text)?;
+        }
+        self.flush_held()?;
+        self.write(token.text())?;
+        self.frames.pop();
+        self.path.pop();
+        self.end_item();
+        Ok(())
+    }
+
+    // Splits what is held at the first newline. Returns what follows it.
+    fn split_line(&mut self) -> Vec<Held> {
+        for i in 0..self.held.len() {
+            if let Held::Space(text) = &mut self.held[i] {
+                if let Some(newline) = memchr(b'\n', text) {
+                    let tail = text.split_off(newline);
+                    let mut rest = self.held.split_off(i + 1);
+                    rest.insert(0, Held::Space(tail));
+                    return rest;
+                }
+            }
+        }
+        std::mem::take(&mut self.held)
+    }
+
+    // Whether `edit` points to the value starting at `segment` of the
+    // innermost container, the whole document when there is none.
+    fn targets(&self, edit: &Edit, segment: Option<&str>) -> bool {
+        match segment {
+            Some(segment) => edit.is_in(&self.path) && edit.last_segment() == segment,
+            None => edit.pointer.is_root(),
+        }
+    }
+
+    // Keeps the whitespace before the next item and after its key, to lay
+    // out the added items the same way.
+    fn remember_format(&mut self) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        let key = self
+            .held
+            .iter()
+            .position(|held| matches!(held, Held::Key(_)));
+        frame.lead = match self.held[..key.unwrap_or(self.held.len())].last() {
+            Some(Held::Space(text)) => text.clone(),
+            _ => vec![],
+        };
+        if let Some(key) = key {
+            frame.colon = self.held[key + 1..]
+                .iter()
+                .filter(|held| !matches!(held, Held::Comment(_)))
+                .flat_map(|held| held.text().iter().copied())
+                .collect();
+        }
+    }
+
+    // Whitespace after the comma before a new item.
+    fn separator(&self) -> Vec<u8> {
+        let lead = self.frames.last().map(|frame| &frame.lead);
+        match lead {
+            Some(lead) if !lead.is_empty() => lead.clone(),
+            _ => b" ".to_vec(),
+        }
+    }
+
+    fn count_written(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.written += 1;
+        }
+    }
+
+    fn end_item(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.count += 1;
+        }
+    }
+
+    fn flush_held(&mut self) -> Result<()> {
+        let held = std::mem::take(&mut self.held);
+        for held in &held {
+            self.write(held.text())?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, text: &[u8]) -> Result<()> {
+        self.out
+            .write_all(text)
+            .map_err(|err| self.error(ErrorCode::Io(err.kind())))
+    }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        Error {
+            code,
+            column: self.at,
+        }
+    }
+}
+
+fn decode_key(text: &[u8]) -> String {
+    let mut key = vec![];
+    unescape_into(&text[1..text.len() - 1], &mut key);
+    String::from_utf8(key).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod edit_tests {
+    use super::*;
+
+    const SETTINGS: &str = r#"{
+  "server": {
+    "host": "localhost",
+    "port": 80
+  },
+  "debug": true,
+  "plugins": [
+    "a",
+    "b"
+  ]
+}
+"#;
+
+    fn edit(doc: &str, edits: &[&str]) -> String {
+        let edits = edits.iter().map(|edit| edit.parse().unwrap()).collect();
+        let out = Editor::new(edits).apply_to_vec(doc.as_bytes()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn should_change_edited_lines_only() {
+        let out = edit(
+            SETTINGS,
+            &[
+                "set /server/port = 8080",
+                "delete /debug",
+                r#"insert /plugins/- = "x""#,
+            ],
+        );
+        assert_eq!(
+            out,
+            r#"{
+  "server": {
+    "host": "localhost",
+    "port": 8080
+  },
+  "plugins": [
+    "a",
+    "b",
+    "x"
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn should_delete_first_and_last_members() {
+        let doc = "{\n  \"a\": 1,\n  \"b\": [1, 2],\n  \"c\": 3\n}";
+        assert_eq!(
+            edit(doc, &["delete /a"]),
+            "{\n  \"b\": [1, 2],\n  \"c\": 3\n}"
+        );
+        assert_eq!(
+            edit(doc, &["delete /c"]),
+            "{\n  \"a\": 1,\n  \"b\": [1, 2]\n}"
+        );
+        assert_eq!(
+            edit(doc, &["delete /b/0", "delete /b/1"]),
+            "{\n  \"a\": 1,\n  \"b\": [],\n  \"c\": 3\n}"
+        );
+        assert_eq!(edit(doc, &["delete /a", "delete /b", "delete /c"]), "{\n}");
+        assert_eq!(edit("[1, 2, 3]", &["delete /1"]), "[1, 3]");
+    }
+
+    #[test]
+    fn should_add_members_and_elements() {
+        assert_eq!(
+            edit(
+                SETTINGS,
+                &[
+                    "set /server/tls = {\"on\": true}",
+                    "insert /plugins/0 = \"z\""
+                ]
+            ),
+            r#"{
+  "server": {
+    "host": "localhost",
+    "port": 80,
+    "tls": {"on": true}
+  },
+  "debug": true,
+  "plugins": [
+    "z",
+    "a",
+    "b"
+  ]
+}
+"#
+        );
+        assert_eq!(edit(r#"{"a":1}"#, &["set /b = 2"]), r#"{"a":1, "b":2}"#);
+        assert_eq!(edit("{}", &["set /a = null"]), r#"{"a": null}"#);
+        assert_eq!(edit("[ ]", &["insert /- = 1", "insert /- = 2"]), "[1, 2 ]");
+        assert_eq!(
+            edit("[1, 2]", &["insert /1 = 9", "insert /2 = 3"]),
+            "[1, 9, 2, 3]"
+        );
+        let edits = ["delete /1", "insert /1 = 9"];
+        assert_eq!(edit("[1, 2]", &edits), "[1, 9]");
+        assert_eq!(edit("[1, 2]", &[edits[1], edits[0]]), "[1, 9]");
+        assert_eq!(edit("[1, 2, 3]", &edits), "[1, 9, 3]");
+        assert_eq!(edit("[1]", &["delete /0", "insert /0 = 9"]), "[9]");
+        assert_eq!(edit("[\n  1,\n  2\n]", &edits), "[\n  1,\n  9\n]");
+        let root = Editor::new(vec![Edit::set("", "0").unwrap()]);
+        assert_eq!(root.apply_to_vec(b" [1] ").unwrap(), b" 0 ");
+    }
+
+    #[test]
+    fn should_keep_comments_in_jsonc() {
+        let doc = r#"// deployment settings
+{
+  /* where to listen */
+  "port": 80, // http
+  "debug": true, // remove before release
+  "tags": ["a" /* first */]
+}
+"#;
+        let edits = vec![
+            Edit::set("/port", "443").unwrap(),
+            Edit::delete("/debug").unwrap(),
+            Edit::set("/region", "\"eu\"").unwrap(),
+        ];
+        let out = Editor::new(edits.clone())
+            .apply_to_vec(doc.as_bytes())
+            .unwrap_err();
+        assert_eq!(out.code, ErrorCode::ExpectedObjectOrArray);
+        let out = Editor::new(edits)
+            .with_comments()
+            .apply_to_vec(doc.as_bytes())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"// deployment settings
+{
+  /* where to listen */
+  "port": 443, // http
+  "tags": ["a" /* first */],
+  "region": "eu"
+}
+"#
+        );
+    }
+
+    #[test]
+    fn should_parse_and_print_edits() {
+        let edit: Edit = "  set /server/port=8080 ".parse().unwrap();
+        assert_eq!(edit, Edit::set("/server/port", "8080").unwrap());
+        assert_eq!(edit.to_string(), "set /server/port = 8080");
+        let edit: Edit = "insert /a~1b/- = [1, {\"x\": 2}]".parse().unwrap();
+        assert_eq!(edit.pointer().segments(), ["a/b", "-"]);
+        assert_eq!(edit.to_string(), "insert /a~1b/- = [1, {\"x\": 2}]");
+        for invalid in [
+            "set /a = 1 2",
+            "set /a = ",
+            "set /a = [1",
+            "set /a 1",
+            "delete ",
+            "insert = 1",
+            "move /a",
+        ] {
+            let res = invalid.parse::<Edit>();
+            assert_eq!(res.unwrap_err().code, ErrorCode::InvalidEdit, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn should_fail_on_missing_path() {
+        let editor = Editor::new(vec![Edit::set("/x/y", "1").unwrap()]);
+        let err = editor.apply_to_vec(br#"{"a": 1}"#).unwrap_err();
+        assert_eq!(
+            err,
+            Error {
+                code: ErrorCode::PointerNotFound,
+                column: 8
+            }
+        );
+        let editor = Editor::new(vec![Edit::insert("/-", "1").unwrap()]);
+        let err = editor.apply_to_vec(br#"{"a": 1}"#).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ExpectedArray);
+        let editor = Editor::new(vec![Edit::set("/5", "1").unwrap()]);
+        let err = editor.apply_to_vec(b"[1, 2]").unwrap_err();
+        assert_eq!(err.code, ErrorCode::PointerNotFound);
+    }
+
+    // Hands out the document one byte at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = self.0.len().min(buf.len()).min(1);
+            buf[..size].copy_from_slice(&self.0[..size]);
+            self.0 = &self.0[size..];
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn should_edit_document_read_in_chunks() {
+        let edits: Vec<Edit> = ["set /server/host = \"0.0.0.0\"", "delete /plugins/0"]
+            .iter()
+            .map(|edit| edit.parse().unwrap())
+            .collect();
+        let editor = Editor::new(edits);
+        let whole = editor.apply_to_vec(SETTINGS.as_bytes()).unwrap();
+        let trickled = editor.apply(Trickle(SETTINGS.as_bytes()), vec![]).unwrap();
+        assert_eq!(trickled, whole);
+        let expected = SETTINGS
+            .replace("\"localhost\"", "\"0.0.0.0\"")
+            .replace("\"a\",\n    ", "");
+        assert_eq!(String::from_utf8(whole).unwrap(), expected);
+    }
+}
@@ -1,9 +1,11 @@
 //! When json parsing goes wrong.
 use core::result;
+use std::fmt;
+use std::io;
 
 // A list specifying categories of JSON parser errors.
 #[non_exhaustive]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ErrorCode {
     /// Expected this character to be a `':'`.
     ExpectedColon,
@@ -58,13 +60,108 @@ pub enum ErrorCode {
 
     /// The array is invalid. It started with [ followed by an invalid character.
     InvalidArrFormat,
+
+    /// The string contains an invalid escape sequence or is not valid UTF-8.
+    InvalidEscape,
+
+    /// The input ended in the middle of a JSON document.
+    UnexpectedEof,
+
+    /// Reading from the underlying source failed.
+    Io(io::ErrorKind),
+
+    /// A free-form message, e.g. reported by a serde `Deserialize` impl.
+    Message(String),
+
+    /// The JSON Pointer is malformed.
+    InvalidPointer,
+
+    /// The document has no value at the given JSON Pointer.
+    PointerNotFound,
+
+    /// Expected this value to be an array.
+    ExpectedArray,
+
+    /// Expected this value to be an object.
+    ExpectedObject,
+
+    /// The object has the key more than once.
+    DuplicateKey(String),
+
+    /// The string is not valid base64 text.
+    InvalidBase64,
+
+    /// A `/` does not start a comment.
+    InvalidComment,
+
+    /// The edit is malformed or cannot be made.
+    InvalidEdit,
+
+    /// The checkpoint is damaged or was made by another version.
+    InvalidCheckpoint,
+
+    /// The array index is damaged or was made by another version.
+    InvalidIndex,
+
+    /// The JSON Schema is malformed or uses an unsupported pattern.
+    InvalidSchema,
 }
 
-#[derive(Debug, PartialEq)]
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCode::ExpectedColon => f.write_str("expected `:`"),
+            ErrorCode::ExpectedKey => f.write_str("expected a key"),
+            ErrorCode::ExpectedNumber => f.write_str("expected a number"),
+            ErrorCode::ExpectedObjectOrArray => f.write_str("expected `{` or `[`"),
+            ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd => {
+                f.write_str("expected `,`, `}` or `]`")
+            }
+            ErrorCode::ExpectedAnyTerm => f.write_str("expected a value"),
+            ErrorCode::ExpectedTrue => f.write_str("expected `true`"),
+            ErrorCode::ExpectedFalse => f.write_str("expected `false`"),
+            ErrorCode::ExpectedNull => f.write_str("expected `null`"),
+            ErrorCode::InvalidNumber => f.write_str("invalid number"),
+            ErrorCode::ExpectedString => f.write_str("expected a string"),
+            ErrorCode::ExpectedListCommaOrEnd => f.write_str("expected `,` or `]`"),
+            ErrorCode::ExpectedObjectCommaOrEnd => f.write_str("expected `,` or `}`"),
+            ErrorCode::StopSignal(msg) => write!(f, "stopped: {}", msg),
+            ErrorCode::TooLongKey => f.write_str("object key is too long"),
+            ErrorCode::InvalidFormat => f.write_str("invalid format"),
+            ErrorCode::TooManyTokens => f.write_str("nesting is too deep"),
+            ErrorCode::InvalidArrFormat => f.write_str("invalid array"),
+            ErrorCode::InvalidEscape => f.write_str("invalid string escape or UTF-8"),
+            ErrorCode::UnexpectedEof => f.write_str("unexpected end of input"),
+            ErrorCode::Io(kind) => write!(f, "io error: {}", kind),
+            ErrorCode::Message(msg) => f.write_str(msg),
+            ErrorCode::InvalidPointer => f.write_str("invalid JSON pointer"),
+            ErrorCode::PointerNotFound => f.write_str("JSON pointer not found"),
+            ErrorCode::ExpectedArray => f.write_str("expected an array"),
+            ErrorCode::ExpectedObject => f.write_str("expected an object"),
+            ErrorCode::DuplicateKey(key) => write!(f, "duplicate key `{}`", key),
+            ErrorCode::InvalidBase64 => f.write_str("invalid base64"),
+            ErrorCode::InvalidComment => f.write_str("invalid comment"),
+            ErrorCode::InvalidEdit => f.write_str("invalid edit"),
+            ErrorCode::InvalidCheckpoint => f.write_str("invalid checkpoint"),
+            ErrorCode::InvalidIndex => f.write_str("invalid array index"),
+            ErrorCode::InvalidSchema => f.write_str("invalid schema"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Error {
     pub code: ErrorCode,
     pub column: usize,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.code, self.column)
+    }
+}
+
+impl std::error::Error for Error {}
+
 // Alias for a `Result` w/ the error type `Error`.
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,335 @@
+//! Streaming pretty-printing and minifying.
+//!
+//! Tokens go straight from the reader to the writer, so large files are
+//! reformatted in constant memory. Number lexemes are written exactly as
+//! they were read.
+use std::io::{self, Read, Write};
+
+use crate::error::Result;
+use crate::json_stream_writer::{CompactFormatter, Formatter, JsonStreamWriter};
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+use crate::token_reader::TokenReader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    Spaces(usize),
+    Tabs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub indent: Indent,
+    pub line_ending: LineEnding,
+    pub space_before_colon: bool,
+    pub space_after_colon: bool,
+    /// Arrays of scalars that fit in this many bytes are kept on one line.
+    pub inline_arrays_width: Option<usize>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent: Indent::Spaces(2),
+            line_ending: LineEnding::Lf,
+            space_before_colon: false,
+            space_after_colon: true,
+            inline_arrays_width: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Style {
+    Compact,
+    Pretty(FormatOptions),
+}
+
+/// Writes one member or element per line.
+#[derive(Debug, Clone)]
+pub struct PrettyFormatter {
+    options: FormatOptions,
+    level: usize,
+    // the current array is written on one line
+    inline: bool,
+}
+
+impl PrettyFormatter {
+    pub fn new(options: FormatOptions) -> Self {
+        PrettyFormatter {
+            options,
+            level: 0,
+            inline: false,
+        }
+    }
+
+    fn new_line<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.options.line_ending.as_bytes())?;
+        for _ in 0..self.level {
+            match self.options.indent {
+                Indent::Spaces(n) => write!(w, "{:1$}", "", n)?,
+                Indent::Tabs => w.write_all(b"\t")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Formatter for PrettyFormatter {
+    fn begin_object<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.level += 1;
+        w.write_all(b"{")
+    }
+
+    fn end_object<W: Write>(&mut self, w: &mut W, empty: bool) -> io::Result<()> {
+        self.level -= 1;
+        if !empty {
+            self.new_line(w)?;
+        }
+        w.write_all(b"}")
+    }
+
+    fn begin_array<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.level += 1;
+        w.write_all(b"[")
+    }
+
+    fn end_array<W: Write>(&mut self, w: &mut W, empty: bool) -> io::Result<()> {
+        self.level -= 1;
+        if !empty && !self.inline {
+            self.new_line(w)?;
+        }
+        self.inline = false;
+        w.write_all(b"]")
+    }
+
+    fn begin_key<W: Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        if !first {
+            w.write_all(b",")?;
+        }
+        self.new_line(w)
+    }
+
+    fn key_separator<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        if self.options.space_before_colon {
+            w.write_all(b" ")?;
+        }
+        w.write_all(b":")?;
+        if self.options.space_after_colon {
+            w.write_all(b" ")?;
+        }
+        Ok(())
+    }
+
+    fn begin_element<W: Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        match (first, self.inline) {
+            (true, true) => Ok(()),
+            (false, true) => w.write_all(b", "),
+            (true, false) => self.new_line(w),
+            (false, false) => {
+                w.write_all(b",")?;
+                self.new_line(w)
+            }
+        }
+    }
+
+    fn end_document<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.options.line_ending.as_bytes())
+    }
+}
+
+/// Pretty-prints tokens, deciding which arrays fit on one line.
+///
+/// To find out whether an array is short, its elements are held back until
+/// the array ends or the width is exceeded, so at most `inline_arrays_width`
+/// worth of tokens is buffered.
+pub struct PrettyPrinter<W> {
+    writer: JsonStreamWriter<W, PrettyFormatter>,
+    width: Option<usize>,
+    // elements of the array that might fit on one line
+    pending: Option<(Vec<JsonValue>, usize)>,
+}
+
+impl<W: Write> PrettyPrinter<W> {
+    pub fn new(out: W, options: FormatOptions) -> Self {
+        PrettyPrinter {
+            width: options.inline_arrays_width,
+            writer: JsonStreamWriter::with_formatter(out, PrettyFormatter::new(options)),
+            pending: None,
+        }
+    }
+
+    pub fn write_token(&mut self, token: &JsonToken) -> Result<()> {
+        if let Some((items, width)) = &mut self.pending {
+            match token {
+                JsonToken::Val(value) => {
+                    // the brackets and separators take two bytes per element
+                    *width += rendered_len(value) + 2;
+                    items.push(value.clone());
+                    if *width > self.width.unwrap_or(0) {
+                        self.flush_pending(false)?;
+                    }
+                    return Ok(());
+                }
+                JsonToken::ArrEnd => {
+                    self.flush_pending(true)?;
+                    return self.writer.end_array();
+                }
+                _ => self.flush_pending(false)?,
+            }
+        }
+        match token {
+            JsonToken::ArrBeg if self.width.is_some() => {
+                self.writer.begin_array()?;
+                self.pending = Some((vec![], 0));
+                Ok(())
+            }
+            token => self.writer.write_token(token),
+        }
+    }
+
+    pub fn finish(self) -> Result<W> {
+        self.writer.finish()
+    }
+
+    fn flush_pending(&mut self, inline: bool) -> Result<()> {
+        if let Some((items, _)) = self.pending.take() {
+            self.writer.formatter_mut().inline = inline;
+            for item in &items {
+                self.writer.value(item)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Bytes taken by a scalar on the output, escapes aside.
+fn rendered_len(value: &JsonValue) -> usize {
+    match value {
+        JsonValue::Null => 4,
+        JsonValue::Bool(true) => 4,
+        JsonValue::Bool(false) => 5,
+        JsonValue::String(s) => s.len() + 2,
+        JsonValue::Number(n) => n.len(),
+    }
+}
+
+/// Reads a document from `source` and writes it to `out` in the given style.
+pub fn reformat<R: Read, W: Write>(source: R, out: W, style: &Style) -> Result<W> {
+    let tokens = TokenReader::new(source);
+    match style {
+        Style::Compact => {
+            let mut writer = JsonStreamWriter::with_formatter(out, CompactFormatter);
+            for token in tokens {
+                writer.write_token(&token?)?;
+            }
+            writer.finish()
+        }
+        Style::Pretty(options) => {
+            let mut printer = PrettyPrinter::new(out, options.clone());
+            for token in tokens {
+                printer.write_token(&token?)?;
+            }
+            printer.finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+
+    const DOC: &str =
+        r#"{"a": [1, 2.50, 3], "b": {"c": null, "d": []}, "e": [{"f": true}], "g": {}}"#;
+
+    fn format(doc: &str, style: Style) -> String {
+        String::from_utf8(reformat(doc.as_bytes(), vec![], &style).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn should_minify() {
+        assert_eq!(
+            format(DOC, Style::Compact),
+            r#"{"a":[1,2.50,3],"b":{"c":null,"d":[]},"e":[{"f":true}],"g":{}}"#
+        );
+    }
+
+    #[test]
+    fn should_pretty_print() {
+        let expected = r#"{
+  "a": [
+    1,
+    2.50,
+    3
+  ],
+  "b": {
+    "c": null,
+    "d": []
+  },
+  "e": [
+    {
+      "f": true
+    }
+  ],
+  "g": {}
+}
+"#;
+        assert_eq!(
+            format(DOC, Style::Pretty(FormatOptions::default())),
+            expected
+        );
+    }
+
+    #[test]
+    fn should_use_tabs_crlf_and_colon_spaces() {
+        let options = FormatOptions {
+            indent: Indent::Tabs,
+            line_ending: LineEnding::CrLf,
+            space_before_colon: true,
+            space_after_colon: true,
+            inline_arrays_width: None,
+        };
+        assert_eq!(
+            format(r#"{"a": {"b": 1}}"#, Style::Pretty(options)),
+            "{\r\n\t\"a\" : {\r\n\t\t\"b\" : 1\r\n\t}\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn should_inline_short_arrays() {
+        let options = FormatOptions {
+            inline_arrays_width: Some(16),
+            ..FormatOptions::default()
+        };
+        let doc = r#"{"short": [1, "two", null], "long": [1234567, 1234567, 1234567], "nested": [[1], {}]}"#;
+        let expected = r#"{
+  "short": [1, "two", null],
+  "long": [
+    1234567,
+    1234567,
+    1234567
+  ],
+  "nested": [
+    [1],
+    {}
+  ]
+}
+"#;
+        assert_eq!(format(doc, Style::Pretty(options)), expected);
+    }
+}
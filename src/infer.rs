@@ -0,0 +1,522 @@
+//! Schema inference over the token stream.
+//!
+//! `SchemaInferrer` learns the shape of documents from their tokens. Values
+//! are grouped by path, array indices being collapsed so that all elements
+//! of an array share the path of `items`. For each path it counts the types
+//! seen and how often object members are present, and tracks the range of
+//! numbers, string lengths and array lengths, along with the distinct strings
+//! while there are only a few of them. Memory grows with the number of
+//! distinct paths, not with the data. The result is a JSON Schema document
+//! and a summary meant for people.
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Read;
+
+use crate::error::Result;
+use crate::json_node::JsonNode;
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+use crate::token_reader::TokenReader;
+
+const DEFAULT_ENUM_LIMIT: usize = 10;
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Infers a schema, see the module docs.
+#[derive(Debug, Clone)]
+pub struct SchemaInferrer {
+    // the root first
+    paths: Vec<PathStats>,
+    // open containers, with the number of elements of arrays
+    stack: Vec<(usize, Option<usize>)>,
+    // the key of the next member
+    key: Option<String>,
+    // the path of a string read in fragments, and its length so far
+    string: Option<(usize, usize)>,
+    enum_limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Object,
+    Array,
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Null,
+}
+
+const KINDS: [Kind; 7] = [
+    Kind::Object,
+    Kind::Array,
+    Kind::String,
+    Kind::Integer,
+    Kind::Number,
+    Kind::Boolean,
+    Kind::Null,
+];
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Object => "object",
+            Kind::Array => "array",
+            Kind::String => "string",
+            Kind::Integer => "integer",
+            Kind::Number => "number",
+            Kind::Boolean => "boolean",
+            Kind::Null => "null",
+        }
+    }
+}
+
+// What was seen at a path.
+#[derive(Debug, Clone, Default)]
+struct PathStats {
+    // values seen, by kind
+    kinds: [usize; 7],
+    minimum: Option<(f64, String)>,
+    maximum: Option<(f64, String)>,
+    lengths: Option<(usize, usize)>,
+    sizes: Option<(usize, usize)>,
+    // `None` once there are too many
+    strings: Option<Vec<String>>,
+    properties: Vec<(String, usize)>,
+    // index of `properties` by name
+    members: HashMap<String, usize>,
+    items: Option<usize>,
+}
+
+impl PathStats {
+    fn count(&self) -> usize {
+        self.kinds.iter().sum()
+    }
+
+    fn seen(&self, kind: Kind) -> usize {
+        self.kinds[kind as usize]
+    }
+
+    // The kinds seen, a number being an integer only if all of them are.
+    fn kinds(&self) -> Vec<Kind> {
+        KINDS
+            .into_iter()
+            .filter(|&kind| match kind {
+                Kind::Integer => self.seen(kind) > 0 && self.seen(Kind::Number) == 0,
+                _ => self.seen(kind) > 0,
+            })
+            .collect()
+    }
+}
+
+impl SchemaInferrer {
+    pub fn new() -> Self {
+        SchemaInferrer {
+            paths: vec![PathStats {
+                strings: Some(vec![]),
+                ..PathStats::default()
+            }],
+            stack: vec![],
+            key: None,
+            string: None,
+            enum_limit: DEFAULT_ENUM_LIMIT,
+        }
+    }
+
+    /// Sets how many distinct strings a path may have to be given an `enum`.
+    pub fn with_enum_limit(mut self, limit: usize) -> Self {
+        self.enum_limit = limit;
+        self
+    }
+
+    /// Takes the next token into account. Any number of documents can be
+    /// pushed one after the other, e.g. the records of an NDJSON file. Keys
+    /// pushed as `KnownKey` are named `#` and their index.
+    pub fn push(&mut self, token: &JsonToken) {
+        match token {
+            JsonToken::Key(key) => self.key = Some(key.to_string()),
+            JsonToken::KnownKey(id) => self.key = Some(format!("#{}", id)),
+            JsonToken::ObjBeg => {
+                let path = self.start_value(Kind::Object);
+                self.stack.push((path, None));
+            }
+            JsonToken::ArrBeg => {
+                let path = self.start_value(Kind::Array);
+                self.stack.push((path, Some(0)));
+            }
+            JsonToken::ObjEnd => {
+                self.stack.pop();
+            }
+            JsonToken::ArrEnd => {
+                if let Some((path, Some(len))) = self.stack.pop() {
+                    let sizes = &mut self.paths[path].sizes;
+                    *sizes = widen(*sizes, len);
+                }
+            }
+            JsonToken::Val(value) => self.push_value(value),
+            JsonToken::StrBegin => {
+                let path = self.start_value(Kind::String);
+                // long strings are no enum candidates
+                self.paths[path].strings = None;
+                self.string = Some((path, 0));
+            }
+            JsonToken::StrChunk(chunk) => {
+                if let Some((_, len)) = &mut self.string {
+                    *len += chunk.chars().count();
+                }
+            }
+            JsonToken::StrEnd => {
+                if let Some((path, len)) = self.string.take() {
+                    let lengths = &mut self.paths[path].lengths;
+                    *lengths = widen(*lengths, len);
+                }
+            }
+            // captured values are not looked into
+            JsonToken::Raw(_) => {
+                self.next_path();
+            }
+        }
+    }
+
+    /// Pushes all the tokens of a document read from `source`.
+    pub fn read<R: Read>(&mut self, source: R) -> Result<()> {
+        let mut tokens = TokenReader::new(source);
+        while let Some(token) = tokens.next_token()? {
+            self.push(&token);
+        }
+        Ok(())
+    }
+
+    /// The inferred schema: types, the members of objects with those
+    /// present in all of them as `required`, `items`, the ranges seen and an
+    /// `enum` for strings with few distinct values, each seen more than once
+    /// on average.
+    pub fn schema(&self) -> JsonNode {
+        let mut schema = match self.schema_at(0) {
+            JsonNode::Object(members) => members,
+            _ => vec![],
+        };
+        schema.insert(
+            0,
+            ("$schema".to_string(), JsonNode::String(DRAFT.to_string())),
+        );
+        JsonNode::Object(schema)
+    }
+
+    /// One line per path: the types seen, how many values or how often a
+    /// member was present, and the ranges and distinct strings.
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        self.summarize(0, "(root)", None, &mut out);
+        out
+    }
+
+    fn push_value(&mut self, value: &JsonValue) {
+        match value {
+            JsonValue::Null => {
+                self.start_value(Kind::Null);
+            }
+            JsonValue::Bool(_) => {
+                self.start_value(Kind::Boolean);
+            }
+            JsonValue::String(text) => {
+                let path = self.start_value(Kind::String);
+                let limit = self.enum_limit;
+                let stats = &mut self.paths[path];
+                stats.lengths = widen(stats.lengths, text.chars().count());
+                if let Some(strings) = &mut stats.strings {
+                    if !strings.iter().any(|string| string == text) {
+                        strings.push(text.to_string());
+                    }
+                    if strings.len() > limit {
+                        stats.strings = None;
+                    }
+                }
+            }
+            JsonValue::Number(text) => {
+                let n = text.parse::<f64>().unwrap_or(f64::NAN);
+                // `1.0` and `1e3` are integers in JSON Schema, but not to
+                // the parsers of most languages
+                let kind = if !text.contains(['.', 'e', 'E']) {
+                    Kind::Integer
+                } else {
+                    Kind::Number
+                };
+                let path = self.start_value(kind);
+                let stats = &mut self.paths[path];
+                if stats.minimum.as_ref().map_or(true, |(min, _)| n < *min) {
+                    stats.minimum = Some((n, text.to_string()));
+                }
+                if stats.maximum.as_ref().map_or(true, |(max, _)| n > *max) {
+                    stats.maximum = Some((n, text.to_string()));
+                }
+            }
+        }
+    }
+
+    // Counts a value of `kind` starting at the next path, which is returned.
+    fn start_value(&mut self, kind: Kind) -> usize {
+        let path = self.next_path();
+        self.paths[path].kinds[kind as usize] += 1;
+        path
+    }
+
+    fn next_path(&mut self) -> usize {
+        let Some((parent, len)) = self.stack.last_mut() else {
+            return 0;
+        };
+        let parent = *parent;
+        if let Some(len) = len {
+            *len += 1;
+            if let Some(child) = self.paths[parent].items {
+                return child;
+            }
+            let child = self.new_path();
+            self.paths[parent].items = Some(child);
+            return child;
+        }
+        let key = self.key.take().unwrap_or_default();
+        if let Some(&child) = self.paths[parent].members.get(&key) {
+            return child;
+        }
+        let child = self.new_path();
+        let stats = &mut self.paths[parent];
+        stats.members.insert(key.clone(), child);
+        stats.properties.push((key, child));
+        child
+    }
+
+    fn new_path(&mut self) -> usize {
+        self.paths.push(PathStats {
+            strings: Some(vec![]),
+            ..PathStats::default()
+        });
+        self.paths.len() - 1
+    }
+
+    fn schema_at(&self, path: usize) -> JsonNode {
+        let stats = &self.paths[path];
+        let kinds = stats.kinds();
+        let mut schema = vec![];
+        let mut add = |keyword: &str, value: JsonNode| schema.push((keyword.to_string(), value));
+        let mut names = kinds
+            .iter()
+            .map(|kind| JsonNode::String(kind.name().to_string()))
+            .collect::<Vec<_>>();
+        match names.len() {
+            0 => return JsonNode::Bool(true),
+            1 => add("type", names.pop().unwrap()),
+            _ => add("type", JsonNode::Array(names)),
+        }
+        if !stats.properties.is_empty() {
+            let properties = stats.properties.iter();
+            let schemas = properties.map(|(name, child)| (name.clone(), self.schema_at(*child)));
+            add("properties", JsonNode::Object(schemas.collect()));
+            let objects = stats.seen(Kind::Object);
+            let required = stats
+                .properties
+                .iter()
+                .filter(|(_, child)| self.paths[*child].count() >= objects)
+                .map(|(name, _)| JsonNode::String(name.clone()))
+                .collect::<Vec<_>>();
+            if !required.is_empty() {
+                add("required", JsonNode::Array(required));
+            }
+        }
+        if let Some(items) = stats.items.filter(|&items| self.paths[items].count() > 0) {
+            add("items", self.schema_at(items));
+        }
+        if let Some((min, max)) = stats.sizes {
+            add("minItems", number(min));
+            add("maxItems", number(max));
+        }
+        if let Some(strings) = self.enum_candidates(stats) {
+            let mut values = strings
+                .iter()
+                .map(|text| JsonNode::String(text.clone()))
+                .collect::<Vec<_>>();
+            if stats.seen(Kind::Null) > 0 {
+                values.push(JsonNode::Null);
+            }
+            add("enum", JsonNode::Array(values));
+        }
+        if let Some((min, max)) = stats.lengths {
+            add("minLength", number(min));
+            add("maxLength", number(max));
+        }
+        if let (Some((_, min)), Some((_, max))) = (&stats.minimum, &stats.maximum) {
+            add("minimum", JsonNode::Number(min.clone()));
+            add("maximum", JsonNode::Number(max.clone()));
+        }
+        JsonNode::Object(schema)
+    }
+
+    // The distinct strings of a path holding nothing but strings and nulls,
+    // each seen more than once on average.
+    fn enum_candidates<'a>(&self, stats: &'a PathStats) -> Option<&'a Vec<String>> {
+        let strings = stats.strings.as_ref()?;
+        let only_strings = stats
+            .kinds()
+            .iter()
+            .all(|kind| matches!(kind, Kind::String | Kind::Null));
+        let repeated = stats.seen(Kind::String) > strings.len();
+        (only_strings && repeated && !strings.is_empty()).then_some(strings)
+    }
+
+    // Writes the line of `path`, then those of its members and items.
+    // `parent` is the number of objects a member is in.
+    fn summarize(&self, path: usize, pointer: &str, parent: Option<usize>, out: &mut String) {
+        let stats = &self.paths[path];
+        let kinds = stats.kinds();
+        let names = kinds.iter().map(|kind| kind.name()).collect::<Vec<_>>();
+        let count = stats.count();
+        write!(out, "{}: {}", pointer, names.join(" | ")).unwrap();
+        match parent {
+            Some(objects) => {
+                let percent = 100.0 * count as f64 / objects.max(1) as f64;
+                write!(
+                    out,
+                    ", in {} of {} objects ({:.1}%)",
+                    count, objects, percent
+                )
+                .unwrap();
+            }
+            None => write!(out, ", {} values", count).unwrap(),
+        }
+        if let (Some((_, min)), Some((_, max))) = (&stats.minimum, &stats.maximum) {
+            write!(out, ", range {}..{}", min, max).unwrap();
+        }
+        if let Some((min, max)) = stats.lengths {
+            write!(out, ", length {}..{}", min, max).unwrap();
+        }
+        if let Some((min, max)) = stats.sizes {
+            write!(out, ", items {}..{}", min, max).unwrap();
+        }
+        if let Some(strings) = self.enum_candidates(stats) {
+            let strings = strings.iter().map(|text| format!("{:?}", text));
+            write!(out, ", one of {}", strings.collect::<Vec<_>>().join(" | ")).unwrap();
+        }
+        out.push('\n');
+        let pointer = pointer.strip_prefix("(root)").unwrap_or(pointer);
+        for (name, child) in &stats.properties {
+            let name = name.replace('~', "~0").replace('/', "~1");
+            let child_pointer = format!("{}/{}", pointer, name);
+            self.summarize(*child, &child_pointer, Some(stats.seen(Kind::Object)), out);
+        }
+        if let Some(items) = stats.items.filter(|&items| self.paths[items].count() > 0) {
+            self.summarize(items, &format!("{}/*", pointer), None, out);
+        }
+    }
+}
+
+impl Default for SchemaInferrer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn widen(range: Option<(usize, usize)>, n: usize) -> Option<(usize, usize)> {
+    let (min, max) = range.unwrap_or((n, n));
+    Some((min.min(n), max.max(n)))
+}
+
+fn number(n: usize) -> JsonNode {
+    JsonNode::Number(n.to_string())
+}
+
+#[cfg(test)]
+mod infer_tests {
+    use super::*;
+    use crate::json_stream_reader::JsonStreamReader;
+    use crate::json_stream_writer::JsonStreamWriter;
+    use crate::schema::Schema;
+
+    const RECORDS: [&str; 4] = [
+        r#"{"id": 1, "role": "admin", "tags": ["a", "bc"], "score": null}"#,
+        r#"{"id": 2, "role": "user", "tags": [], "score": 0.5}"#,
+        r#"{"id": 3, "role": "user", "tags": ["d"], "email": "x@y.z"}"#,
+        r#"{"id": 40, "role": "admin", "tags": ["e", "f", "g"], "score": 2}"#,
+    ];
+
+    fn infer(inferrer: SchemaInferrer) -> SchemaInferrer {
+        let mut inferrer = inferrer;
+        for record in RECORDS {
+            let mut reader = JsonStreamReader::new();
+            for token in reader.read(record.as_bytes()).unwrap() {
+                inferrer.push(&token);
+            }
+        }
+        inferrer
+    }
+
+    fn to_string(node: &JsonNode) -> String {
+        let mut writer = JsonStreamWriter::new(vec![]);
+        writer.node(node).unwrap();
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn should_infer_schema_per_path() {
+        let schema = infer(SchemaInferrer::new()).schema();
+        let expected = concat!(
+            r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","type":"object","#,
+            r#""properties":{"id":{"type":"integer","minimum":1,"maximum":40},"#,
+            r#""role":{"type":"string","enum":["admin","user"],"minLength":4,"maxLength":5},"#,
+            r#""tags":{"type":"array","items":{"type":"string","minLength":1,"maxLength":2},"#,
+            r#""minItems":0,"maxItems":3},"#,
+            r#""score":{"type":["number","null"],"minimum":0.5,"maximum":2},"#,
+            r#""email":{"type":"string","minLength":5,"maxLength":5}},"#,
+            r#""required":["id","role","tags"]}"#,
+        );
+        assert_eq!(to_string(&schema), expected);
+        let schema = Schema::from_node(&schema).unwrap();
+        for record in RECORDS {
+            assert_eq!(schema.validate(record.as_bytes()).unwrap(), []);
+        }
+    }
+
+    #[test]
+    fn should_summarize_paths() {
+        let summary = infer(SchemaInferrer::new().with_enum_limit(1)).summary();
+        let expected = [
+            "(root): object, 4 values",
+            "/id: integer, in 4 of 4 objects (100.0%), range 1..40",
+            "/role: string, in 4 of 4 objects (100.0%), length 4..5",
+            "/tags: array, in 4 of 4 objects (100.0%), items 0..3",
+            "/tags/*: string, 6 values, length 1..2",
+            "/score: number | null, in 3 of 4 objects (75.0%), range 0.5..2",
+            "/email: string, in 1 of 4 objects (25.0%), length 5..5",
+        ];
+        assert_eq!(summary.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn should_infer_map_shaped_objects() {
+        let members = (0..200_000).map(|id| format!(r#""{}": {}"#, id, id));
+        let doc = format!("{{{}}}", members.collect::<Vec<_>>().join(","));
+        let mut inferrer = SchemaInferrer::new();
+        inferrer.read(doc.as_bytes()).unwrap();
+        assert_eq!(inferrer.paths[0].properties.len(), 200_000);
+        assert_eq!(inferrer.paths[0].properties[1234].0, "1234");
+    }
+
+    #[test]
+    fn should_measure_strings_read_in_fragments() {
+        let mut reader = JsonStreamReader::new().with_string_fragments(4);
+        let mut inferrer = SchemaInferrer::new();
+        for part in [&b"[[\"abcdefgh\", \""[..], br#"ab"], ["abc"#, br#"de"]]"#] {
+            for token in reader.read(part).unwrap() {
+                inferrer.push(&token);
+            }
+        }
+        let summary = inferrer.summary();
+        assert_eq!(
+            summary.lines().collect::<Vec<_>>(),
+            [
+                "(root): array, 1 values, items 2..2",
+                "/*: array, 2 values, items 1..2",
+                "/*/*: string, 3 values, length 2..8",
+            ]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! An in-memory JSON tree for small documents or selected subtrees.
+use crate::json_pointer::JsonPointer;
+use crate::json_value::JsonValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonNode {
+    Null,
+    Bool(bool),
+    String(String),
+    Number(String),
+    Array(Vec<JsonNode>),
+    // members are kept in document order
+    Object(Vec<(String, JsonNode)>),
+}
+
+impl JsonNode {
+    /// Returns the value of the first member named `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&JsonNode> {
+        match self {
+            JsonNode::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the node referenced by `pointer`.
+    pub fn pointer(&self, pointer: &JsonPointer) -> Option<&JsonNode> {
+        pointer
+            .segments()
+            .iter()
+            .try_fold(self, |node, segment| match node {
+                JsonNode::Object(_) => node.get(segment),
+                JsonNode::Array(items) => items.get(segment.parse::<usize>().ok()?),
+                _ => None,
+            })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonNode::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonNode::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonNode::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonNode>> {
+        match self {
+            JsonNode::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, JsonNode)>> {
+        match self {
+            JsonNode::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == JsonNode::Null
+    }
+}
+
+impl From<JsonValue> for JsonNode {
+    fn from(value: JsonValue) -> Self {
+        match value {
+            JsonValue::Null => JsonNode::Null,
+            JsonValue::Bool(b) => JsonNode::Bool(b),
+            JsonValue::String(s) => JsonNode::String(s.into_string()),
+            JsonValue::Number(n) => JsonNode::Number(n.into_string()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod de {
+    use std::fmt;
+
+    use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+
+    use super::JsonNode;
+
+    // Members are kept as they come, duplicate keys included.
+    impl<'de> Deserialize<'de> for JsonNode {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(NodeVisitor)
+        }
+    }
+
+    struct NodeVisitor;
+
+    impl<'de> Visitor<'de> for NodeVisitor {
+        type Value = JsonNode;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("any JSON value")
+        }
+
+        fn visit_unit<E>(self) -> Result<JsonNode, E> {
+            Ok(JsonNode::Null)
+        }
+
+        fn visit_none<E>(self) -> Result<JsonNode, E> {
+            Ok(JsonNode::Null)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonNode, D::Error> {
+            JsonNode::deserialize(deserializer)
+        }
+
+        fn visit_bool<E>(self, b: bool) -> Result<JsonNode, E> {
+            Ok(JsonNode::Bool(b))
+        }
+
+        fn visit_i64<E>(self, n: i64) -> Result<JsonNode, E> {
+            Ok(JsonNode::Number(n.to_string()))
+        }
+
+        fn visit_u64<E>(self, n: u64) -> Result<JsonNode, E> {
+            Ok(JsonNode::Number(n.to_string()))
+        }
+
+        fn visit_f64<E>(self, n: f64) -> Result<JsonNode, E> {
+            Ok(JsonNode::Number(n.to_string()))
+        }
+
+        fn visit_str<E>(self, s: &str) -> Result<JsonNode, E> {
+            Ok(JsonNode::String(s.to_string()))
+        }
+
+        fn visit_string<E>(self, s: String) -> Result<JsonNode, E> {
+            Ok(JsonNode::String(s))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonNode, A::Error> {
+            let mut items = vec![];
+            while let Some(item) = seq.next_element()? {
+                items.push(item);
+            }
+            Ok(JsonNode::Array(items))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonNode, A::Error> {
+            let mut members = vec![];
+            while let Some(member) = map.next_entry()? {
+                members.push(member);
+            }
+            Ok(JsonNode::Object(members))
+        }
+    }
+}
+
+#[cfg(test)]
+mod json_node_tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_pointer() {
+        let node = JsonNode::Object(vec![(
+            "rows".to_string(),
+            JsonNode::Array(vec![JsonNode::Null, JsonNode::Bool(true)]),
+        )]);
+        let pointer = JsonPointer::parse("/rows/1").unwrap();
+        assert_eq!(node.pointer(&pointer), Some(&JsonNode::Bool(true)));
+        let pointer = JsonPointer::parse("/rows/2").unwrap();
+        assert_eq!(node.pointer(&pointer), None);
+    }
+}
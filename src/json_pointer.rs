@@ -0,0 +1,252 @@
+//! JSON Pointer (RFC 6901) paths such as `/data/rows/0`.
+use std::fmt;
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_token::JsonToken;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct JsonPointer {
+    segments: Vec<String>,
+}
+
+impl JsonPointer {
+    /// The pointer to the whole document.
+    pub fn root() -> Self {
+        JsonPointer::default()
+    }
+
+    /// Parses a pointer, `""` being the whole document.
+    pub fn parse(pointer: &str) -> Result<Self> {
+        if pointer.is_empty() {
+            return Ok(JsonPointer::root());
+        }
+        if !pointer.starts_with('/') {
+            return Err(Error {
+                code: ErrorCode::InvalidPointer,
+                column: 0,
+            });
+        }
+        let mut segments = vec![];
+        for segment in pointer[1..].split('/') {
+            segments.push(unescape_segment(segment).ok_or(Error {
+                code: ErrorCode::InvalidPointer,
+                column: 0,
+            })?);
+        }
+        Ok(JsonPointer { segments })
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn push(&mut self, segment: impl Into<String>) {
+        self.segments.push(segment.into());
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        self.segments.pop()
+    }
+}
+
+/// A pointer whose `*` segments match any key or index, such as
+/// `/attachments/*/data`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PathPattern {
+    pointer: JsonPointer,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        Ok(PathPattern {
+            pointer: JsonPointer::parse(pattern)?,
+        })
+    }
+
+    pub fn matches(&self, segments: &[String]) -> bool {
+        let pattern = self.pointer.segments();
+        pattern.len() == segments.len()
+            && pattern
+                .iter()
+                .zip(segments)
+                .all(|(expected, segment)| expected == "*" || expected == segment)
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pointer.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Level {
+    object: bool,
+    // elements seen so far
+    count: usize,
+}
+
+/// Follows where tokens are in the document.
+///
+/// After each token, `segments` is the path of the last value that started,
+/// which is the container itself right after `ObjBeg` or `ArrBeg`, and the
+/// value closed right after `ObjEnd` or `ArrEnd`. Known keys have to be named
+/// before they are pushed.
+#[derive(Debug, Clone, Default)]
+pub struct PathTracker {
+    levels: Vec<Level>,
+    segments: Vec<String>,
+    // the key of the next member
+    key: Option<String>,
+}
+
+impl PathTracker {
+    pub fn new() -> Self {
+        PathTracker::default()
+    }
+
+    /// Takes the next token into account. Returns whether it starts a value.
+    pub fn push(&mut self, token: &JsonToken) -> bool {
+        match token {
+            JsonToken::ObjBeg | JsonToken::ArrBeg => {
+                self.start_value();
+                self.levels.push(Level {
+                    object: *token == JsonToken::ObjBeg,
+                    count: 0,
+                });
+                true
+            }
+            JsonToken::ObjEnd | JsonToken::ArrEnd => {
+                self.levels.pop();
+                self.segments.truncate(self.levels.len());
+                false
+            }
+            JsonToken::Key(key) => {
+                self.key = Some(key.to_string());
+                false
+            }
+            JsonToken::Val(_) | JsonToken::StrBegin | JsonToken::Raw(_) => {
+                self.start_value();
+                true
+            }
+            JsonToken::KnownKey(_) | JsonToken::StrChunk(_) | JsonToken::StrEnd => false,
+        }
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    pub fn pointer(&self) -> JsonPointer {
+        JsonPointer {
+            segments: self.segments.clone(),
+        }
+    }
+
+    /// Number of containers the last value is in.
+    pub fn depth(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn start_value(&mut self) {
+        let Some(level) = self.levels.last_mut() else {
+            self.segments.clear();
+            return;
+        };
+        let segment = if level.object {
+            self.key.take().unwrap_or_default()
+        } else {
+            level.count.to_string()
+        };
+        level.count += 1;
+        self.segments.truncate(self.levels.len() - 1);
+        self.segments.push(segment);
+    }
+}
+
+fn unescape_segment(segment: &str) -> Option<String> {
+    let mut out = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '~' => match chars.next() {
+                Some('0') => out.push('~'),
+                Some('1') => out.push('/'),
+                _ => return None,
+            },
+            ch => out.push(ch),
+        }
+    }
+    Some(out)
+}
+
+impl fmt::Display for JsonPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            write!(f, "/{}", segment.replace('~', "~0").replace('/', "~1"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod json_pointer_tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_pointer() {
+        let pointer = JsonPointer::parse("/data/rows/0").unwrap();
+        assert_eq!(pointer.segments(), ["data", "rows", "0"]);
+        assert!(JsonPointer::parse("").unwrap().is_root());
+    }
+
+    #[test]
+    fn should_unescape_and_escape_segments() {
+        let pointer = JsonPointer::parse("/a~1b/m~0n").unwrap();
+        assert_eq!(pointer.segments(), ["a/b", "m~n"]);
+        assert_eq!(pointer.to_string(), "/a~1b/m~0n");
+    }
+
+    #[test]
+    fn should_match_wildcards() {
+        let pattern = PathPattern::parse("/attachments/*/data").unwrap();
+        let path = |p: &str| JsonPointer::parse(p).unwrap().segments().to_vec();
+        assert!(pattern.matches(&path("/attachments/0/data")));
+        assert!(pattern.matches(&path("/attachments/x/data")));
+        assert!(!pattern.matches(&path("/attachments/0")));
+        assert!(!pattern.matches(&path("/attachments/0/data/1")));
+    }
+
+    #[test]
+    fn should_track_path_of_values() {
+        let doc = br#"{"a": [1, {"b": null}], "c": "x"}"#;
+        let tokens = crate::json_stream_reader::JsonStreamReader::new()
+            .read(doc)
+            .unwrap();
+        let mut tracker = PathTracker::new();
+        let mut paths = vec![];
+        for token in &tokens {
+            if tracker.push(token) {
+                paths.push(tracker.pointer().to_string());
+            }
+        }
+        assert_eq!(paths, ["", "/a", "/a/0", "/a/1", "/a/1/b", "/c"]);
+        assert!(tracker.segments().is_empty());
+    }
+
+    #[test]
+    fn should_reject_invalid_pointer() {
+        assert_eq!(
+            JsonPointer::parse("data"),
+            Err(Error {
+                code: ErrorCode::InvalidPointer,
+                column: 0
+            })
+        );
+        assert!(JsonPointer::parse("/a~2").is_err());
+    }
+}
@@ -1,113 +1,1451 @@
-use std::str;
+use std::{mem, str};
 
-use crate::arr::*;
-use crate::error::{Error, Result};
-use crate::json_token::JsonToken;
-use crate::json_value::JsonValue;
-use crate::obj::*;
-use crate::other::*;
-use crate::token::*;
-use crate::val::*;
+use memchr::{memchr2, memchr_iter};
 
+use crate::checkpoint::Checkpoint;
+use crate::constants::*;
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_token::{JsonToken, Span, TokenRef};
+use crate::json_value::{JsonValue, ValueRef};
+use crate::keys::{KeyInterner, KnownKeys};
+use crate::token::{Lexeme, Nesting, RawScan, State, Token};
+use crate::token_buffer::TokenBuffer;
+use crate::utils::{unescape_into, unescape_prefix};
+
+/// A push parser driven by a table of byte classes.
+///
+/// In between tokens every byte is looked up in `ACTIONS` by the current
+/// state and its class. Strings are scanned in bulk up to the next quote or
+/// backslash, numbers and literals byte by byte. A token split between two
+/// buffers is collected in a scratch buffer.
 #[derive(Debug)]
 pub struct JsonStreamReader {
-    // internal state needed for buffering
-    state: Vec<Token>,
+    // open containers
+    stack: Nesting,
+    state: State,
+    // token being read when the previous buffer ended
+    lexeme: Lexeme,
+    // raw bytes of a token split between buffers
+    scratch: Vec<u8>,
+    // text of a string with escapes
+    decoded: Vec<u8>,
+    known_keys: Option<KnownKeys>,
+    key_interner: Option<KeyInterner>,
+    // string values longer than this are emitted in fragments
+    fragments: Option<usize>,
+    // the next value is captured as is
+    capture: bool,
+    // absolute position of the buffer being read
+    offset: usize,
+    spans: Option<Box<Spans>>,
 }
 
-type Res = Result<Option<JsonToken>>;
+// What is kept to locate tokens, see `with_spans`.
+#[derive(Debug, Default)]
+struct Spans {
+    // starts of the keys and values begun but not located yet, in order
+    starts: Vec<usize>,
+    // line and column of a start in a previous buffer
+    open: Option<(usize, usize)>,
+    // newlines are counted up to this position
+    counted: usize,
+    line: usize,
+    // position of the first byte of `line`
+    line_start: usize,
+}
 
 impl JsonStreamReader {
     pub fn new() -> Self {
-        JsonStreamReader { state: vec![] }
+        JsonStreamReader {
+            stack: Nesting::default(),
+            state: State::Start,
+            lexeme: Lexeme::None,
+            scratch: vec![],
+            decoded: vec![],
+            known_keys: None,
+            key_interner: None,
+            fragments: None,
+            capture: false,
+            offset: 0,
+            spans: None,
+        }
+    }
+
+    /// Emits string values longer than `threshold` bytes, as written in the
+    /// document, as a `StrBegin` token, `StrChunk`s of whatever text each
+    /// buffer completes and a `StrEnd` token. Escapes and UTF-8 sequences
+    /// split between buffers are carried over to the next chunk, so memory
+    /// stays bounded however long the string is. Keys are never split.
+    pub fn with_string_fragments(mut self, threshold: usize) -> Self {
+        self.fragments = Some(threshold);
+        self
+    }
+
+    /// Returns `JsonToken::KnownKey` for the given keys instead of
+    /// allocating them.
+    pub fn with_known_keys(mut self, keys: KnownKeys) -> Self {
+        self.known_keys = Some(keys);
+        self
+    }
+
+    /// Returns keys as shared copies taken from `interner`, so that a key
+    /// repeated from one record to the next is allocated once. Known keys
+    /// are looked up first. `read_into` does not allocate keys and ignores
+    /// the interner.
+    pub fn with_key_interner(mut self, interner: KeyInterner) -> Self {
+        self.key_interner = Some(interner);
+        self
+    }
+
+    /// Records where keys and values start, so that `read_spanned` can
+    /// attach a `Span` to every token.
+    pub fn with_spans(mut self) -> Self {
+        self.spans = Some(Box::new(Spans {
+            line: 1,
+            ..Spans::default()
+        }));
+        self
+    }
+
+    pub fn known_keys(&self) -> Option<&KnownKeys> {
+        self.known_keys.as_ref()
+    }
+
+    pub fn key_interner(&self) -> Option<&KeyInterner> {
+        self.key_interner.as_ref()
+    }
+
+    /// Reads the next value to start as a single `JsonToken::Raw` holding
+    /// its text exactly as in the document, whitespace included. Inside the
+    /// value only strings and the nesting of containers are followed, so
+    /// it is not validated any further. Has no effect if a container ends
+    /// first.
+    pub fn capture_next_value(&mut self) {
+        self.capture = true;
     }
 
     // Clears the internal state of the reader.
     pub fn clear(&mut self) -> &Self {
-        self.state.clear();
+        self.stack.clear();
+        self.state = State::Start;
+        self.lexeme = Lexeme::None;
+        self.capture = false;
+        self.offset = 0;
+        if self.spans.is_some() {
+            self.spans = Some(Box::new(Spans {
+                line: 1,
+                ..Spans::default()
+            }));
+        }
+        self.reset_scratch();
         self
     }
 
+    /// Takes a snapshot of the reader at the end of the last buffer read.
+    /// A reader made by `resume` out of it continues with the input that
+    /// follows, the tokens cut short by the end of the buffer included.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            offset: self.offset,
+            stack: self.stack.clone(),
+            state: self.state,
+            lexeme: self.lexeme,
+            scratch: self.scratch.clone(),
+            capture: self.capture,
+        }
+    }
+
+    /// Creates a reader that continues where a checkpoint was taken, to be
+    /// given the input from `checkpoint.offset()` on. Other options are set
+    /// as for a new reader.
+    pub fn resume(checkpoint: &Checkpoint) -> Self {
+        let mut reader = JsonStreamReader::new();
+        reader.restart(
+            checkpoint.stack.clone(),
+            checkpoint.state,
+            checkpoint.offset,
+        );
+        reader.lexeme = checkpoint.lexeme;
+        reader.scratch.extend_from_slice(&checkpoint.scratch);
+        reader.capture = checkpoint.capture;
+        reader
+    }
+
+    // Continues at `offset`, a point in between tokens, the bytes read
+    // since are dropped. Spans are not supported.
+    pub(crate) fn restart(&mut self, stack: Nesting, state: State, offset: usize) {
+        self.stack = stack;
+        self.state = state;
+        self.lexeme = Lexeme::None;
+        self.capture = false;
+        self.offset = offset;
+        self.reset_scratch();
+    }
+
+    /// Returns the number of open containers.
+    pub fn depth(&self) -> usize {
+        self.stack.depth()
+    }
+
     /// Reads buffer from a given start index to the end.
     pub fn read(&mut self, buf: &[u8]) -> Result<Vec<JsonToken>> {
-        let mut json_tokens = vec![];
-        let tokens = &mut self.state;
+        let mut json_tokens = Vec::with_capacity((buf.len() / 8).min(RESERVED_TOKENS));
+        self.read_to(buf, |token, _| json_tokens.push(token))?;
+        Ok(json_tokens)
+    }
+
+    /// Same as `read`, with where each token is in the whole document. Keys
+    /// and strings span from their opening to their closing quote, numbers
+    /// and literals from their first to their last byte. `StrChunk` tokens
+    /// are located at the byte that completed them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was not created `with_spans`.
+    pub fn read_spanned(&mut self, buf: &[u8]) -> Result<Vec<(JsonToken, Span)>> {
+        assert!(self.spans.is_some(), "the reader does not record spans");
+        let base = self.offset;
+        let mut tokens = vec![];
+        self.read_owned(buf, |token, i| tokens.push((token, base + i)))?;
+        let spans = self.spans.as_mut().unwrap();
+        let mut starts = mem::take(&mut spans.starts).into_iter();
+        let mut located = Vec::with_capacity(tokens.len());
+        for (token, at) in tokens {
+            let (start, end) = match &token {
+                JsonToken::Key(_)
+                | JsonToken::KnownKey(_)
+                | JsonToken::Val(_)
+                | JsonToken::StrBegin
+                | JsonToken::Raw(_) => {
+                    // numbers are complete at their delimiter
+                    let delimited = match &token {
+                        JsonToken::Val(JsonValue::Number(_)) => true,
+                        JsonToken::Raw(raw) => !matches!(raw[0], b'"' | b'{' | b'['),
+                        _ => false,
+                    };
+                    (starts.next().unwrap(), at + usize::from(!delimited))
+                }
+                _ => (at, at + 1),
+            };
+            let (start_line, start_col) = spans.locate(start, buf, base);
+            let span = Span {
+                start_byte: start,
+                end_byte: end,
+                start_line,
+                start_col,
+            };
+            located.push((token, span));
+        }
+        // the start of a token still being read
+        spans.starts.extend(starts);
+        if let Some(&start) = spans.starts.first() {
+            if start >= base {
+                spans.open = Some(spans.locate(start, buf, base));
+            }
+        }
+        spans.locate(base + buf.len(), buf, base);
+        Ok(located)
+    }
+
+    /// Reads buffer into `out`, replacing the tokens it held.
+    ///
+    /// Token slots and the text of keys and values are kept in `out` from
+    /// one call to the next: once `out` has grown to fit the largest chunk,
+    /// reading allocates nothing. On error `out` holds the tokens read
+    /// before it.
+    pub fn read_into(&mut self, buf: &[u8], out: &mut TokenBuffer) -> Result<()> {
+        out.clear();
+        self.read_refs(buf, |token, _| out.push(token))?;
+        self.skip_spans(buf);
+        Ok(())
+    }
+
+    /// Reads buffer and passes every token to `emit` together with the index
+    /// of the byte that completed it.
+    pub(crate) fn read_to<F>(&mut self, buf: &[u8], emit: F) -> Result<()>
+    where
+        F: FnMut(JsonToken, usize),
+    {
+        self.read_owned(buf, emit)?;
+        self.skip_spans(buf);
+        Ok(())
+    }
+
+    // Same as `read_refs` with owned tokens, keys taken from the interner.
+    fn read_owned<F>(&mut self, buf: &[u8], mut emit: F) -> Result<()>
+    where
+        F: FnMut(JsonToken, usize),
+    {
+        let Some(mut interner) = self.key_interner.take() else {
+            return self.read_refs(buf, |token, i| emit(token.to_token(), i));
+        };
+        let result = self.read_refs(buf, |token, i| match token {
+            TokenRef::Key(key) => emit(JsonToken::Key(interner.shared(key).into()), i),
+            token => emit(token.to_token(), i),
+        });
+        self.key_interner = Some(interner);
+        result
+    }
+
+    // Keeps spans right when tokens are read w/o them.
+    fn skip_spans(&mut self, buf: &[u8]) {
+        let Some(spans) = self.spans.as_mut() else {
+            return;
+        };
+        let open = matches!(
+            self.lexeme,
+            Lexeme::Key { .. }
+                | Lexeme::Str { .. }
+                | Lexeme::Number { .. }
+                | Lexeme::Literal { .. }
+                | Lexeme::Raw(_)
+        );
+        let base = self.offset - buf.len();
+        let start = spans.starts.last().copied().filter(|_| open);
+        spans.starts.clear();
+        if let Some(start) = start {
+            spans.starts.push(start);
+            if start >= base {
+                spans.open = Some(spans.locate(start, buf, base));
+            }
+        }
+        spans.locate(self.offset, buf, base);
+    }
+
+    // Records that a key or a value starts at `i`.
+    fn mark_start(&mut self, i: usize) {
+        if let Some(spans) = self.spans.as_mut() {
+            spans.starts.push(self.offset + i);
+        }
+    }
+
+    // Same as `read_to`, the text of tokens is only valid during the call.
+    fn read_refs<F>(&mut self, buf: &[u8], mut emit: F) -> Result<()>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
         let size = buf.len();
-        let mut i = 0;
-        let mut res = Ok(None);
-        let mut error = None;
-        while i < size && error.is_none() {
-            let token = tokens.last();
-            res = match token {
-                None => handle_none(&buf, i, tokens),
-                Some(Token::Obj) => handle_obj(buf, i, tokens),
-                Some(Token::Key(_)) => handle_key(&buf, i, tokens),
-                Some(Token::AfterKey) => handle_after_key(&buf, i, tokens),
-                Some(Token::Colon) => handle_colon(&buf, i, tokens),
-                Some(Token::String(_)) => handle_string(&buf, i, tokens),
-                Some(Token::Number(_)) => match handle_number(&buf, i, tokens) {
-                    Ok(Some((number_token, None))) => Ok(Some(number_token)),
-                    Ok(Some((number_token, Some(extra_token)))) => {
-                        json_tokens.push(number_token);
-                        Ok(Some(extra_token))
+        let i = match mem::replace(&mut self.lexeme, Lexeme::None) {
+            Lexeme::None => 0,
+            Lexeme::Key { escaped } => {
+                self.read_string::<true, _>(buf, 0, true, escaped, &mut emit)?
+            }
+            Lexeme::Str { escaped } => {
+                self.read_string::<true, _>(buf, 0, false, escaped, &mut emit)?
+            }
+            Lexeme::Fragments { escaped } => self.read_fragments(buf, 0, escaped, &mut emit)?,
+            Lexeme::Number { dot } => self.read_number(buf, 0, dot, &mut emit)?,
+            Lexeme::Literal { word, pos } => self.read_literal(buf, 0, word, pos, &mut emit)?,
+            Lexeme::Raw(scan) => self.read_raw(buf, 0, 0, scan, &mut emit)?,
+        };
+        // options are only looked at between tokens when one is on
+        if self.capture || self.spans.is_some() || self.fragments.is_some() {
+            self.scan::<true, _>(buf, i, &mut emit)?;
+        } else {
+            self.scan::<false, _>(buf, i, &mut emit)?;
+        }
+        self.offset += size;
+        Ok(())
+    }
+
+    // Reads the tokens of `buf` from `i`, a token boundary. `OPTIONS` is
+    // unset when no capture, spans nor fragments are asked for.
+    fn scan<const OPTIONS: bool, F>(&mut self, buf: &[u8], mut i: usize, emit: &mut F) -> Result<()>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        let size = buf.len();
+        // kept in a local so that it can live in a register
+        let mut state = self.state;
+        while i < size {
+            let ch = buf[i];
+            match ACTIONS[state as usize][CLASS[ch as usize] as usize] {
+                Action::ObjBeg | Action::ArrBeg | Action::Str | Action::Num | Action::Lit
+                    if OPTIONS && self.capture =>
+                {
+                    self.capture = false;
+                    self.mark_start(i);
+                    state = if self.stack.is_empty() {
+                        State::End
+                    } else {
+                        State::AfterValue
+                    };
+                    let scan = RawScan {
+                        depth: usize::from(matches!(ch, b'{' | b'[')),
+                        string: ch == b'"',
+                        escaped: false,
+                    };
+                    i = self.read_raw(buf, i, i + 1, scan, emit)?;
+                }
+                Action::Skip => {
+                    i += 1;
+                    while i < size && CLASS[buf[i] as usize] == C_WS {
+                        i += 1;
+                    }
+                }
+                Action::ObjBeg => {
+                    self.stack.push(Token::Obj);
+                    state = State::ObjFirst;
+                    emit(TokenRef::ObjBeg, i);
+                    i += 1;
+                }
+                Action::ArrBeg => {
+                    self.stack.push(Token::Arr);
+                    state = State::ArrFirst;
+                    emit(TokenRef::ArrBeg, i);
+                    i += 1;
+                }
+                Action::ObjEnd => {
+                    state = self.close(Token::Obj, i)?;
+                    emit(TokenRef::ObjEnd, i);
+                    i += 1;
+                }
+                Action::ArrEnd => {
+                    state = self.close(Token::Arr, i)?;
+                    emit(TokenRef::ArrEnd, i);
+                    i += 1;
+                }
+                Action::Colon => {
+                    state = State::Value;
+                    i += 1;
+                }
+                Action::Comma => {
+                    state = match self.stack.last() {
+                        Some(Token::Obj) => State::ObjNext,
+                        _ => State::ArrNext,
+                    };
+                    i += 1;
+                }
+                Action::Key => {
+                    state = State::AfterKey;
+                    if OPTIONS {
+                        self.mark_start(i);
+                    }
+                    i = self.read_string::<OPTIONS, _>(buf, i + 1, true, false, emit)?;
+                }
+                Action::Str => {
+                    state = State::AfterValue;
+                    if OPTIONS {
+                        self.mark_start(i);
+                    }
+                    i = self.read_string::<OPTIONS, _>(buf, i + 1, false, false, emit)?;
+                }
+                Action::Num => {
+                    state = State::AfterValue;
+                    if OPTIONS {
+                        self.mark_start(i);
+                    }
+                    i = self.read_number(buf, i, false, emit)?;
+                }
+                Action::Lit => {
+                    if OPTIONS {
+                        self.mark_start(i);
+                    }
+                    let word: &'static [u8] = match ch {
+                        b'n' => b"null",
+                        b't' => b"true",
+                        _ => b"false",
+                    };
+                    state = State::AfterValue;
+                    i = self.read_literal(buf, i, word, 0, emit)?;
+                }
+                Action::Fail => return Err(error(unexpected_code(state), i)),
+            }
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    /// Reads a whole document, visiting only the `positions` found by
+    /// `StructuralIndex`. Whitespace never changes the state and the bytes
+    /// of a string lie between two consecutive positions, so the tokens and
+    /// errors are the same as reading byte by byte. Running out of input
+    /// inside a container is an `UnexpectedEof`.
+    pub(crate) fn read_indexed<F>(
+        &mut self,
+        buf: &[u8],
+        positions: &[u32],
+        mut emit: F,
+    ) -> Result<()>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        let eof = || error(ErrorCode::UnexpectedEof, buf.len());
+        let mut state = self.state;
+        let mut next = positions.iter().map(|&i| i as usize);
+        while let Some(i) = next.next() {
+            let ch = buf[i];
+            match ACTIONS[state as usize][CLASS[ch as usize] as usize] {
+                Action::Skip => {}
+                Action::ObjBeg => {
+                    self.stack.push(Token::Obj);
+                    state = State::ObjFirst;
+                    emit(TokenRef::ObjBeg, i);
+                }
+                Action::ArrBeg => {
+                    self.stack.push(Token::Arr);
+                    state = State::ArrFirst;
+                    emit(TokenRef::ArrBeg, i);
+                }
+                Action::ObjEnd => {
+                    state = self.close(Token::Obj, i)?;
+                    emit(TokenRef::ObjEnd, i);
+                }
+                Action::ArrEnd => {
+                    state = self.close(Token::Arr, i)?;
+                    emit(TokenRef::ArrEnd, i);
+                }
+                Action::Colon => state = State::Value,
+                Action::Comma => {
+                    state = match self.stack.last() {
+                        Some(Token::Obj) => State::ObjNext,
+                        _ => State::ArrNext,
+                    };
+                }
+                action @ (Action::Key | Action::Str) => {
+                    // the closing quote is the next position
+                    let end = next.next();
+                    if action == Action::Key {
+                        let last = end.unwrap_or(buf.len() - 1);
+                        if last - i > KEY_MAX_LEN + 1 {
+                            return Err(error(ErrorCode::TooLongKey, i + KEY_MAX_LEN + 2));
+                        }
+                    }
+                    let j = end.ok_or_else(eof)?;
+                    let text = decode(&buf[i + 1..j], &mut self.decoded)
+                        .ok_or_else(|| error(ErrorCode::InvalidEscape, j))?;
+                    if action == Action::Key {
+                        state = State::AfterKey;
+                        emit(key_token(&self.known_keys, text), j);
+                    } else {
+                        state = State::AfterValue;
+                        emit(TokenRef::Val(ValueRef::String(text)), j);
+                    }
+                }
+                Action::Num => {
+                    state = State::AfterValue;
+                    self.read_number(buf, i, false, &mut emit)?;
+                    if self.lexeme != Lexeme::None {
+                        return Err(eof());
+                    }
+                }
+                Action::Lit => {
+                    let word: &'static [u8] = match ch {
+                        b'n' => b"null",
+                        b't' => b"true",
+                        _ => b"false",
+                    };
+                    state = State::AfterValue;
+                    let end = self.read_literal(buf, i, word, 0, &mut emit)?;
+                    if self.lexeme != Lexeme::None {
+                        return Err(eof());
+                    }
+                    // the rest of a word such as `nullx` has no position
+                    if end < buf.len()
+                        && matches!(CLASS[buf[end] as usize], C_OTHER | C_NUM | C_LIT)
+                    {
+                        return Err(error(unexpected_code(state), end));
+                    }
+                }
+                Action::Fail => return Err(error(unexpected_code(state), i)),
+            }
+        }
+        self.state = state;
+        if !self.stack.is_empty() {
+            return Err(eof());
+        }
+        Ok(())
+    }
+
+    // Pops the container closed at `i`. Returns the state after it.
+    fn close(&mut self, expected: Token, i: usize) -> Result<State> {
+        self.capture = false;
+        match self.stack.pop() {
+            Some(token) if token == expected => Ok(if self.stack.is_empty() {
+                State::End
+            } else {
+                State::AfterValue
+            }),
+            _ if expected == Token::Obj => Err(error(ErrorCode::ExpectedListCommaOrEnd, i)),
+            _ => Err(error(ErrorCode::ExpectedObjectCommaOrEnd, i)),
+        }
+    }
+
+    // Reads a key or a string value from `i`, right after the opening quote
+    // or wherever the previous buffer stopped. Returns the index of the next
+    // byte to read. `OPTIONS` is the same as for `scan`.
+    fn read_string<const OPTIONS: bool, F>(
+        &mut self,
+        buf: &[u8],
+        mut i: usize,
+        key: bool,
+        escaped: bool,
+        emit: &mut F,
+    ) -> Result<usize>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        if escaped {
+            if i == buf.len() {
+                self.lexeme = string_lexeme(key, true);
+                return Ok(i);
+            }
+            if key {
+                self.check_key_len(i, i)?;
+            }
+            self.scratch.push(buf[i]);
+            i += 1;
+        }
+        loop {
+            let j = match find_quote_or_escape(&buf[i..]) {
+                Some(n) => i + n,
+                None => {
+                    if key && i < buf.len() {
+                        self.check_key_len(i, buf.len() - 1)?;
                     }
-                    Ok(None) => Ok(None),
-                    Err(err) => Err(err),
-                },
-                Some(Token::Null(_)) => handle_null(&buf, i, tokens),
-                Some(Token::True(_)) => handle_true(&buf, i, tokens),
-                Some(Token::False(_)) => handle_false(&buf, i, tokens),
-                Some(Token::Arr) => handle_arr(&buf, i, tokens),
-                Some(Token::Comma) => handle_comma(&buf, i, tokens),
-                Some(Token::None) => handle_nil_token(&buf, i, tokens),
+                    if OPTIONS && self.is_long(key, self.scratch.len() + buf.len() - i) {
+                        return self.begin_fragments(buf, i, emit);
+                    }
+                    self.scratch.extend_from_slice(&buf[i..]);
+                    self.lexeme = string_lexeme(key, false);
+                    return Ok(buf.len());
+                }
+            };
+            if key {
+                self.check_key_len(i, j)?;
+            }
+            if OPTIONS && self.is_long(key, self.scratch.len() + j + 1 - i) {
+                return self.begin_fragments(buf, i, emit);
+            }
+            if buf[j] == b'\\' {
+                self.scratch.extend_from_slice(&buf[i..=j]);
+                if j + 1 == buf.len() {
+                    self.lexeme = string_lexeme(key, true);
+                    return Ok(buf.len());
+                }
+                if key {
+                    self.check_key_len(j + 1, j + 1)?;
+                }
+                self.scratch.push(buf[j + 1]);
+                i = j + 2;
+                continue;
+            }
+            // the common case of a string within one buffer and no escapes
+            // does not go through the scratch buffer
+            let spilled = !self.scratch.is_empty();
+            let text = if spilled {
+                self.scratch.extend_from_slice(&buf[i..j]);
+                decode(&self.scratch, &mut self.decoded)
+            } else {
+                str::from_utf8(&buf[i..j]).ok()
             };
-            match res {
-                Ok(Some(new_token)) => json_tokens.push(new_token),
-                Ok(None) => {}
-                Err(err) => {
-                    error = Some(err);
+            let text = text.ok_or_else(|| error(ErrorCode::InvalidEscape, j))?;
+            if key {
+                emit(key_token(&self.known_keys, text), j);
+            } else {
+                emit(TokenRef::Val(ValueRef::String(text)), j);
+            }
+            // which then needs no reset
+            if spilled {
+                self.reset_scratch();
+            }
+            return Ok(j + 1);
+        }
+    }
+
+    fn is_long(&self, key: bool, len: usize) -> bool {
+        !key && self.fragments.is_some_and(|threshold| len > threshold)
+    }
+
+    // Switches the string value being read to fragments, the bytes before
+    // `i` are in the scratch buffer.
+    fn begin_fragments<F>(&mut self, buf: &[u8], i: usize, emit: &mut F) -> Result<usize>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        emit(TokenRef::StrBegin, i.min(buf.len().saturating_sub(1)));
+        self.read_fragments(buf, i, false, emit)
+    }
+
+    // Reads a string value emitted in fragments from `i`, `escaped` is set
+    // when the byte at `i` is escaped.
+    fn read_fragments<F>(
+        &mut self,
+        buf: &[u8],
+        start: usize,
+        escaped: bool,
+        emit: &mut F,
+    ) -> Result<usize>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        let mut i = start;
+        if escaped {
+            if i == buf.len() {
+                self.lexeme = Lexeme::Fragments { escaped: true };
+                return Ok(i);
+            }
+            i += 1;
+        }
+        loop {
+            match find_quote_or_escape(&buf[i..]) {
+                Some(n) if buf[i + n] == b'"' => {
+                    let j = i + n;
+                    self.flush_fragment(&buf[start..j], true, j, emit)?;
+                    emit(TokenRef::StrEnd, j);
+                    self.reset_scratch();
+                    return Ok(j + 1);
+                }
+                Some(n) if i + n + 1 < buf.len() => i += n + 2,
+                found => {
+                    self.flush_fragment(&buf[start..], false, buf.len() - 1, emit)?;
+                    self.lexeme = Lexeme::Fragments {
+                        escaped: found.is_some(),
+                    };
+                    return Ok(buf.len());
+                }
+            }
+        }
+    }
+
+    // Emits the text of a long string decoded so far, `raw` being the bytes
+    // of the current buffer. An escape or a UTF-8 sequence cut short by the
+    // end of the buffer is left in the scratch buffer, unless this is the
+    // `last` fragment.
+    fn flush_fragment<F>(&mut self, raw: &[u8], last: bool, at: usize, emit: &mut F) -> Result<()>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        let invalid = || error(ErrorCode::InvalidEscape, at);
+        if self.scratch.is_empty() && !raw.contains(&b'\\') {
+            let text = utf8_prefix(raw, last).ok_or_else(invalid)?;
+            if !text.is_empty() {
+                emit(TokenRef::StrChunk(text), at);
+            }
+            self.scratch.extend_from_slice(&raw[text.len()..]);
+            return Ok(());
+        }
+        self.scratch.extend_from_slice(raw);
+        self.decoded.clear();
+        let decoded = unescape_prefix(&self.scratch, &mut self.decoded).ok_or_else(invalid)?;
+        let text = utf8_prefix(&self.decoded, last).ok_or_else(invalid)?;
+        // the bytes of a cut UTF-8 sequence are copied as is
+        let consumed = decoded - (self.decoded.len() - text.len());
+        if last && consumed < self.scratch.len() {
+            return Err(invalid());
+        }
+        if !text.is_empty() {
+            emit(TokenRef::StrChunk(text), at);
+        }
+        self.scratch.drain(..consumed);
+        Ok(())
+    }
+
+    // Empties the scratch buffers, giving back the memory taken by an
+    // unusually long token.
+    fn reset_scratch(&mut self) {
+        self.scratch.clear();
+        self.scratch.shrink_to(SCRATCH_CAPACITY);
+        self.decoded.clear();
+        self.decoded.shrink_to(SCRATCH_CAPACITY);
+    }
+
+    // Fails the way a byte by byte reader would: on the first byte read while
+    // the key is already longer than `KEY_MAX_LEN`. Bytes from `i` to `last`
+    // are about to be added to the key.
+    fn check_key_len(&self, i: usize, last: usize) -> Result<()> {
+        if self.scratch.len() + (last - i) > KEY_MAX_LEN {
+            let column = i + (KEY_MAX_LEN + 1).saturating_sub(self.scratch.len());
+            return Err(error(ErrorCode::TooLongKey, column));
+        }
+        Ok(())
+    }
+
+    // Reads a number up to its delimiter, which is left to the caller.
+    fn read_number<F>(
+        &mut self,
+        buf: &[u8],
+        start: usize,
+        mut dot: bool,
+        emit: &mut F,
+    ) -> Result<usize>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        let mut i = start;
+        while i < buf.len() && NUM_CHAR[buf[i] as usize] {
+            if buf[i] == b'.' {
+                if dot {
+                    return Err(error(ErrorCode::InvalidNumber, i));
+                }
+                dot = true;
+            }
+            i += 1;
+        }
+        if i == buf.len() {
+            self.scratch.extend_from_slice(&buf[start..]);
+            self.lexeme = Lexeme::Number { dot };
+            return Ok(i);
+        }
+        if !matches!(
+            CLASS[buf[i] as usize],
+            C_WS | C_COMMA | C_OBJ_END | C_ARR_END
+        ) {
+            return Err(error(ErrorCode::InvalidNumber, i));
+        }
+        // only ASCII bytes make it into a number
+        if self.scratch.is_empty() {
+            let number = str::from_utf8(&buf[start..i]).unwrap();
+            emit(TokenRef::Val(ValueRef::Number(number)), i);
+        } else {
+            self.scratch.extend_from_slice(&buf[start..i]);
+            let number = str::from_utf8(&self.scratch).unwrap();
+            emit(TokenRef::Val(ValueRef::Number(number)), i);
+            self.reset_scratch();
+        }
+        Ok(i)
+    }
+
+    // Reads a value captured as is, its text starts at `start` or in the
+    // scratch buffer and is scanned from `i`. The value is emitted at its
+    // last byte, a number or a literal at its delimiter.
+    fn read_raw<F>(
+        &mut self,
+        buf: &[u8],
+        start: usize,
+        mut i: usize,
+        mut scan: RawScan,
+        emit: &mut F,
+    ) -> Result<usize>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        let end = loop {
+            if i >= buf.len() {
+                self.scratch.extend_from_slice(&buf[start..]);
+                self.lexeme = Lexeme::Raw(scan);
+                return Ok(buf.len());
+            }
+            if scan.escaped {
+                scan.escaped = false;
+                i += 1;
+            } else if scan.string {
+                match find_quote_or_escape(&buf[i..]) {
+                    None => i = buf.len(),
+                    Some(n) if buf[i + n] == b'\\' => {
+                        scan.escaped = true;
+                        i += n + 1;
+                    }
+                    Some(n) => {
+                        scan.string = false;
+                        i += n + 1;
+                        if scan.depth == 0 {
+                            break i;
+                        }
+                    }
                 }
+            } else if scan.depth == 0 {
+                // a number or a literal
+                while i < buf.len() && (NUM_CHAR[buf[i] as usize] || buf[i].is_ascii_alphabetic()) {
+                    i += 1;
+                }
+                if i < buf.len() {
+                    break i;
+                }
+            } else {
+                match buf[i] {
+                    b'"' => scan.string = true,
+                    b'{' | b'[' => scan.depth += 1,
+                    b'}' | b']' => {
+                        scan.depth -= 1;
+                        if scan.depth == 0 {
+                            break i + 1;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+        };
+        let text = if self.scratch.is_empty() {
+            &buf[start..end]
+        } else {
+            self.scratch.extend_from_slice(&buf[start..end]);
+            &self.scratch[..]
+        };
+        if matches!(text[0], b'"' | b'{' | b'[') {
+            emit(TokenRef::Raw(text), end - 1);
+        } else {
+            let delimited = matches!(
+                CLASS[buf[end] as usize],
+                C_WS | C_COMMA | C_OBJ_END | C_ARR_END
+            );
+            if !delimited || !is_scalar(text) {
+                return Err(error(scalar_code(text[0]), end));
             }
+            emit(TokenRef::Raw(text), end);
+        }
+        self.reset_scratch();
+        Ok(end)
+    }
+
+    fn read_literal<F>(
+        &mut self,
+        buf: &[u8],
+        mut i: usize,
+        word: &'static [u8],
+        mut pos: usize,
+        emit: &mut F,
+    ) -> Result<usize>
+    where
+        F: FnMut(TokenRef<'_>, usize),
+    {
+        while pos < word.len() && i < buf.len() {
+            if buf[i] != word[pos] {
+                let code = match word[0] {
+                    b'n' => ErrorCode::ExpectedNull,
+                    b't' => ErrorCode::ExpectedTrue,
+                    _ => ErrorCode::ExpectedFalse,
+                };
+                return Err(error(code, i));
+            }
+            pos += 1;
             i += 1;
         }
-        match error {
-            None => Ok(json_tokens),
-            Some(err) => Err(err),
+        if pos < word.len() {
+            self.lexeme = Lexeme::Literal { word, pos };
+            return Ok(i);
+        }
+        let value = match word[0] {
+            b'n' => ValueRef::Null,
+            b't' => ValueRef::Bool(true),
+            _ => ValueRef::Bool(false),
+        };
+        emit(TokenRef::Val(value), i - 1);
+        Ok(i)
+    }
+}
+
+impl Spans {
+    // Returns the line and column of `pos`, counting the newlines of `buf`,
+    // which starts at `base`, up to it.
+    fn locate(&mut self, pos: usize, buf: &[u8], base: usize) -> (usize, usize) {
+        if pos < self.counted {
+            return self.open.unwrap_or((self.line, pos + 1 - self.line_start));
+        }
+        let from = self.counted.max(base) - base;
+        for n in memchr_iter(b'\n', &buf[from..pos - base]) {
+            self.line += 1;
+            self.line_start = base + from + n + 1;
+        }
+        self.counted = pos;
+        (self.line, pos + 1 - self.line_start)
+    }
+}
+
+fn scalar_code(first: u8) -> ErrorCode {
+    match first {
+        b'n' => ErrorCode::ExpectedNull,
+        b't' => ErrorCode::ExpectedTrue,
+        b'f' => ErrorCode::ExpectedFalse,
+        _ => ErrorCode::InvalidNumber,
+    }
+}
+
+// Whether a scalar captured as is reads the same as `read_number` or
+// `read_literal` would accept it.
+fn is_scalar(text: &[u8]) -> bool {
+    match text {
+        b"null" | b"true" | b"false" => true,
+        _ => {
+            text.iter().all(|&ch| NUM_CHAR[ch as usize])
+                && text.iter().filter(|&&ch| ch == b'.').count() <= 1
+        }
+    }
+}
+
+fn string_lexeme(key: bool, escaped: bool) -> Lexeme {
+    if key {
+        Lexeme::Key { escaped }
+    } else {
+        Lexeme::Str { escaped }
+    }
+}
+
+fn key_token<'a>(known_keys: &Option<KnownKeys>, key: &'a str) -> TokenRef<'a> {
+    match known_keys.as_ref().and_then(|keys| keys.id(key)) {
+        Some(id) => TokenRef::KnownKey(id),
+        None => TokenRef::Key(key),
+    }
+}
+
+// The longest valid UTF-8 prefix of `bytes`, all of them if `last`.
+fn utf8_prefix(bytes: &[u8], last: bool) -> Option<&str> {
+    match str::from_utf8(bytes) {
+        Ok(text) => Some(text),
+        Err(err) if !last && err.error_len().is_none() => {
+            Some(str::from_utf8(&bytes[..err.valid_up_to()]).unwrap())
+        }
+        Err(_) => None,
+    }
+}
+
+// Decodes the raw content of a string, going through `out` only if it has
+// escapes.
+fn decode<'a>(raw: &'a [u8], out: &'a mut Vec<u8>) -> Option<&'a str> {
+    if !raw.contains(&b'\\') {
+        return str::from_utf8(raw).ok();
+    }
+    out.clear();
+    unescape_into(raw, out)?;
+    str::from_utf8(out).ok()
+}
+
+// Most strings are short: their first bytes are checked a word at a time,
+// which is done before `memchr2` would be done setting up.
+fn find_quote_or_escape(data: &[u8]) -> Option<usize> {
+    const ONES: u64 = u64::from_ne_bytes([0x01; 8]);
+    const HIGH: u64 = u64::from_ne_bytes([0x80; 8]);
+    const QUOTES: u64 = u64::from_ne_bytes([b'"'; 8]);
+    const ESCAPES: u64 = u64::from_ne_bytes([b'\\'; 8]);
+    let mut i = 0;
+    while i + 8 <= data.len() && i < 32 {
+        let word = u64::from_le_bytes(data[i..i + 8].try_into().unwrap());
+        let (quotes, escapes) = (word ^ QUOTES, word ^ ESCAPES);
+        // flags the bytes of `x` that are zero, the lowest flag is exact,
+        // a borrow might flag some of the bytes above it
+        let found =
+            (quotes.wrapping_sub(ONES) & !quotes | escapes.wrapping_sub(ONES) & !escapes) & HIGH;
+        if found != 0 {
+            return Some(i + (found.trailing_zeros() / 8) as usize);
         }
+        i += 8;
+    }
+    memchr2(b'"', b'\\', &data[i..]).map(|n| i + n)
+}
+
+fn unexpected_code(state: State) -> ErrorCode {
+    match state {
+        State::Start => ErrorCode::ExpectedObjectOrArray,
+        State::ObjFirst => ErrorCode::ExpectedKey,
+        State::AfterKey => ErrorCode::ExpectedColon,
+        State::ArrFirst => ErrorCode::InvalidArrFormat,
+        State::ObjNext | State::Value | State::ArrNext => ErrorCode::ExpectedAnyTerm,
+        State::AfterValue | State::End => ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd,
+    }
+}
+
+fn error(code: ErrorCode, column: usize) -> Error {
+    Error { code, column }
+}
+
+impl Default for JsonStreamReader {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::cell::RefCell;
+    use crate::json_value::JsonValue;
+    use crate::keys::KnownKeys;
 
     #[test]
     fn test_read() {
         let buf = "{\"foo1\": \"bar1\", \"foo2\": \"bar2\", \"foo3\": { \"foo4\": \"bar4\" }, \"foo5\": [ \"bar5\", \"bar6\" ] }".as_bytes();
 
         let mut reader = JsonStreamReader::new();
-        let res = reader.read(&buf);
+        let res = reader.read(buf);
         assert_eq!(
             res.unwrap(),
             vec![
                 JsonToken::ObjBeg,
-                JsonToken::Key("foo1".to_string()),
-                JsonToken::Val(JsonValue::String("bar1".to_string())),
-                JsonToken::Key("foo2".to_string()),
-                JsonToken::Val(JsonValue::String("bar2".to_string())),
-                JsonToken::Key("foo3".to_string()),
+                JsonToken::Key("foo1".into()),
+                JsonToken::Val(JsonValue::String("bar1".into())),
+                JsonToken::Key("foo2".into()),
+                JsonToken::Val(JsonValue::String("bar2".into())),
+                JsonToken::Key("foo3".into()),
                 JsonToken::ObjBeg,
-                JsonToken::Key("foo4".to_string()),
-                JsonToken::Val(JsonValue::String("bar4".to_string())),
+                JsonToken::Key("foo4".into()),
+                JsonToken::Val(JsonValue::String("bar4".into())),
                 JsonToken::ObjEnd,
-                JsonToken::Key("foo5".to_string()),
+                JsonToken::Key("foo5".into()),
                 JsonToken::ArrBeg,
-                JsonToken::Val(JsonValue::String("bar5".to_string())),
-                JsonToken::Val(JsonValue::String("bar6".to_string())),
+                JsonToken::Val(JsonValue::String("bar5".into())),
+                JsonToken::Val(JsonValue::String("bar6".into())),
                 JsonToken::ArrEnd,
                 JsonToken::ObjEnd
             ]
         );
     }
+
+    fn read_in_chunks(doc: &[u8], size: usize) -> Vec<JsonToken> {
+        let mut reader = JsonStreamReader::new();
+        doc.chunks(size)
+            .flat_map(|chunk| reader.read(chunk).unwrap())
+            .collect()
+    }
+
+    fn read_err(doc: &str) -> Error {
+        JsonStreamReader::new().read(doc.as_bytes()).unwrap_err()
+    }
+
+    fn val(value: JsonValue) -> JsonToken {
+        JsonToken::Val(value)
+    }
+
+    fn num(n: &str) -> JsonToken {
+        val(JsonValue::Number(n.into()))
+    }
+
+    #[test]
+    fn should_read_scalars() {
+        let doc = br#"[null, true, false, 42, -42.123, 42e-123, 1E+2, "", "foo"]"#;
+        assert_eq!(
+            read_in_chunks(doc, doc.len()),
+            vec![
+                JsonToken::ArrBeg,
+                val(JsonValue::Null),
+                val(JsonValue::Bool(true)),
+                val(JsonValue::Bool(false)),
+                num("42"),
+                num("-42.123"),
+                num("42e-123"),
+                num("1E+2"),
+                val(JsonValue::String("".into())),
+                val(JsonValue::String("foo".into())),
+                JsonToken::ArrEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_decode_escapes_in_keys_and_strings() {
+        let doc = br#"{"foo\"bar": "foo\\", "\u00e9": "a\nb"}"#;
+        assert_eq!(
+            read_in_chunks(doc, doc.len()),
+            vec![
+                JsonToken::ObjBeg,
+                JsonToken::Key("foo\"bar".into()),
+                val(JsonValue::String("foo\\".into())),
+                JsonToken::Key("\u{e9}".into()),
+                val(JsonValue::String("a\nb".into())),
+                JsonToken::ObjEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_read_same_tokens_whatever_the_chunk_size() {
+        let doc = br#" {"a\"b": [1.5, -2 , true,"x\\\"y"], "c": {"d": null, "e": []},
+            "f": [{}, "caf\u00e9", false, 10]} "#;
+        let expected = read_in_chunks(doc, doc.len());
+        for size in 1..doc.len() {
+            assert_eq!(read_in_chunks(doc, size), expected, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn should_report_index_of_completing_byte() {
+        let mut positions = vec![];
+        let doc = br#"{"a": [1, null], "b": "x"}"#;
+        //            01234567890123456789012345
+        JsonStreamReader::new()
+            .read_to(doc, |_, i| positions.push(i))
+            .unwrap();
+        assert_eq!(positions, vec![0, 3, 6, 8, 13, 14, 19, 24, 25]);
+    }
+
+    #[test]
+    fn should_emit_number_before_container_end() {
+        let mut tokens = vec![];
+        JsonStreamReader::new()
+            .read_to(b"[42]", |token, i| tokens.push((token, i)))
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (JsonToken::ArrBeg, 0),
+                (num("42"), 3),
+                (JsonToken::ArrEnd, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_return_too_long_key_error() {
+        // the key is of KEY_MAX_LEN + 1 size
+        let doc = format!("{{\"{}\": 1}}", "o".repeat(KEY_MAX_LEN + 1));
+        let err = read_err(&doc);
+        assert_eq!(err, error(ErrorCode::TooLongKey, KEY_MAX_LEN + 3));
+        let doc = format!("{{\"{}\": 1}}", "o".repeat(KEY_MAX_LEN));
+        assert!(JsonStreamReader::new().read(doc.as_bytes()).is_ok());
+        // the error is the same when the key is split between buffers
+        let mut reader = JsonStreamReader::new();
+        let head = format!("{{\"{}", "o".repeat(KEY_MAX_LEN + 1));
+        reader.read(head.as_bytes()).unwrap();
+        let err = reader.read(b"\": 1}").unwrap_err();
+        assert_eq!(err, error(ErrorCode::TooLongKey, 0));
+    }
+
+    #[test]
+    fn should_return_syntax_errors() {
+        let cases = [
+            (" foo", ErrorCode::ExpectedObjectOrArray, 1),
+            ("{    foo  \"foo\": \"bar\"  }", ErrorCode::ExpectedKey, 5),
+            ("{\"foo\" ? \"bar\"  }", ErrorCode::ExpectedColon, 7),
+            ("{\"foo\": ?}", ErrorCode::ExpectedAnyTerm, 8),
+            ("[}", ErrorCode::InvalidArrFormat, 1),
+            ("[1, ]", ErrorCode::ExpectedAnyTerm, 4),
+            ("[1 2]", ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd, 3),
+            ("{\"a\": 1]", ErrorCode::ExpectedObjectCommaOrEnd, 7),
+            ("[1}", ErrorCode::ExpectedListCommaOrEnd, 2),
+            ("[nil]", ErrorCode::ExpectedNull, 2),
+            ("[truE]", ErrorCode::ExpectedTrue, 4),
+            ("[falz,", ErrorCode::ExpectedFalse, 4),
+            ("[42b]", ErrorCode::InvalidNumber, 3),
+            ("[1.2.3]", ErrorCode::InvalidNumber, 4),
+            (r#"["foo\x"]"#, ErrorCode::InvalidEscape, 7),
+            ("{} {}", ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd, 3),
+        ];
+        for (doc, code, column) in cases {
+            assert_eq!(read_err(doc), error(code, column), "{}", doc);
+        }
+    }
+
+    #[test]
+    fn should_return_ids_of_known_keys() {
+        let doc = br#"{"id": 1, "n\u0061me": "id", "other": {"id": []}}"#;
+        let keys = KnownKeys::new(["name", "id"]);
+        let mut reader = JsonStreamReader::new().with_known_keys(keys);
+        let tokens: Vec<_> = doc
+            .chunks(3)
+            .flat_map(|chunk| reader.read(chunk).unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                JsonToken::ObjBeg,
+                JsonToken::KnownKey(1),
+                num("1"),
+                JsonToken::KnownKey(0),
+                val(JsonValue::String("id".into())),
+                JsonToken::Key("other".into()),
+                JsonToken::ObjBeg,
+                JsonToken::KnownKey(1),
+                JsonToken::ArrBeg,
+                JsonToken::ArrEnd,
+                JsonToken::ObjEnd,
+                JsonToken::ObjEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_share_interned_keys() {
+        let doc = br#"[{"id": 1, "name": "a"}, {"id": 2, "name": "id"}, {"id": 3}]"#;
+        let mut reader = JsonStreamReader::new().with_key_interner(KeyInterner::new());
+        let keys: Vec<_> = doc
+            .chunks(5)
+            .flat_map(|chunk| reader.read(chunk).unwrap())
+            .filter_map(|token| match token {
+                JsonToken::Key(key) => Some(key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, ["id", "name", "id", "name", "id"]);
+        let interner = reader.key_interner().unwrap();
+        assert_eq!(interner.len(), 2);
+        for key in &keys {
+            let shared = interner.resolve(interner.get(key).unwrap());
+            assert_eq!(key.as_ptr(), shared.as_ptr());
+        }
+    }
+
+    // Joins fragments back into whole strings.
+    fn join_fragments(tokens: Vec<JsonToken>) -> Vec<JsonToken> {
+        let mut joined = vec![];
+        let mut text = None;
+        for token in tokens {
+            match token {
+                JsonToken::StrBegin => text = Some(String::new()),
+                JsonToken::StrChunk(chunk) => text.as_mut().unwrap().push_str(&chunk),
+                JsonToken::StrEnd => {
+                    joined.push(val(JsonValue::String(text.take().unwrap().into())))
+                }
+                token => joined.push(token),
+            }
+        }
+        joined
+    }
+
+    #[test]
+    fn should_emit_long_strings_in_fragments() {
+        let doc = r#"{"a_long_key_is_not_split": ["short", "just  8!", "nine byte",
+            "caf\u00e9 \ud83d\ude00 \"quoted\" \\ back", "café 😀 ünïcödé", ""]}"#;
+        let expected = JsonStreamReader::new().read(doc.as_bytes()).unwrap();
+        for size in 1..doc.len() {
+            let mut reader = JsonStreamReader::new().with_string_fragments(8);
+            let mut tokens = vec![];
+            for chunk in doc.as_bytes().chunks(size) {
+                tokens.extend(reader.read(chunk).unwrap());
+                // only a cut escape or UTF-8 sequence of a value is kept
+                let key = matches!(reader.lexeme, Lexeme::Key { .. });
+                assert!(key || reader.scratch.len() <= 11, "chunk size {}", size);
+            }
+            let begins = tokens.iter().filter(|t| **t == JsonToken::StrBegin).count();
+            assert_eq!(begins, 4, "chunk size {}", size);
+            assert_eq!(join_fragments(tokens), expected, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn should_capture_value_as_is() {
+        let doc = br#" {"a" : [1, "x]\"}" ], "b": { } } "#;
+        for size in 1..doc.len() {
+            let mut reader = JsonStreamReader::new();
+            reader.capture_next_value();
+            let mut tokens = vec![];
+            for chunk in doc.chunks(size) {
+                tokens.extend(reader.read(chunk).unwrap());
+            }
+            assert_eq!(tokens, vec![JsonToken::Raw(doc[1..doc.len() - 1].to_vec())]);
+            assert_eq!(reader.state, State::End);
+        }
+        let mut tokens = vec![];
+        let mut reader = JsonStreamReader::new();
+        reader
+            .read_to(b"[1, ", |token, i| tokens.push((token, i)))
+            .unwrap();
+        reader.capture_next_value();
+        reader
+            .read_to(b"-2.5e3 ,null]", |token, i| tokens.push((token, i)))
+            .unwrap();
+        assert_eq!(
+            tokens[2..],
+            [
+                (JsonToken::Raw(b"-2.5e3".to_vec()), 6),
+                (val(JsonValue::Null), 11),
+                (JsonToken::ArrEnd, 12)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_locate_tokens_across_chunks() {
+        let doc = "{\"a\": [1.5, true],\n  \"b\\\"\": \"x\\ny\", \"c\":\nnull}";
+        let span = |start_byte, end_byte, start_line, start_col| Span {
+            start_byte,
+            end_byte,
+            start_line,
+            start_col,
+        };
+        let expected = [
+            span(0, 1, 1, 1),
+            span(1, 4, 1, 2),
+            span(6, 7, 1, 7),
+            span(7, 10, 1, 8),
+            span(12, 16, 1, 13),
+            span(16, 17, 1, 17),
+            span(21, 26, 2, 3),
+            span(28, 34, 2, 10),
+            span(36, 39, 2, 18),
+            span(41, 45, 3, 1),
+            span(45, 46, 3, 5),
+        ];
+        let tokens = read_in_chunks(doc.as_bytes(), doc.len());
+        for size in 1..=doc.len() {
+            let mut reader = JsonStreamReader::new().with_spans();
+            let mut located = vec![];
+            for (n, chunk) in doc.as_bytes().chunks(size).enumerate() {
+                // spans stay right when some chunks are read w/o them
+                if n % 3 == 1 {
+                    let read = reader.read(chunk).unwrap();
+                    located.extend(read.into_iter().map(|token| (token, Span::default())));
+                } else {
+                    located.extend(reader.read_spanned(chunk).unwrap());
+                }
+            }
+            assert_eq!(located.len(), tokens.len(), "chunk size {}", size);
+            for (n, (token, span)) in located.into_iter().enumerate() {
+                assert_eq!(token, tokens[n], "chunk size {}", size);
+                if span != Span::default() {
+                    assert_eq!(span, expected[n], "chunk size {}", size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_fail_on_invalid_captured_scalar() {
+        for (doc, code) in [
+            ("[nul]", ErrorCode::ExpectedNull),
+            ("[1x]", ErrorCode::InvalidNumber),
+            ("[1.2.3]", ErrorCode::InvalidNumber),
+            ("[true\"]", ErrorCode::ExpectedTrue),
+        ] {
+            let mut reader = JsonStreamReader::new();
+            reader.read(b"").unwrap();
+            reader.read(&doc.as_bytes()[..1]).unwrap();
+            reader.capture_next_value();
+            let err = reader.read(&doc.as_bytes()[1..]).unwrap_err();
+            assert_eq!(err.code, code, "{}", doc);
+        }
+    }
+
+    #[test]
+    fn should_fail_on_invalid_fragment() {
+        let mut reader = JsonStreamReader::new().with_string_fragments(4);
+        let err = reader.read(br#"["abcdef\x"]"#).unwrap_err();
+        assert_eq!(err, error(ErrorCode::InvalidEscape, 10));
+        let mut reader = JsonStreamReader::new().with_string_fragments(4);
+        reader.read(b"[\"abcdef\xc3").unwrap();
+        let err = reader.read(b"(\"]").unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidEscape);
+    }
+
+    #[test]
+    fn should_track_depth_of_deeply_nested_containers() {
+        let depth = 100_000;
+        let doc = format!("{}0{}", "[{\"a\":".repeat(depth), "}]".repeat(depth));
+        let mut reader = JsonStreamReader::new();
+        let (head, tail) = doc.as_bytes().split_at(depth * 6);
+        reader.read(head).unwrap();
+        assert_eq!(reader.depth(), depth * 2);
+        let tokens = reader.read(tail).unwrap();
+        assert_eq!(reader.depth(), 0);
+        assert_eq!(tokens.last(), Some(&JsonToken::ArrEnd));
+    }
+
+    #[test]
+    fn should_release_scratch_after_long_token() {
+        let doc = format!("[\"{}\"]", "x".repeat(100_000));
+        let mut reader = JsonStreamReader::new();
+        let tokens: Vec<_> = doc
+            .as_bytes()
+            .chunks(4096)
+            .flat_map(|chunk| reader.read(chunk).unwrap())
+            .collect();
+        assert_eq!(tokens.len(), 3);
+        assert!(reader.scratch.capacity() <= SCRATCH_CAPACITY);
+    }
+
+    #[test]
+    fn should_resume_from_checkpoint_anywhere() {
+        let doc = br#"{"a": [1.5, -20, "x\"y", null, true], "bb": {"c": "long string"}}"#;
+        let expected = read_in_chunks(doc, doc.len());
+        for split in 0..=doc.len() {
+            let mut reader = JsonStreamReader::new().with_string_fragments(4);
+            let mut tokens = reader.read(&doc[..split]).unwrap();
+            let blob = reader.checkpoint().to_bytes();
+            drop(reader);
+            let checkpoint = Checkpoint::from_bytes(&blob).unwrap();
+            assert_eq!(checkpoint.offset(), split);
+            let mut reader = JsonStreamReader::resume(&checkpoint).with_string_fragments(4);
+            tokens.extend(reader.read(&doc[checkpoint.offset()..]).unwrap());
+            assert_eq!(reader.depth(), 0);
+            assert_eq!(join_fragments(tokens), expected, "split at {}", split);
+        }
+    }
 }
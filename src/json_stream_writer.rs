@@ -0,0 +1,572 @@
+//! The inverse of `JsonStreamReader`: writes JSON incrementally out of
+//! tokens, making sure they come in a valid order.
+use std::io::{self, Write};
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_node::JsonNode;
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+
+/// Decides on the whitespace around the punctuation, see `CompactFormatter`.
+pub trait Formatter {
+    fn begin_object<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(b"{")
+    }
+
+    fn end_object<W: Write>(&mut self, w: &mut W, _empty: bool) -> io::Result<()> {
+        w.write_all(b"}")
+    }
+
+    fn begin_array<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(b"[")
+    }
+
+    fn end_array<W: Write>(&mut self, w: &mut W, _empty: bool) -> io::Result<()> {
+        w.write_all(b"]")
+    }
+
+    /// Called before a key, `first` is set for the first key of an object.
+    fn begin_key<W: Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            w.write_all(b",")
+        }
+    }
+
+    /// Writes the colon between a key and its value.
+    fn key_separator<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(b":")
+    }
+
+    /// Called before an array element, `first` is set for the first one.
+    fn begin_element<W: Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            w.write_all(b",")
+        }
+    }
+
+    /// Called once the top-level value is complete.
+    fn end_document<W: Write>(&mut self, _w: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes JSON w/o any insignificant whitespace.
+#[derive(Debug, Default, Clone)]
+pub struct CompactFormatter;
+
+impl Formatter for CompactFormatter {}
+
+// Nesting state, similar to the reader's `Token` stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    // inside an object, a key or `}` is expected
+    Key { first: bool },
+    // inside an object, right after a key
+    Val,
+    // inside an array, an element or `]` is expected
+    Arr { first: bool },
+    // inside a string written in fragments
+    Str,
+}
+
+pub struct JsonStreamWriter<W, F = CompactFormatter> {
+    out: Output<W>,
+    formatter: F,
+    stack: Vec<State>,
+    // the top-level value is complete
+    done: bool,
+}
+
+impl<W: Write> JsonStreamWriter<W> {
+    pub fn new(out: W) -> Self {
+        JsonStreamWriter::with_formatter(out, CompactFormatter)
+    }
+}
+
+impl<W: Write, F: Formatter> JsonStreamWriter<W, F> {
+    pub fn with_formatter(out: W, formatter: F) -> Self {
+        JsonStreamWriter {
+            out: Output {
+                inner: out,
+                written: 0,
+            },
+            formatter,
+            stack: vec![],
+            done: false,
+        }
+    }
+
+    pub fn formatter_mut(&mut self) -> &mut F {
+        &mut self.formatter
+    }
+
+    /// Number of objects and arrays opened but not closed yet.
+    pub fn depth(&self) -> usize {
+        match self.stack.last() {
+            Some(State::Str) => self.stack.len() - 1,
+            _ => self.stack.len(),
+        }
+    }
+
+    /// Whether a complete document has been written.
+    pub fn is_complete(&self) -> bool {
+        self.done
+    }
+
+    /// Number of bytes written so far.
+    pub fn bytes_written(&self) -> usize {
+        self.out.written
+    }
+
+    /// Whether the next token has to be a key (or `ObjEnd`).
+    pub fn expects_key(&self) -> bool {
+        matches!(self.stack.last(), Some(State::Key { .. }))
+    }
+
+    pub fn write_token(&mut self, token: &JsonToken) -> Result<()> {
+        match token {
+            JsonToken::ObjBeg => self.begin_object(),
+            JsonToken::ObjEnd => self.end_object(),
+            JsonToken::ArrBeg => self.begin_array(),
+            JsonToken::ArrEnd => self.end_array(),
+            JsonToken::Key(key) => self.key(key),
+            // only the reader knows the name
+            JsonToken::KnownKey(_) => Err(self.error(ErrorCode::InvalidFormat)),
+            JsonToken::Val(value) => self.value(value),
+            JsonToken::StrBegin => self.begin_string(),
+            JsonToken::StrChunk(text) => self.string_chunk(text),
+            JsonToken::StrEnd => self.end_string(),
+            JsonToken::Raw(raw) => self.raw_value(raw),
+        }
+    }
+
+    pub fn begin_object(&mut self) -> Result<()> {
+        self.before_value(true)?;
+        self.io(|f, w| f.begin_object(w))?;
+        self.stack.push(State::Key { first: true });
+        Ok(())
+    }
+
+    pub fn end_object(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(&State::Key { first }) => {
+                self.stack.pop();
+                self.io(|f, w| f.end_object(w, first))?;
+                self.after_value()
+            }
+            Some(State::Val) => Err(self.error(ErrorCode::ExpectedAnyTerm)),
+            Some(State::Arr { .. }) => Err(self.error(ErrorCode::ExpectedListCommaOrEnd)),
+            Some(State::Str) => Err(self.error(ErrorCode::ExpectedString)),
+            None => Err(self.error(ErrorCode::InvalidFormat)),
+        }
+    }
+
+    pub fn begin_array(&mut self) -> Result<()> {
+        self.before_value(true)?;
+        self.io(|f, w| f.begin_array(w))?;
+        self.stack.push(State::Arr { first: true });
+        Ok(())
+    }
+
+    pub fn end_array(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(&State::Arr { first }) => {
+                self.stack.pop();
+                self.io(|f, w| f.end_array(w, first))?;
+                self.after_value()
+            }
+            Some(State::Val) => Err(self.error(ErrorCode::ExpectedAnyTerm)),
+            Some(State::Key { .. }) => Err(self.error(ErrorCode::ExpectedObjectCommaOrEnd)),
+            Some(State::Str) => Err(self.error(ErrorCode::ExpectedString)),
+            None => Err(self.error(ErrorCode::InvalidFormat)),
+        }
+    }
+
+    pub fn key(&mut self, key: &str) -> Result<()> {
+        match self.stack.last() {
+            Some(&State::Key { first }) => {
+                self.io(|f, w| f.begin_key(w, first))?;
+                self.io(|_, w| write_escaped(w, key))?;
+                self.io(|f, w| f.key_separator(w))?;
+                *self.stack.last_mut().unwrap() = State::Val;
+                Ok(())
+            }
+            Some(State::Str) => Err(self.error(ErrorCode::ExpectedString)),
+            _ => Err(self.error(ErrorCode::ExpectedAnyTerm)),
+        }
+    }
+
+    pub fn value(&mut self, value: &JsonValue) -> Result<()> {
+        match value {
+            JsonValue::Null => self.null(),
+            JsonValue::Bool(b) => self.bool(*b),
+            JsonValue::String(s) => self.string(s),
+            JsonValue::Number(n) => self.number(n),
+        }
+    }
+
+    pub fn null(&mut self) -> Result<()> {
+        self.scalar(|w| w.write_all(b"null"))
+    }
+
+    pub fn bool(&mut self, b: bool) -> Result<()> {
+        self.scalar(|w| w.write_all(if b { b"true" } else { b"false" }))
+    }
+
+    pub fn string(&mut self, s: &str) -> Result<()> {
+        self.scalar(|w| write_escaped(w, s))
+    }
+
+    /// Opens a string value whose text is written with `string_chunk`.
+    pub fn begin_string(&mut self) -> Result<()> {
+        self.before_value(false)?;
+        self.io(|_, w| w.write_all(b"\""))?;
+        self.stack.push(State::Str);
+        Ok(())
+    }
+
+    pub fn string_chunk(&mut self, text: &str) -> Result<()> {
+        match self.stack.last() {
+            Some(State::Str) => self.io(|_, w| write_escaped_fragment(w, text)),
+            _ => Err(self.error(ErrorCode::InvalidFormat)),
+        }
+    }
+
+    pub fn end_string(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(State::Str) => {
+                self.stack.pop();
+                self.io(|_, w| w.write_all(b"\""))?;
+                self.after_value()
+            }
+            _ => Err(self.error(ErrorCode::InvalidFormat)),
+        }
+    }
+
+    /// Writes a number lexeme as is, e.g. `"1.50"` stays `1.50`.
+    pub fn number(&mut self, n: &str) -> Result<()> {
+        if !is_valid_number(n) {
+            return Err(self.error(ErrorCode::InvalidNumber));
+        }
+        self.scalar(|w| w.write_all(n.as_bytes()))
+    }
+
+    /// Writes a value captured as is, e.g. by `TokenReader::next_raw`. The
+    /// text is trusted to be valid JSON.
+    pub fn raw_value(&mut self, raw: &[u8]) -> Result<()> {
+        if raw.is_empty() {
+            return Err(self.error(ErrorCode::ExpectedAnyTerm));
+        }
+        self.before_value(matches!(raw[0], b'{' | b'['))?;
+        self.io(|_, w| w.write_all(raw))?;
+        self.after_value()
+    }
+
+    /// Writes a whole tree, members in their order.
+    pub fn node(&mut self, node: &JsonNode) -> Result<()> {
+        match node {
+            JsonNode::Null => self.null(),
+            JsonNode::Bool(b) => self.bool(*b),
+            JsonNode::String(s) => self.string(s),
+            JsonNode::Number(n) => self.number(n),
+            JsonNode::Array(items) => {
+                self.begin_array()?;
+                for item in items {
+                    self.node(item)?;
+                }
+                self.end_array()
+            }
+            JsonNode::Object(members) => {
+                self.begin_object()?;
+                for (key, value) in members {
+                    self.key(key)?;
+                    self.node(value)?;
+                }
+                self.end_object()
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.io(|_, w| w.flush())
+    }
+
+    /// Flushes and returns the underlying writer, the document must be
+    /// complete.
+    pub fn finish(mut self) -> Result<W> {
+        if !self.done {
+            return Err(self.error(ErrorCode::UnexpectedEof));
+        }
+        self.flush()?;
+        Ok(self.out.inner)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.inner
+    }
+
+    fn scalar<G>(&mut self, write: G) -> Result<()>
+    where
+        G: FnOnce(&mut Output<W>) -> io::Result<()>,
+    {
+        self.before_value(false)?;
+        self.io(|_, w| write(w))?;
+        self.after_value()
+    }
+
+    // Checks that a value may come next and writes what precedes it.
+    fn before_value(&mut self, container: bool) -> Result<()> {
+        match self.stack.last() {
+            None if self.done => Err(self.error(ErrorCode::InvalidFormat)),
+            None if !container => Err(self.error(ErrorCode::ExpectedObjectOrArray)),
+            None | Some(State::Val) => Ok(()),
+            Some(&State::Arr { first }) => {
+                self.io(|f, w| f.begin_element(w, first))?;
+                *self.stack.last_mut().unwrap() = State::Arr { first: false };
+                Ok(())
+            }
+            Some(State::Key { .. }) => Err(self.error(ErrorCode::ExpectedKey)),
+            Some(State::Str) => Err(self.error(ErrorCode::ExpectedString)),
+        }
+    }
+
+    fn after_value(&mut self) -> Result<()> {
+        match self.stack.last_mut() {
+            Some(state @ State::Val) => *state = State::Key { first: false },
+            Some(_) => {}
+            None => {
+                self.done = true;
+                self.io(|f, w| f.end_document(w))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn io<G>(&mut self, write: G) -> Result<()>
+    where
+        G: FnOnce(&mut F, &mut Output<W>) -> io::Result<()>,
+    {
+        write(&mut self.formatter, &mut self.out).map_err(|err| Error {
+            code: ErrorCode::Io(err.kind()),
+            column: self.out.written,
+        })
+    }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        Error {
+            code,
+            column: self.out.written,
+        }
+    }
+}
+
+// Counts the bytes written to locate errors.
+struct Output<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        self.written += size;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes `s` as a quoted JSON string.
+pub(crate) fn write_escaped<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    w.write_all(b"\"")?;
+    write_escaped_fragment(w, s)?;
+    w.write_all(b"\"")
+}
+
+/// Writes `s` escaped, w/o the quotes.
+pub(crate) fn write_escaped_fragment<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    let bytes = s.as_bytes();
+    let mut start = 0;
+    for (i, &ch) in bytes.iter().enumerate() {
+        let escaped: &[u8] = match ch {
+            b'"' => b"\\\"",
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            0x08 => b"\\b",
+            0x0c => b"\\f",
+            0..=0x1f => b"",
+            _ => continue,
+        };
+        w.write_all(&bytes[start..i])?;
+        if escaped.is_empty() {
+            write!(w, "\\u{:04x}", ch)?;
+        } else {
+            w.write_all(escaped)?;
+        }
+        start = i + 1;
+    }
+    w.write_all(&bytes[start..])
+}
+
+/// Checks `n` against the JSON number grammar.
+pub(crate) fn is_valid_number(n: &str) -> bool {
+    let b = n.as_bytes();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < b.len() && b[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i - start
+    };
+    if b.first() == Some(&b'-') {
+        i += 1;
+    }
+    match b.get(i) {
+        Some(b'0') => i += 1,
+        Some(b'1'..=b'9') => {
+            digits(&mut i);
+        }
+        _ => return false,
+    }
+    if b.get(i) == Some(&b'.') {
+        i += 1;
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    if let Some(b'e' | b'E') = b.get(i) {
+        i += 1;
+        if let Some(b'+' | b'-') = b.get(i) {
+            i += 1;
+        }
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    i == b.len()
+}
+
+#[cfg(test)]
+mod json_stream_writer_tests {
+    use super::*;
+    use crate::json_stream_reader::JsonStreamReader;
+
+    fn write(tokens: &[JsonToken]) -> Result<String> {
+        let mut writer = JsonStreamWriter::new(vec![]);
+        for token in tokens {
+            writer.write_token(token)?;
+        }
+        Ok(String::from_utf8(writer.finish()?).unwrap())
+    }
+
+    #[test]
+    fn should_round_trip_reader_tokens() {
+        let doc = r#"{"a":[1,-2.5e3,{"b":null}],"c\n\"":"x\ty","d":[],"e":{},"f":true}"#;
+        let tokens = JsonStreamReader::new().read(doc.as_bytes()).unwrap();
+        assert_eq!(write(&tokens).unwrap(), doc);
+    }
+
+    #[test]
+    fn should_write_with_calls() {
+        let mut writer = JsonStreamWriter::new(vec![]);
+        writer.begin_array().unwrap();
+        writer.string("\u{1}é").unwrap();
+        writer.begin_object().unwrap();
+        writer.key("n").unwrap();
+        writer.number("0.10").unwrap();
+        writer.end_object().unwrap();
+        writer.end_array().unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[\"\\u0001é\",{\"n\":0.10}]"
+        );
+    }
+
+    #[test]
+    fn should_write_string_in_fragments() {
+        let doc = r#"["a \"long\" string\n",1]"#;
+        let tokens = JsonStreamReader::new()
+            .with_string_fragments(4)
+            .read(doc.as_bytes())
+            .unwrap();
+        assert_eq!(tokens[1], JsonToken::StrBegin);
+        assert_eq!(write(&tokens).unwrap(), doc);
+        let mut writer = JsonStreamWriter::new(vec![]);
+        writer.begin_array().unwrap();
+        writer.begin_string().unwrap();
+        assert!(writer.end_array().is_err());
+        writer.string_chunk("x").unwrap();
+        writer.end_string().unwrap();
+        assert!(writer.string_chunk("y").is_err());
+    }
+
+    #[test]
+    fn should_write_raw_values() {
+        let mut writer = JsonStreamWriter::new(vec![]);
+        writer.begin_object().unwrap();
+        writer.key("payload").unwrap();
+        writer.raw_value(br#"{ "x": [1, 2] }"#).unwrap();
+        writer.key("n").unwrap();
+        writer.write_token(&JsonToken::Raw(b"12".to_vec())).unwrap();
+        writer.end_object().unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(out, br#"{"payload":{ "x": [1, 2] },"n":12}"#);
+        let mut writer = JsonStreamWriter::new(vec![]);
+        let err = writer.raw_value(b"12").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ExpectedObjectOrArray);
+    }
+
+    #[test]
+    fn should_reject_value_instead_of_key() {
+        let res = write(&[JsonToken::ObjBeg, JsonToken::Val(JsonValue::Null)]);
+        assert_eq!(
+            res,
+            Err(Error {
+                code: ErrorCode::ExpectedKey,
+                column: 1
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_mismatched_end() {
+        let res = write(&[JsonToken::ArrBeg, JsonToken::ObjEnd]);
+        assert_eq!(res.unwrap_err().code, ErrorCode::ExpectedListCommaOrEnd);
+        let res = write(&[
+            JsonToken::ObjBeg,
+            JsonToken::Key("a".into()),
+            JsonToken::ObjEnd,
+        ]);
+        assert_eq!(res.unwrap_err().code, ErrorCode::ExpectedAnyTerm);
+    }
+
+    #[test]
+    fn should_reject_incomplete_or_extra_tokens() {
+        let res = write(&[JsonToken::ArrBeg]);
+        assert_eq!(res.unwrap_err().code, ErrorCode::UnexpectedEof);
+        let res = write(&[JsonToken::ArrBeg, JsonToken::ArrEnd, JsonToken::ArrBeg]);
+        assert_eq!(res.unwrap_err().code, ErrorCode::InvalidFormat);
+        let res = write(&[JsonToken::Val(JsonValue::Null)]);
+        assert_eq!(res.unwrap_err().code, ErrorCode::ExpectedObjectOrArray);
+    }
+
+    #[test]
+    fn should_validate_numbers() {
+        for n in ["0", "-0", "12", "1.5", "1e10", "-1.5E-3"] {
+            assert!(is_valid_number(n), "{}", n);
+        }
+        for n in ["", "-", "01", "1.", ".5", "1e", "1-2", "+1"] {
+            assert!(!is_valid_number(n), "{}", n);
+        }
+    }
+}
@@ -1,11 +1,69 @@
-use crate::json_value::JsonValue;
+use crate::json_value::{JsonValue, ValueRef};
+use crate::text::Text;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum JsonToken {
     ObjBeg,
     ObjEnd,
     ArrBeg,
     ArrEnd,
-    Key(String),
+    Key(Text),
+    /// A key registered with `JsonStreamReader::with_known_keys`, by its
+    /// index in the registered list.
+    KnownKey(u32),
     Val(JsonValue),
+    /// A long string value, see `JsonStreamReader::with_string_fragments`,
+    /// its text comes in any number of `StrChunk`s until `StrEnd`.
+    StrBegin,
+    StrChunk(String),
+    StrEnd,
+    /// A value captured as is, see `JsonStreamReader::capture_next_value`.
+    Raw(Vec<u8>),
+}
+
+/// Where a token is in the document, see `JsonStreamReader::read_spanned`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    /// Position of the first byte of the token.
+    pub start_byte: usize,
+    /// Position right after its last byte.
+    pub end_byte: usize,
+    /// Line of the first byte, from 1.
+    pub start_line: usize,
+    /// Column of the first byte in bytes, from 1.
+    pub start_col: usize,
+}
+
+/// A token whose text is borrowed, from the reader or a `TokenBuffer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenRef<'a> {
+    ObjBeg,
+    ObjEnd,
+    ArrBeg,
+    ArrEnd,
+    Key(&'a str),
+    KnownKey(u32),
+    Val(ValueRef<'a>),
+    StrBegin,
+    StrChunk(&'a str),
+    StrEnd,
+    Raw(&'a [u8]),
+}
+
+impl TokenRef<'_> {
+    pub fn to_token(&self) -> JsonToken {
+        match *self {
+            TokenRef::ObjBeg => JsonToken::ObjBeg,
+            TokenRef::ObjEnd => JsonToken::ObjEnd,
+            TokenRef::ArrBeg => JsonToken::ArrBeg,
+            TokenRef::ArrEnd => JsonToken::ArrEnd,
+            TokenRef::Key(key) => JsonToken::Key(Text::new(key)),
+            TokenRef::KnownKey(id) => JsonToken::KnownKey(id),
+            TokenRef::Val(value) => JsonToken::Val(value.to_value()),
+            TokenRef::StrBegin => JsonToken::StrBegin,
+            TokenRef::StrChunk(text) => JsonToken::StrChunk(text.to_string()),
+            TokenRef::StrEnd => JsonToken::StrEnd,
+            TokenRef::Raw(raw) => JsonToken::Raw(raw.to_vec()),
+        }
+    }
 }
@@ -1,7 +1,29 @@
-#[derive(Debug, PartialEq)]
+use crate::text::Text;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum JsonValue {
     Null,
     Bool(bool),
-    String(String),
-    Number(String),
+    String(Text),
+    Number(Text),
+}
+
+/// A scalar whose text is borrowed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueRef<'a> {
+    Null,
+    Bool(bool),
+    String(&'a str),
+    Number(&'a str),
+}
+
+impl ValueRef<'_> {
+    pub fn to_value(&self) -> JsonValue {
+        match *self {
+            ValueRef::Null => JsonValue::Null,
+            ValueRef::Bool(b) => JsonValue::Bool(b),
+            ValueRef::String(s) => JsonValue::String(Text::new(s)),
+            ValueRef::Number(n) => JsonValue::Number(Text::new(n)),
+        }
+    }
 }
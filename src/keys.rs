@@ -0,0 +1,266 @@
+//! Shared and numbered object keys.
+//!
+//! Documents made of many records repeat the same few keys over and over.
+//! `KeyInterner` keeps a single `Arc<str>` per distinct key: a reader created
+//! `with_key_interner` returns keys that share it, and the keys of
+//! `JsonStreamReader::read_into`, which are not allocated, can be fed to it.
+//! `KnownKeys` is a set of keys registered up front: a reader created
+//! `with_known_keys` returns `JsonToken::KnownKey(id)` for them, and a plain
+//! `JsonToken::Key` for any other key.
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// The number of a key in a `KeyInterner`, in the order keys were first seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyId(u32);
+
+impl KeyId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyInterner {
+    ids: HashMap<Arc<str>, KeyId>,
+    keys: Vec<Arc<str>>,
+    limit: Option<usize>,
+}
+
+impl KeyInterner {
+    pub fn new() -> Self {
+        KeyInterner::default()
+    }
+
+    /// Keeps at most `limit` keys, so that documents with ever new keys do
+    /// not grow the interner without bounds.
+    pub fn with_limit(limit: usize) -> Self {
+        KeyInterner {
+            limit: Some(limit),
+            ..KeyInterner::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the id of `key`, adding it if needed. Returns `None` for a
+    /// new key once the limit is reached.
+    pub fn intern(&mut self, key: &str) -> Option<KeyId> {
+        if let Some(&id) = self.ids.get(key) {
+            return Some(id);
+        }
+        if self.limit.is_some_and(|limit| self.keys.len() >= limit)
+            || self.keys.len() >= u32::MAX as usize
+        {
+            return None;
+        }
+        let id = KeyId(self.keys.len() as u32);
+        let key: Arc<str> = Arc::from(key);
+        self.keys.push(key.clone());
+        self.ids.insert(key, id);
+        Some(id)
+    }
+
+    /// Returns the shared copy of `key`, or a new one once the limit is
+    /// reached.
+    pub fn shared(&mut self, key: &str) -> Arc<str> {
+        match self.intern(key) {
+            Some(id) => self.keys[id.index()].clone(),
+            None => Arc::from(key),
+        }
+    }
+
+    /// Returns the id of `key` if it was interned.
+    pub fn get(&self, key: &str) -> Option<KeyId> {
+        self.ids.get(key).copied()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `id` comes from another interner.
+    pub fn resolve(&self, id: KeyId) -> &Arc<str> {
+        &self.keys[id.index()]
+    }
+}
+
+// marks a slot without a key
+const EMPTY: u32 = u32::MAX;
+
+/// A fixed set of keys, numbered in the order they were registered.
+///
+/// Lookups go through a perfect hash built with the hash and displace
+/// method: keys are spread over buckets of about four, and each bucket has a
+/// displacement chosen so that its keys land in free slots. A lookup hashes
+/// the key once and compares it with the single candidate.
+#[derive(Debug, Clone)]
+pub struct KnownKeys {
+    keys: Vec<Box<str>>,
+    seed: u64,
+    displacements: Vec<u32>,
+    // key ids, twice as many slots as keys
+    slots: Vec<u32>,
+}
+
+impl KnownKeys {
+    /// # Panics
+    ///
+    /// Panics if a key is registered twice.
+    pub fn new<I, S>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let keys: Vec<Box<str>> = keys.into_iter().map(|key| key.as_ref().into()).collect();
+        assert!(keys.len() < EMPTY as usize, "too many known keys");
+        let mut seen = HashSet::new();
+        for key in &keys {
+            assert!(seen.insert(key), "key `{}` is registered twice", key);
+        }
+        let buckets = keys.len().div_ceil(4).max(1);
+        let size = (keys.len() * 2).next_power_of_two();
+        // keys with the same hash can't be told apart, hash them again
+        for seed in 0.. {
+            if let Some(keys) = KnownKeys::build(&keys, seed, buckets, size) {
+                return keys;
+            }
+        }
+        unreachable!()
+    }
+
+    fn build(keys: &[Box<str>], seed: u64, buckets: usize, size: usize) -> Option<KnownKeys> {
+        let mut members = vec![vec![]; buckets];
+        for (id, key) in keys.iter().enumerate() {
+            let (bucket, base, step) = split(hash(key, seed), buckets);
+            members[bucket].push((id as u32, base, step));
+        }
+        // the largest buckets are the hardest to place, they go first
+        let mut order: Vec<usize> = (0..buckets).collect();
+        order.sort_by_key(|&bucket| std::cmp::Reverse(members[bucket].len()));
+
+        let mut displacements = vec![0; buckets];
+        let mut slots = vec![EMPTY; size];
+        let mut taken = vec![];
+        for bucket in order {
+            let keys = &members[bucket];
+            if keys.is_empty() {
+                break;
+            }
+            // an odd step visits every slot as the displacement grows
+            let placed = (0..size as u32).find(|&displacement| {
+                taken.clear();
+                keys.iter().all(|&(_, base, step)| {
+                    let slot = slot(base, step, displacement, size);
+                    let free = slots[slot] == EMPTY && !taken.contains(&slot);
+                    taken.push(slot);
+                    free
+                })
+            })?;
+            displacements[bucket] = placed;
+            for &(id, base, step) in keys {
+                slots[slot(base, step, placed, size)] = id;
+            }
+        }
+        Some(KnownKeys {
+            keys: keys.to_vec(),
+            seed,
+            displacements,
+            slots,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn id(&self, key: &str) -> Option<u32> {
+        let (bucket, base, step) = split(hash(key, self.seed), self.displacements.len());
+        let slot = slot(base, step, self.displacements[bucket], self.slots.len());
+        let id = self.slots[slot];
+        (id != EMPTY && *self.keys[id as usize] == *key).then_some(id)
+    }
+
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.keys.get(id as usize).map(|key| &**key)
+    }
+}
+
+// FNV-1a, followed by a finalizer spreading the bits of short keys.
+fn hash(key: &str, seed: u64) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325 ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    for &byte in key.as_bytes() {
+        h = (h ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ h >> 33
+}
+
+// The bucket of a hash, and where its key goes for a displacement.
+fn split(hash: u64, buckets: usize) -> (usize, u32, u32) {
+    let bucket = (hash >> 32) as usize % buckets;
+    (bucket, hash as u32, (hash >> 16) as u32 | 1)
+}
+
+fn slot(base: u32, step: u32, displacement: u32, size: usize) -> usize {
+    base.wrapping_add(displacement.wrapping_mul(step)) as usize & (size - 1)
+}
+
+#[cfg(test)]
+mod keys_tests {
+    use super::*;
+
+    #[test]
+    fn should_intern_keys_once() {
+        let mut interner = KeyInterner::new();
+        let id = interner.intern("name").unwrap();
+        assert_eq!(interner.intern("id"), Some(KeyId(1)));
+        assert_eq!(interner.intern("name"), Some(id));
+        let (a, b) = (interner.shared("name"), interner.shared("name"));
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(&**interner.resolve(id), "name");
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn should_stop_interning_at_limit() {
+        let mut interner = KeyInterner::with_limit(1);
+        assert!(interner.intern("a").is_some());
+        assert_eq!(interner.intern("b"), None);
+        assert_eq!(&*interner.shared("b"), "b");
+        assert_eq!(interner.get("b"), None);
+        assert_eq!(interner.len(), 1);
+    }
+
+    #[test]
+    fn should_look_up_every_known_key() {
+        for n in [0, 1, 2, 30, 1000] {
+            let names: Vec<String> = (0..n).map(|i| format!("key_{}", i)).collect();
+            let keys = KnownKeys::new(&names);
+            assert_eq!(keys.len(), n);
+            for (i, name) in names.iter().enumerate() {
+                assert_eq!(keys.id(name), Some(i as u32));
+                assert_eq!(keys.name(i as u32), Some(name.as_str()));
+            }
+            assert_eq!(keys.id("key_"), None);
+            assert_eq!(keys.id(&format!("key_{}", n)), None);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "registered twice")]
+    fn should_reject_duplicate_known_keys() {
+        KnownKeys::new(["a", "b", "a"]);
+    }
+}
@@ -1,17 +1,38 @@
 //! # JSON Stream Reader
 //!
 ///////////////////////////
-#[macro_use]
-extern crate lazy_static;
-
-mod arr;
+pub mod array_index;
+#[cfg(feature = "serde")]
+pub mod array_iter;
+pub mod base64;
+pub mod checkpoint;
+pub mod codegen;
 mod constants;
+pub mod cst;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod edit;
 pub mod error;
+pub mod format;
+pub mod infer;
+pub mod json_node;
+pub mod json_pointer;
 pub mod json_stream_reader;
+pub mod json_stream_writer;
 pub mod json_token;
 pub mod json_value;
-mod obj;
-mod other;
+pub mod keys;
+pub mod ndjson;
+#[cfg(feature = "serde")]
+pub mod object_iter;
+pub mod parallel_array;
+mod pattern;
+pub mod raw_value;
+pub mod schema;
+pub mod structural;
+pub mod text;
 mod token;
+pub mod token_buffer;
+pub mod token_reader;
+pub mod tree_builder;
 mod utils;
-mod val;
@@ -1,63 +1,693 @@
-use json_stream_reader::json_stream_reader::*;
+use json_stream_reader::codegen::RustCodegen;
+use json_stream_reader::cst::{parse_cst_with_comments, CstToken};
+use json_stream_reader::error::{Error, ErrorCode};
+use json_stream_reader::infer::SchemaInferrer;
+use json_stream_reader::json_stream_reader::JsonStreamReader;
 use json_stream_reader::json_token::JsonToken;
 use json_stream_reader::json_value::JsonValue;
+use json_stream_reader::ndjson::ParallelNdjson;
+use json_stream_reader::parallel_array::ParallelArray;
+use json_stream_reader::schema::{Schema, Validator, Violation};
+use json_stream_reader::token_reader::TokenReader;
 use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::path;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-fn main() {
-    let dir = env::current_dir().unwrap();
-    let large_file_path = dir.join(path::PathBuf::from("data/large-file.json"));
-    let mut file = File::open(large_file_path).unwrap();
-    let mut buf = [0; 512];
-
-    let obj = vec![];
-    let r = std::cell::RefCell::new(obj);
-    let time = std::time::SystemTime::now();
+const USAGE: &str = "\
+Usage: json_stream_reader <COMMAND> [OPTIONS] [FILE]...
+
+Reads each FILE in turn, or stdin where there is none or FILE is `-`.
+
+Commands:
+  tokens     Prints the tokens of the input, one per line
+  validate   Checks the input is valid JSON, or matches a JSON Schema
+  bench      Times reading the input, which is loaded in memory first
+  codegen    Prints Rust types for the documents of the input
+
+Options:
+  --chunk-size BYTES        Reads the input BYTES at a time [default: 8192]
+  --string-fragments BYTES  Returns strings longer than BYTES in fragments
+  --max-depth N             Rejects documents nested deeper than N
+  --comments                Allows `//` and `/* */` comments, reading each
+                            FILE whole before parsing it
+  --ndjson                  Reads a document per line
+  --threads N               Parses NDJSON, or in `bench` a top-level array,
+                            on N threads
+  --offsets                 tokens: prefixes tokens with the byte ending them
+  --schema FILE             validate: the JSON Schema to match
+  --quiet                   validate: prints nothing, only sets the status
+  --iterations N            bench: reads the input N times [default: 5]
+  --root NAME               codegen: names the top-level type [default: Root]
+  -h, --help                Prints this help
+
+Exit status:
+  0  success
+  1  invalid input, or input not matching the schema
+  2  bad usage, or an unusable schema
+  3  the input could not be read or the output written
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Tokens,
+    Validate,
+    Bench,
+    Codegen,
+}
+
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    files: Vec<String>,
+    chunk_size: Option<usize>,
+    string_fragments: Option<usize>,
+    max_depth: Option<usize>,
+    comments: bool,
+    ndjson: bool,
+    threads: Option<usize>,
+    offsets: bool,
+    schema: Option<String>,
+    quiet: bool,
+    iterations: usize,
+    root: String,
+}
+
+// Why a command failed, which decides the exit status.
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Invalid(String),
+    Input(String),
+    Output(io::Error),
+}
+
+impl Failure {
+    // The failure of reading `file`.
+    fn reading(file: &str, err: Error) -> Self {
+        match err.code {
+            ErrorCode::Io(_) => Failure::Input(format!("{}: {}", name(file), err)),
+            _ => Failure::Invalid(format!("{}: {}", name(file), err)),
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            Failure::Invalid(_) => 1,
+            Failure::Usage(_) => 2,
+            Failure::Input(_) => 3,
+            // the reader of the output is gone, as in `| head`
+            Failure::Output(err) if err.kind() == io::ErrorKind::BrokenPipe => 0,
+            Failure::Output(_) => 3,
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Self {
+        Failure::Output(err)
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, Failure> {
+    let command = match args.first().map(String::as_str) {
+        Some("tokens") => Command::Tokens,
+        Some("validate") => Command::Validate,
+        Some("bench") => Command::Bench,
+        Some("codegen") => Command::Codegen,
+        Some(other) => return Err(Failure::Usage(format!("unknown command {}", other))),
+        None => return Err(Failure::Usage("missing command".to_string())),
+    };
+    let mut options = Options {
+        command,
+        files: vec![],
+        chunk_size: None,
+        string_fragments: None,
+        max_depth: None,
+        comments: false,
+        ndjson: false,
+        threads: None,
+        offsets: false,
+        schema: None,
+        quiet: false,
+        iterations: 5,
+        root: "Root".to_string(),
+    };
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let only = |wanted: Command| match command == wanted {
+            true => Ok(()),
+            false => Err(Failure::Usage(format!(
+                "{} is not an option of this command",
+                arg
+            ))),
+        };
+        match arg.as_str() {
+            "--chunk-size" => options.chunk_size = Some(number(arg, args.next())?),
+            "--string-fragments" => options.string_fragments = Some(number(arg, args.next())?),
+            "--max-depth" => options.max_depth = Some(number(arg, args.next())?),
+            "--comments" => options.comments = true,
+            "--ndjson" => options.ndjson = true,
+            "--threads" => options.threads = Some(number(arg, args.next())?),
+            "--offsets" => {
+                only(Command::Tokens)?;
+                options.offsets = true;
+            }
+            "--schema" => {
+                only(Command::Validate)?;
+                options.schema = Some(value(arg, args.next())?.to_string());
+            }
+            "--quiet" => {
+                only(Command::Validate)?;
+                options.quiet = true;
+            }
+            "--iterations" => {
+                only(Command::Bench)?;
+                options.iterations = number(arg, args.next())?.max(1);
+            }
+            "--root" => {
+                only(Command::Codegen)?;
+                options.root = value(arg, args.next())?.to_string();
+            }
+            "-" => options.files.push(arg.clone()),
+            _ if arg.starts_with('-') => {
+                return Err(Failure::Usage(format!("unknown option {}", arg)))
+            }
+            _ => options.files.push(arg.clone()),
+        }
+    }
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+    if options.ndjson && options.comments {
+        return Err(Failure::Usage(
+            "--comments cannot be used with --ndjson".to_string(),
+        ));
+    }
+    if options.ndjson && (options.offsets || options.string_fragments.is_some()) {
+        let message = "--offsets and --string-fragments cannot be used with --ndjson";
+        return Err(Failure::Usage(message.to_string()));
+    }
+    Ok(options)
+}
+
+fn value<'a>(option: &str, value: Option<&'a String>) -> Result<&'a str, Failure> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| Failure::Usage(format!("{} needs a value", option)))
+}
+
+fn number(option: &str, text: Option<&String>) -> Result<usize, Failure> {
+    let text = value(option, text)?;
+    match text.parse::<usize>() {
+        Ok(n) if n > 0 || option == "--max-depth" => Ok(n),
+        _ => Err(Failure::Usage(format!(
+            "{} needs a positive number, not {}",
+            option, text
+        ))),
+    }
+}
+
+fn name(file: &str) -> &str {
+    match file {
+        "-" => "<stdin>",
+        file => file,
+    }
+}
+
+// Opens `file`, with its comments blanked out in JSONC mode.
+fn open(file: &str, options: &Options) -> Result<Box<dyn Read + Send>, Failure> {
+    let mut source: Box<dyn Read + Send> = match file {
+        "-" => Box::new(io::stdin()),
+        _ => match File::open(file) {
+            Ok(source) => Box::new(source),
+            Err(err) => return Err(Failure::Input(format!("{}: {}", file, err))),
+        },
+    };
+    if !options.comments {
+        return Ok(source);
+    }
+    let mut doc = vec![];
+    if let Err(err) = source.read_to_end(&mut doc) {
+        return Err(Failure::Input(format!("{}: {}", name(file), err)));
+    }
+    let tokens = parse_cst_with_comments(&doc).map_err(|err| Failure::reading(file, err))?;
+    // the blanked out copy keeps the offsets of the document
+    let mut masked = Vec::with_capacity(doc.len());
+    for token in tokens {
+        match token {
+            CstToken::Comment(text) => {
+                masked.extend(text.iter().map(|&b| if b == b'\n' { b'\n' } else { b' ' }))
+            }
+            token => masked.extend_from_slice(token.text()),
+        }
+    }
+    Ok(Box::new(Cursor::new(masked)))
+}
+
+// Reads a whole document from `source`, passing every token and the offset
+// of the byte ending it to `visit`. Returns the number of tokens.
+fn read_document<R, F>(source: R, options: &Options, mut visit: F) -> Result<usize, Error>
+where
+    R: Read,
+    F: FnMut(&JsonToken, usize),
+{
     let mut reader = JsonStreamReader::new();
-    while let Ok(size) = file.read(&mut buf) {
-        if size > 0 {
-            let res = reader.read(&buf[0..size]);
-            match res {
-                Ok(tokens) => {
-                    for token in tokens {
-                        match token {
-                            JsonToken::ObjBeg => r.borrow_mut().push("{".to_string()),
-                            JsonToken::ObjEnd => r.borrow_mut().push("}".to_string()),
-                            JsonToken::ArrBeg => r.borrow_mut().push("[".to_string()),
-                            JsonToken::ArrEnd => r.borrow_mut().push("]".to_string()),
-                            JsonToken::Key(obj_key) => r
-                                .borrow_mut()
-                                .push(format!("key: {:}", obj_key.to_string())),
-                            JsonToken::Val(JsonValue::String(str)) => {
-                                r.borrow_mut().push(format!("str: {:}", str))
-                            }
-                            JsonToken::Val(JsonValue::Number(str)) => r
-                                .borrow_mut()
-                                .push(format!("num: {:}", str.parse::<f32>().unwrap())),
-                            JsonToken::Val(JsonValue::Bool(b)) => {
-                                r.borrow_mut().push(format!("bool: {:}", b))
-                            }
-                            JsonToken::Val(JsonValue::Null) => {
-                                r.borrow_mut().push("null".to_string())
-                            }
-                        }
-                    }
+    if let Some(threshold) = options.string_fragments {
+        reader = reader.with_string_fragments(threshold);
+    }
+    let mut tokens = TokenReader::with_reader(source, reader);
+    if let Some(size) = options.chunk_size {
+        tokens = tokens.with_chunk_size(size);
+    }
+    while let Some(token) = tokens.next_token()? {
+        if options.max_depth.is_some_and(|max| tokens.depth() > max) {
+            return Err(tokens.error(ErrorCode::TooManyTokens));
+        }
+        visit(&token, tokens.token_offset());
+    }
+    if tokens.token_count() == 0 {
+        return Err(tokens.error(ErrorCode::UnexpectedEof));
+    }
+    Ok(tokens.token_count())
+}
+
+// The tokens of every line of the NDJSON `file`, or why it is invalid.
+fn read_records(
+    file: &str,
+    options: &Options,
+) -> Result<impl Iterator<Item = Result<Vec<JsonToken>, Failure>>, Failure> {
+    let source = open(file, options)?;
+    let owned = file.to_string();
+    let max_depth = options.max_depth;
+    Ok(ndjson(options).read(source).map(move |record| {
+        let record = record.map_err(|err| match err.error.code {
+            ErrorCode::Io(_) => Failure::Input(format!("{}: {}", name(&owned), err.error)),
+            _ => Failure::Invalid(format!("{}:{}: {}", name(&owned), err.line, err.error)),
+        })?;
+        if max_depth.is_some_and(|max| depth(&record.tokens) > max) {
+            let (file, line) = (name(&owned), record.line);
+            let message = format!("{}:{}: {}", file, line, ErrorCode::TooManyTokens);
+            return Err(Failure::Invalid(message));
+        }
+        Ok(record.tokens)
+    }))
+}
+
+fn ndjson(options: &Options) -> ParallelNdjson {
+    let mut ndjson = ParallelNdjson::new();
+    if let Some(threads) = options.threads {
+        ndjson = ndjson.with_threads(threads);
+    }
+    if let Some(size) = options.chunk_size {
+        ndjson = ndjson.with_block_size(size);
+    }
+    ndjson
+}
+
+// The deepest nesting of the tokens of a document.
+fn depth(tokens: &[JsonToken]) -> usize {
+    let mut depth = 0;
+    let mut deepest = 0;
+    for token in tokens {
+        match token {
+            JsonToken::ObjBeg | JsonToken::ArrBeg => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            JsonToken::ObjEnd | JsonToken::ArrEnd => depth -= 1,
+            _ => {}
+        }
+    }
+    deepest
+}
+
+fn write_token(out: &mut impl Write, token: &JsonToken) -> io::Result<()> {
+    match token {
+        JsonToken::ObjBeg => writeln!(out, "{{"),
+        JsonToken::ObjEnd => writeln!(out, "}}"),
+        JsonToken::ArrBeg => writeln!(out, "["),
+        JsonToken::ArrEnd => writeln!(out, "]"),
+        JsonToken::Key(key) => writeln!(out, "key {:?}", key),
+        JsonToken::KnownKey(id) => writeln!(out, "key #{}", id),
+        JsonToken::Val(JsonValue::String(text)) => writeln!(out, "string {:?}", text),
+        JsonToken::Val(JsonValue::Number(number)) => writeln!(out, "number {}", number),
+        JsonToken::Val(JsonValue::Bool(b)) => writeln!(out, "{}", b),
+        JsonToken::Val(JsonValue::Null) => writeln!(out, "null"),
+        JsonToken::StrBegin => writeln!(out, "string-begin"),
+        JsonToken::StrChunk(text) => writeln!(out, "string-chunk {:?}", text),
+        JsonToken::StrEnd => writeln!(out, "string-end"),
+        JsonToken::Raw(raw) => writeln!(out, "raw {}", String::from_utf8_lossy(raw)),
+    }
+}
+
+fn tokens(options: &Options, out: &mut impl Write) -> Result<(), Failure> {
+    for file in &options.files {
+        if options.ndjson {
+            for record in read_records(file, options)? {
+                for token in record? {
+                    write_token(out, &token)?;
                 }
-                Err(err) => {
-                    println!("{:?}", err);
-                    break;
+            }
+            continue;
+        }
+        let mut written = Ok(());
+        let source = open(file, options)?;
+        let read = read_document(source, options, |token, offset| {
+            if written.is_ok() {
+                written = match options.offsets {
+                    true => write!(out, "{}\t", offset).and_then(|_| write_token(out, token)),
+                    false => write_token(out, token),
+                };
+            }
+        });
+        // what was read is shown before the error
+        written?;
+        read.map_err(|err| Failure::reading(file, err))?;
+    }
+    Ok(())
+}
+
+// Checks every file, reporting all the invalid ones and lines, and fails
+// with the worst failure found.
+fn validate(options: &Options, out: &mut impl Write) -> Result<(), Failure> {
+    let schema = match &options.schema {
+        Some(file) => {
+            let text = fs::read_to_string(file)
+                .map_err(|err| Failure::Usage(format!("{}: {}", file, err)))?;
+            let schema = text.parse::<Schema>();
+            Some(schema.map_err(|err| Failure::Usage(format!("{}: {}", file, err)))?)
+        }
+        None => None,
+    };
+    let mut worst: Option<Failure> = None;
+    let mut report = |failure: Failure| {
+        if let Failure::Invalid(message) | Failure::Input(message) = &failure {
+            if !message.is_empty() && (!options.quiet || matches!(failure, Failure::Input(_))) {
+                eprintln!("{}", message);
+            }
+        }
+        if worst
+            .as_ref()
+            .map_or(true, |worst| worst.exit_code() < failure.exit_code())
+        {
+            worst = Some(failure);
+        }
+    };
+    for file in &options.files {
+        let res = match &schema {
+            Some(schema) if options.ndjson => validate_lines(file, schema, options, out),
+            Some(schema) => open(file, options).and_then(|source| {
+                let violations = violations(source, schema, options)
+                    .map_err(|err| Failure::reading(file, err))?;
+                write_violations(out, name(file), &violations, options)
+            }),
+            None if options.ndjson => match read_records(file, options) {
+                Ok(records) => {
+                    records.filter_map(Result::err).for_each(&mut report);
+                    Ok(())
                 }
+                Err(failure) => Err(failure),
+            },
+            None => open(file, options).and_then(|source| {
+                let read = read_document(source, options, |_, _| {});
+                read.map(drop).map_err(|err| Failure::reading(file, err))
+            }),
+        };
+        match res {
+            Err(Failure::Output(err)) => return Err(Failure::Output(err)),
+            Err(failure) => report(failure),
+            Ok(()) => {}
+        }
+    }
+    match worst {
+        Some(Failure::Invalid(_)) => Err(Failure::Invalid(String::new())),
+        Some(Failure::Input(_)) => Err(Failure::Input(String::new())),
+        Some(failure) => Err(failure),
+        None => Ok(()),
+    }
+}
+
+fn violations<R: Read>(
+    source: R,
+    schema: &Schema,
+    options: &Options,
+) -> Result<Vec<Violation>, Error> {
+    let mut validator = Validator::new(schema);
+    let mut found = vec![];
+    read_document(source, options, |token, offset| {
+        found.extend_from_slice(validator.push(token, offset));
+    })?;
+    Ok(found)
+}
+
+// Prints the violations, failing if there is any.
+fn write_violations(
+    out: &mut impl Write,
+    prefix: &str,
+    violations: &[Violation],
+    options: &Options,
+) -> Result<(), Failure> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    if !options.quiet {
+        for violation in violations {
+            writeln!(out, "{}: {}", prefix, violation)?;
+        }
+    }
+    Err(Failure::Invalid(String::new()))
+}
+
+// Validates the lines of an NDJSON file one by one, so that violations are
+// located in their line.
+fn validate_lines(
+    file: &str,
+    schema: &Schema,
+    options: &Options,
+    out: &mut impl Write,
+) -> Result<(), Failure> {
+    let mut res = Ok(());
+    let lines = BufReader::new(open(file, options)?).split(b'\n');
+    for (n, line) in lines.enumerate() {
+        let line = line.map_err(|err| Failure::Input(format!("{}: {}", name(file), err)))?;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let prefix = format!("{}:{}", name(file), n + 1);
+        let found = match violations(line.as_slice(), schema, options) {
+            Ok(violations) => write_violations(out, &prefix, &violations, options),
+            Err(err) => Err(Failure::Invalid(format!("{}: {}", prefix, err))),
+        };
+        match found {
+            Err(Failure::Invalid(message)) => {
+                if !message.is_empty() && !options.quiet {
+                    eprintln!("{}", message);
+                }
+                res = Err(Failure::Invalid(String::new()));
+            }
+            found => found?,
+        }
+    }
+    res
+}
+
+fn bench(options: &Options, out: &mut impl Write) -> Result<(), Failure> {
+    for file in &options.files {
+        let mut data = vec![];
+        open(file, options)?
+            .read_to_end(&mut data)
+            .map_err(|err| Failure::Input(format!("{}: {}", name(file), err)))?;
+        let data: Arc<[u8]> = data.into();
+        let mut times = vec![];
+        let mut count = 0;
+        for _ in 0..options.iterations {
+            let start = Instant::now();
+            count = match (options.ndjson, options.threads) {
+                (true, _) => count_records(file, &data, options)?,
+                (false, Some(threads)) => ParallelArray::new()
+                    .with_threads(threads)
+                    .parse(&data)
+                    .map_err(|err| Failure::reading(file, err))?
+                    .len(),
+                (false, None) => read_document(&data[..], options, |_, _| {})
+                    .map_err(|err| Failure::reading(file, err))?,
+            };
+            times.push(start.elapsed());
+        }
+        let best = times.iter().min().copied().unwrap_or_default();
+        let mean = times.iter().sum::<Duration>() / times.len() as u32;
+        let throughput = data.len() as f64 / best.as_secs_f64().max(f64::EPSILON) / 1e6;
+        writeln!(
+            out,
+            "{}: {} bytes, {} tokens, best {:.3?}, mean {:.3?}, {:.1} MB/s",
+            name(file),
+            data.len(),
+            count,
+            best,
+            mean,
+            throughput
+        )?;
+    }
+    Ok(())
+}
+
+fn count_records(file: &str, data: &Arc<[u8]>, options: &Options) -> Result<usize, Failure> {
+    let mut count = 0;
+    for record in ndjson(options).read(Cursor::new(Arc::clone(data))) {
+        match record {
+            Ok(record) => count += record.tokens.len(),
+            Err(err) => {
+                let message = format!("{}:{}: {}", name(file), err.line, err.error);
+                return Err(Failure::Invalid(message));
+            }
+        }
+    }
+    Ok(count)
+}
+
+// Prints Rust types for all the documents of the input.
+fn codegen(options: &Options, out: &mut impl Write) -> Result<(), Failure> {
+    let mut inferrer = SchemaInferrer::new();
+    for file in &options.files {
+        if options.ndjson {
+            for tokens in read_records(file, options)? {
+                tokens?.iter().for_each(|token| inferrer.push(token));
             }
         } else {
-            break;
+            let source = open(file, options)?;
+            read_document(source, options, |token, _| inferrer.push(token))
+                .map_err(|err| Failure::reading(file, err))?;
+        }
+    }
+    write!(
+        out,
+        "{}",
+        RustCodegen::new(&options.root).generate(&inferrer.schema())
+    )?;
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+    let options = parse_args(args)?;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match options.command {
+        Command::Tokens => tokens(&options, &mut out),
+        Command::Validate => validate(&options, &mut out),
+        Command::Bench => bench(&options, &mut out),
+        Command::Codegen => codegen(&options, &mut out),
+    }?;
+    out.flush()?;
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let Err(failure) = run(&args) else {
+        return;
+    };
+    match &failure {
+        Failure::Usage(message) => eprintln!("error: {}\n\n{}", message, USAGE),
+        Failure::Invalid(message) | Failure::Input(message) if !message.is_empty() => {
+            eprintln!("error: {}", message)
+        }
+        Failure::Output(err) if failure.exit_code() != 0 => eprintln!("error: {}", err),
+        _ => {}
+    }
+    process::exit(failure.exit_code());
+}
+
+#[cfg(test)]
+mod main_tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, Failure> {
+        parse_args(
+            &args
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn usage_error(args: &str) -> String {
+        match parse(args) {
+            Err(Failure::Usage(message)) => message,
+            res => panic!("{args}: {res:?}"),
+        }
+    }
+
+    #[test]
+    fn should_parse_options_of_the_command() {
+        let options = parse("validate --schema s.json --max-depth 0 --quiet a.json -").unwrap();
+        assert_eq!(options.command, Command::Validate);
+        assert_eq!(options.schema.as_deref(), Some("s.json"));
+        assert_eq!(options.max_depth, Some(0));
+        assert!(options.quiet);
+        assert_eq!(options.files, ["a.json", "-"]);
+        let options = parse("tokens --chunk-size 16 --string-fragments 4").unwrap();
+        assert_eq!(options.chunk_size, Some(16));
+        assert_eq!(options.string_fragments, Some(4));
+        assert_eq!(options.files, ["-"]);
+        assert_eq!(parse("codegen --root Doc").unwrap().root, "Doc");
+    }
+
+    #[test]
+    fn should_reject_bad_usage() {
+        assert_eq!(usage_error(""), "missing command");
+        assert_eq!(usage_error("check a.json"), "unknown command check");
+        assert_eq!(usage_error("tokens --frob"), "unknown option --frob");
+        assert_eq!(
+            usage_error("tokens --chunk-size"),
+            "--chunk-size needs a value"
+        );
+        assert_eq!(
+            usage_error("bench --chunk-size 0"),
+            "--chunk-size needs a positive number, not 0"
+        );
+        assert_eq!(
+            usage_error("tokens --schema s.json"),
+            "--schema is not an option of this command"
+        );
+        assert_eq!(
+            usage_error("validate --ndjson --comments"),
+            "--comments cannot be used with --ndjson"
+        );
+        assert_eq!(
+            usage_error("tokens --ndjson --offsets"),
+            "--offsets and --string-fragments cannot be used with --ndjson"
+        );
+    }
+
+    #[test]
+    fn should_map_failures_to_exit_codes() {
+        let output = |kind| Failure::Output(io::Error::from(kind));
+        assert_eq!(Failure::Invalid(String::new()).exit_code(), 1);
+        assert_eq!(Failure::Usage(String::new()).exit_code(), 2);
+        assert_eq!(Failure::Input(String::new()).exit_code(), 3);
+        assert_eq!(output(io::ErrorKind::BrokenPipe).exit_code(), 0);
+        assert_eq!(output(io::ErrorKind::Other).exit_code(), 3);
+        let io = Error {
+            code: ErrorCode::Io(io::ErrorKind::Other),
+            column: 0,
+        };
+        assert_eq!(Failure::reading("-", io).exit_code(), 3);
+        let syntax = Error {
+            code: ErrorCode::ExpectedAnyTerm,
+            column: 3,
+        };
+        match Failure::reading("-", syntax) {
+            Failure::Invalid(message) => {
+                assert_eq!(message, "<stdin>: expected a value at column 3")
+            }
+            failure => panic!("{failure:?}"),
         }
     }
-    println!("took: {:?}", time.elapsed().unwrap());
-    println!("Number of nodes: {:?}", r.borrow().len());
-    let data = &*r.borrow();
-    data.into_iter()
-        .skip(data.len() - 100)
-        .for_each(|s| println!("{}", s));
 }
@@ -0,0 +1,391 @@
+//! Parallel reading of newline-delimited JSON.
+//!
+//! Every line of an NDJSON input is a document of its own, so lines can be
+//! parsed in any order. `ParallelNdjson` reads the input on a thread of its
+//! own, cut into large blocks ending at a newline, and parses the blocks on
+//! worker threads, each with its own `JsonStreamReader`. `NdjsonRecords`
+//! hands the records out in input order. Blocks are read ahead of the
+//! consumer up to a limit, which bounds memory however large the input.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::{thread, vec};
+
+use memchr::{memchr_iter, memrchr};
+
+use crate::error::{Error, ErrorCode};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+
+const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+
+/// The tokens of one line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Line in the whole input, from 1.
+    pub line: usize,
+    pub tokens: Vec<JsonToken>,
+}
+
+/// A line that is not a valid document, or a failure to read the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordError {
+    /// Line in the whole input, from 1.
+    pub line: usize,
+    /// Located at a byte of the line, from 0.
+    pub error: Error,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+pub type RecordResult = std::result::Result<Record, RecordError>;
+
+// The records of a block, lines being counted from 0 in the block.
+struct Block {
+    // number of newlines
+    lines: usize,
+    records: Vec<RecordResult>,
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Block")
+            .field("lines", &self.lines)
+            .field("records", &self.records.len())
+            .finish()
+    }
+}
+
+type Parsed = (usize, io::Result<Block>);
+
+/// Options of a parallel read, see the module docs.
+#[derive(Debug, Clone)]
+pub struct ParallelNdjson {
+    threads: usize,
+    block_size: usize,
+    blocks_in_flight: Option<usize>,
+}
+
+impl ParallelNdjson {
+    /// Parses on as many threads as the machine runs in parallel.
+    pub fn new() -> Self {
+        ParallelNdjson {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            block_size: DEFAULT_BLOCK_SIZE,
+            blocks_in_flight: None,
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets the number of bytes in a block, which is extended to the end of
+    /// its last line.
+    pub fn with_block_size(mut self, size: usize) -> Self {
+        self.block_size = size.max(1);
+        self
+    }
+
+    /// Sets how many blocks may be read but not handed out yet, twice the
+    /// number of threads by default.
+    pub fn with_blocks_in_flight(mut self, blocks: usize) -> Self {
+        self.blocks_in_flight = Some(blocks.max(1));
+        self
+    }
+
+    /// Starts reading `source`. Blank lines are skipped. After an error
+    /// reading the source, no more records are returned.
+    pub fn read<R: Read + Send + 'static>(&self, source: R) -> NdjsonRecords {
+        let (job_tx, job_rx) = mpsc::sync_channel(self.threads);
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (parsed_tx, parsed_rx) = mpsc::channel();
+        let (permit_tx, permit_rx) = mpsc::channel();
+        for _ in 0..self.blocks_in_flight.unwrap_or(2 * self.threads) {
+            permit_tx.send(()).unwrap_or_default();
+        }
+        for _ in 0..self.threads {
+            let jobs = Arc::clone(&job_rx);
+            let parsed = parsed_tx.clone();
+            thread::spawn(move || parse_blocks(&jobs, &parsed));
+        }
+        let block_size = self.block_size;
+        thread::spawn(move || split(source, block_size, &permit_rx, &job_tx, &parsed_tx));
+        NdjsonRecords {
+            parsed: parsed_rx,
+            permits: permit_tx,
+            pending: BTreeMap::new(),
+            next: 0,
+            line: 1,
+            current: vec![].into_iter(),
+            failed: false,
+        }
+    }
+}
+
+impl Default for ParallelNdjson {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The records of a parallel read, in input order. Dropping it stops the
+/// threads once they are done with their current block.
+#[derive(Debug)]
+pub struct NdjsonRecords {
+    parsed: Receiver<Parsed>,
+    // given back for every block handed out
+    permits: Sender<()>,
+    // blocks parsed ahead of the next one
+    pending: BTreeMap<usize, io::Result<Block>>,
+    next: usize,
+    // line of the first line of the next block
+    line: usize,
+    current: vec::IntoIter<RecordResult>,
+    failed: bool,
+}
+
+impl Iterator for NdjsonRecords {
+    type Item = RecordResult;
+
+    fn next(&mut self) -> Option<RecordResult> {
+        loop {
+            if let Some(record) = self.current.next() {
+                return Some(record);
+            }
+            if self.failed {
+                return None;
+            }
+            let block = loop {
+                if let Some(block) = self.pending.remove(&self.next) {
+                    break block;
+                }
+                let (seq, block) = self.parsed.recv().ok()?;
+                self.pending.insert(seq, block);
+            };
+            self.next += 1;
+            self.permits.send(()).unwrap_or_default();
+            match block {
+                Ok(block) => {
+                    let base = self.line;
+                    self.line += block.lines;
+                    let mut records = block.records;
+                    for record in &mut records {
+                        match record {
+                            Ok(Record { line, .. }) | Err(RecordError { line, .. }) => {
+                                *line += base
+                            }
+                        }
+                    }
+                    self.current = records.into_iter();
+                }
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(RecordError {
+                        line: self.line,
+                        error: Error {
+                            code: ErrorCode::Io(err.kind()),
+                            column: 0,
+                        },
+                    }));
+                }
+            }
+        }
+    }
+}
+
+// Reads the source into blocks ending after a newline, or at the end of the
+// input, each one once a permit is given.
+fn split<R: Read>(
+    mut source: R,
+    block_size: usize,
+    permits: &Receiver<()>,
+    jobs: &SyncSender<(usize, Vec<u8>)>,
+    parsed: &Sender<Parsed>,
+) {
+    let mut carry = vec![];
+    for seq in 0.. {
+        if permits.recv().is_err() {
+            return;
+        }
+        let mut block = std::mem::take(&mut carry);
+        let eof = loop {
+            let start = block.len();
+            // the line left over from the previous block has no newline
+            let want = block_size.saturating_sub(start).max(block_size / 4).max(1);
+            let read = match source.by_ref().take(want as u64).read_to_end(&mut block) {
+                Ok(read) => read,
+                Err(err) => {
+                    parsed.send((seq, Err(err))).unwrap_or_default();
+                    return;
+                }
+            };
+            if read < want {
+                break true;
+            }
+            if block.len() >= block_size && memrchr(b'\n', &block[start..]).is_some() {
+                break false;
+            }
+        };
+        if !eof {
+            let end = memrchr(b'\n', &block).map_or(block.len(), |i| i + 1);
+            carry = block.split_off(end);
+        }
+        if jobs.send((seq, block)).is_err() || eof {
+            return;
+        }
+    }
+}
+
+fn parse_blocks(jobs: &Mutex<Receiver<(usize, Vec<u8>)>>, parsed: &Sender<Parsed>) {
+    let mut reader = JsonStreamReader::new();
+    loop {
+        let job = jobs.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        let Ok((seq, block)) = job else {
+            return;
+        };
+        let records = parse_block(&mut reader, &block);
+        if parsed.send((seq, Ok(records))).is_err() {
+            return;
+        }
+    }
+}
+
+fn parse_block(reader: &mut JsonStreamReader, block: &[u8]) -> Block {
+    let mut records = vec![];
+    let mut lines = 0;
+    let mut start = 0;
+    let ends = memchr_iter(b'\n', block).chain(Some(block.len()));
+    for (line, end) in ends.enumerate() {
+        let text = &block[start..end];
+        lines = line;
+        start = end + 1;
+        if text.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        reader.clear();
+        let res = match reader.read(text) {
+            Ok(tokens) if reader.depth() == 0 => Ok(Record { line, tokens }),
+            Ok(_) => Err(Error {
+                code: ErrorCode::UnexpectedEof,
+                column: text.len(),
+            }),
+            Err(error) => Err(error),
+        };
+        records.push(res.map_err(|error| RecordError { line, error }));
+    }
+    Block { lines, records }
+}
+
+#[cfg(test)]
+mod ndjson_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn sequential(input: &[u8]) -> Vec<RecordResult> {
+        let mut reader = JsonStreamReader::new();
+        let mut block = parse_block(&mut reader, input);
+        for record in &mut block.records {
+            match record {
+                Ok(Record { line, .. }) | Err(RecordError { line, .. }) => *line += 1,
+            }
+        }
+        block.records
+    }
+
+    #[test]
+    fn should_return_records_in_order() {
+        let mut input = vec![];
+        for i in 0..2000 {
+            let pad = " ".repeat(i % 37);
+            input.extend(format!("{{\"n\": {i},{pad}\"s\": [\"{pad}\"]}}\n").bytes());
+            if i % 100 == 0 {
+                input.extend(b"\r\n  \n");
+            }
+        }
+        input.extend(b"[true]");
+        let records = ParallelNdjson::new()
+            .with_threads(4)
+            .with_block_size(100)
+            .with_blocks_in_flight(3)
+            .read(io::Cursor::new(input.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2001);
+        assert_eq!(records, sequential(&input));
+        assert_eq!(records[2000].as_ref().unwrap().line, 2041);
+    }
+
+    #[test]
+    fn should_report_errors_with_global_lines() {
+        let input = b"[1]\n{\"a\": tru}\n\n[2]\n{\"b\": [\n[3] [4]\n";
+        let records = ParallelNdjson::new()
+            .with_threads(2)
+            .with_block_size(4)
+            .read(&input[..])
+            .collect::<Vec<_>>();
+        let lines = records
+            .iter()
+            .map(|record| match record {
+                Ok(record) => (record.line, None),
+                Err(err) => (err.line, Some(err.error.clone())),
+            })
+            .collect::<Vec<_>>();
+        let error = |code, column| Some(Error { code, column });
+        assert_eq!(
+            lines,
+            [
+                (1, None),
+                (2, error(ErrorCode::ExpectedTrue, 9)),
+                (4, None),
+                (5, error(ErrorCode::UnexpectedEof, 7)),
+                (6, error(ErrorCode::ExpectedCommaOrObjectEndOrArrayEnd, 4)),
+            ]
+        );
+        assert_eq!(
+            records[3].as_ref().unwrap_err().to_string(),
+            "line 5: unexpected end of input at column 7"
+        );
+    }
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Read for Counted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let at = self.0.fetch_add(buf.len(), Ordering::SeqCst);
+            if at > 1 << 20 {
+                return Err(io::Error::other("too far"));
+            }
+            for (i, ch) in buf.iter_mut().enumerate() {
+                *ch = if (at + i) % 8 == 7 { b'\n' } else { b' ' };
+            }
+            buf[..1].fill(b'[');
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn should_bound_read_ahead_and_stop_on_io_error() {
+        let read = Arc::new(AtomicUsize::new(0));
+        let mut records = ParallelNdjson::new()
+            .with_threads(2)
+            .with_block_size(1000)
+            .with_blocks_in_flight(2)
+            .read(Counted(Arc::clone(&read)));
+        assert!(records.next().is_some());
+        thread::sleep(Duration::from_millis(100));
+        assert!(read.load(Ordering::SeqCst) <= 5 * 1000);
+        let last = records.last().unwrap().unwrap_err();
+        assert_eq!(last.error.code, ErrorCode::Io(io::ErrorKind::Other));
+    }
+}
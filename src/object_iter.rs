@@ -0,0 +1,211 @@
+//! Typed iteration over the members of a (huge) object, like ijson's
+//! `kvitems`.
+//!
+//! Only the value of the current member is materialized, so objects with
+//! millions of entries can be streamed in constant memory.
+use std::io::Read;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use crate::de::from_element;
+use crate::error::{ErrorCode, Result};
+use crate::json_pointer::JsonPointer;
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::token_reader::TokenReader;
+
+pub struct ObjectIter<'a, T, R> {
+    tokens: TokenReader<R, &'a mut JsonStreamReader>,
+    // the pointer is resolved on the first call to `next`
+    pointer: Option<Result<JsonPointer>>,
+    // nesting level of the object members
+    depth: usize,
+    done: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl JsonStreamReader {
+    /// Iterates over the `(key, value)` pairs of the top-level object read
+    /// from `source`.
+    pub fn iter_object<T, R>(&mut self, source: R) -> ObjectIter<'_, T, R>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        ObjectIter::new(self, source, Ok(JsonPointer::root()))
+    }
+
+    /// Iterates over the `(key, value)` pairs of the object at `pointer`.
+    pub fn iter_object_at<T, R>(&mut self, source: R, pointer: &str) -> ObjectIter<'_, T, R>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        ObjectIter::new(self, source, JsonPointer::parse(pointer))
+    }
+}
+
+impl<'a, T: DeserializeOwned, R: Read> ObjectIter<'a, T, R> {
+    fn new(reader: &'a mut JsonStreamReader, source: R, pointer: Result<JsonPointer>) -> Self {
+        reader.clear();
+        ObjectIter {
+            tokens: TokenReader::with_reader(source, reader).with_raw_values(),
+            pointer: Some(pointer),
+            depth: 0,
+            done: false,
+            marker: PhantomData,
+        }
+    }
+
+    // Moves to the first member of the object.
+    fn start(&mut self, pointer: Result<JsonPointer>) -> Result<()> {
+        self.tokens.seek(&pointer?)?;
+        match self.tokens.next_token()? {
+            Some(JsonToken::ObjBeg) => {
+                self.depth = self.tokens.depth();
+                Ok(())
+            }
+            _ => Err(self.tokens.error(ErrorCode::ExpectedObject)),
+        }
+    }
+
+    fn next_member(&mut self) -> Result<Option<(String, T)>> {
+        if let Some(pointer) = self.pointer.take() {
+            self.start(pointer)?;
+        }
+        match self
+            .tokens
+            .next_token()?
+            .map(|token| self.tokens.named(token))
+        {
+            Some(JsonToken::Key(key)) => {
+                let value = from_element(&mut self.tokens, self.depth)?;
+                Ok(Some((key.into_string(), value)))
+            }
+            Some(JsonToken::ObjEnd) => Ok(None),
+            Some(_) => Err(self.tokens.error(ErrorCode::ExpectedKey)),
+            None => Err(self.tokens.error(ErrorCode::UnexpectedEof)),
+        }
+    }
+}
+
+impl<T: DeserializeOwned, R: Read> Iterator for ObjectIter<'_, T, R> {
+    type Item = Result<(String, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_member() {
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Ok(Some(member)) => Some(Ok(member)),
+            Err(err) => {
+                // there is nothing to resume from if the object wasn't reached
+                if self.depth == 0 || self.tokens.is_failed() {
+                    self.done = true;
+                }
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod object_iter_tests {
+    use super::*;
+    use crate::json_node::JsonNode;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct User {
+        name: String,
+    }
+
+    #[test]
+    fn should_iterate_top_level_object() {
+        let buf = r#"{"u1": {"name": "Ann"}, "u2": {"name": "Bob", "age": 7}}"#;
+        let mut reader = JsonStreamReader::new();
+        let users = reader
+            .iter_object::<User, _>(buf.as_bytes())
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            users.unwrap(),
+            vec![
+                (
+                    "u1".to_string(),
+                    User {
+                        name: "Ann".to_string()
+                    }
+                ),
+                (
+                    "u2".to_string(),
+                    User {
+                        name: "Bob".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_iterate_nested_object() {
+        let buf = r#"{"version": 1, "counts": {"a": 1, "b": 2}}"#;
+        let mut reader = JsonStreamReader::new();
+        let counts = reader
+            .iter_object_at::<u32, _>(buf.as_bytes(), "/counts")
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            counts.unwrap(),
+            vec![("a".to_string(), 1), ("b".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn should_yield_json_nodes() {
+        let buf = r#"{"u1": {"tags": ["a"]}, "u2": null}"#;
+        let mut reader = JsonStreamReader::new();
+        let users = reader
+            .iter_object::<JsonNode, _>(buf.as_bytes())
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            users.unwrap(),
+            vec![
+                (
+                    "u1".to_string(),
+                    JsonNode::Object(vec![(
+                        "tags".to_string(),
+                        JsonNode::Array(vec![JsonNode::String("a".to_string())])
+                    )])
+                ),
+                ("u2".to_string(), JsonNode::Null),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_skip_malformed_values() {
+        let buf = r#"{"a": 1, "b": [true], "c": 3}"#;
+        let mut reader = JsonStreamReader::new();
+        let res = reader
+            .iter_object::<u32, _>(buf.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(res.len(), 3);
+        assert!(res[1].is_err());
+        assert_eq!(res[2], Ok(("c".to_string(), 3)));
+    }
+
+    #[test]
+    fn should_return_expected_object() {
+        let buf = r#"[1, 2]"#;
+        let mut reader = JsonStreamReader::new();
+        let res = reader
+            .iter_object::<u32, _>(buf.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].as_ref().unwrap_err().code, ErrorCode::ExpectedObject);
+    }
+}
@@ -0,0 +1,254 @@
+//! Speculative parallel parsing of a single large array.
+//!
+//! The input is cut at arbitrary offsets. For every cut, a worker guesses
+//! whether it fell inside a string and looks for an element boundary: a comma
+//! at the lowest depth reached in its chunk, hopefully the depth of the
+//! array's elements. Each chunk is then parsed on its own, assuming it starts
+//! right after a comma of the top-level array. Chunks are stitched in order,
+//! a chunk being kept only if the previous one ended in exactly the state it
+//! assumed. Once a guess turns out wrong, the rest of the input is parsed
+//! sequentially, so the tokens are always those of a sequential parse.
+use std::num::NonZeroUsize;
+use std::thread;
+
+use memchr::memchr;
+
+use crate::checkpoint::Checkpoint;
+use crate::error::{Error, Result};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::token::{Lexeme, Nesting, State, Token};
+
+const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Options of a parallel parse, see the module docs.
+#[derive(Debug, Clone)]
+pub struct ParallelArray {
+    threads: usize,
+    chunk_size: usize,
+}
+
+// The parse of a chunk, and the state it ended in.
+struct Chunk {
+    start: usize,
+    tokens: Result<Vec<JsonToken>>,
+    end: Checkpoint,
+}
+
+impl ParallelArray {
+    /// Parses on as many threads as the machine runs in parallel.
+    pub fn new() -> Self {
+        ParallelArray {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets the least number of bytes given to a thread, smaller inputs are
+    /// parsed on fewer threads.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Returns the same as `JsonStreamReader::new().read(input)`, errors
+    /// included.
+    pub fn parse(&self, input: &[u8]) -> Result<Vec<JsonToken>> {
+        self.speculate(input).0
+    }
+
+    // The tokens, and whether a guess was wrong.
+    fn speculate(&self, input: &[u8]) -> (Result<Vec<JsonToken>>, bool) {
+        let count = self.threads.min(input.len() / self.chunk_size).max(1);
+        let array = input.iter().find(|ch| !ch.is_ascii_whitespace()) == Some(&b'[');
+        if count == 1 || !array {
+            return (JsonStreamReader::new().read(input), false);
+        }
+        let cuts = (0..=count)
+            .map(|k| k * input.len() / count)
+            .collect::<Vec<_>>();
+        let mut starts = thread::scope(|scope| {
+            let workers = cuts[1..count]
+                .iter()
+                .zip(&cuts[2..])
+                .map(|(&from, &to)| scope.spawn(move || boundary(input, from, to)))
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .filter_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        starts.insert(0, 0);
+        starts.push(input.len());
+        let chunks = thread::scope(|scope| {
+            let workers = starts
+                .windows(2)
+                .map(|range| scope.spawn(|| parse_chunk(input, range[0], range[1])))
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let mut tokens = vec![];
+        let mut state = None;
+        for chunk in chunks {
+            match state {
+                Some(end) if end != element_start(chunk.start) => {
+                    let mut reader = JsonStreamReader::resume(&end);
+                    let rest = reader.read(&input[chunk.start..]);
+                    let rest = rest.map_err(|err| shift(err, chunk.start));
+                    tokens.extend(match rest {
+                        Ok(rest) => rest,
+                        Err(err) => return (Err(err), true),
+                    });
+                    return (Ok(tokens), true);
+                }
+                _ => match chunk.tokens {
+                    Ok(chunk_tokens) => tokens.extend(chunk_tokens),
+                    Err(err) => return (Err(shift(err, chunk.start)), false),
+                },
+            }
+            state = Some(chunk.end);
+        }
+        (Ok(tokens), false)
+    }
+}
+
+impl Default for ParallelArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The state right after a comma of the top-level array.
+fn element_start(offset: usize) -> Checkpoint {
+    let mut stack = Nesting::default();
+    stack.push(Token::Arr);
+    Checkpoint {
+        offset,
+        stack,
+        state: State::ArrNext,
+        lexeme: Lexeme::None,
+        scratch: vec![],
+        capture: false,
+    }
+}
+
+fn parse_chunk(input: &[u8], start: usize, end: usize) -> Chunk {
+    let mut reader = match start {
+        0 => JsonStreamReader::new(),
+        _ => JsonStreamReader::resume(&element_start(start)),
+    };
+    Chunk {
+        start,
+        tokens: reader.read(&input[start..end]),
+        end: reader.checkpoint(),
+    }
+}
+
+// Errors of a chunk are located in the chunk.
+fn shift(err: Error, start: usize) -> Error {
+    Error {
+        code: err.code,
+        column: err.column + start,
+    }
+}
+
+// Guesses whether `at` falls inside a string from the first quote after it:
+// a closing quote is followed by `:`, `,`, `]` or `}`.
+fn in_string(input: &[u8], at: usize) -> bool {
+    let Some(quote) = memchr(b'"', &input[at..]) else {
+        return false;
+    };
+    let next = input[at + quote + 1..]
+        .iter()
+        .find(|ch| !ch.is_ascii_whitespace());
+    matches!(next, Some(b':' | b',' | b']' | b'}'))
+}
+
+// Finds the end of the first comma in `from..to` at the lowest depth reached
+// there, which is the depth of the elements if the chunk closes one.
+fn boundary(input: &[u8], from: usize, to: usize) -> Option<usize> {
+    let mut string = in_string(input, from);
+    let mut escaped = false;
+    let (mut depth, mut lowest) = (0isize, 0isize);
+    let mut found = None;
+    for (i, &ch) in input[from..to].iter().enumerate() {
+        if string {
+            match ch {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            b'"' => string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth < lowest {
+                    lowest = depth;
+                    found = None;
+                }
+            }
+            b',' if depth == lowest && found.is_none() => found = Some(from + i + 1),
+            _ => {}
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod parallel_array_tests {
+    use super::*;
+
+    fn check(input: &[u8], chunk_size: usize) -> bool {
+        let (tokens, fell_back) = ParallelArray::new()
+            .with_threads(5)
+            .with_chunk_size(chunk_size)
+            .speculate(input);
+        assert_eq!(tokens, JsonStreamReader::new().read(input));
+        fell_back
+    }
+
+    #[test]
+    fn should_match_sequential_parse() {
+        let mut input = b"[".to_vec();
+        for i in 0..500 {
+            let text =
+                format!(r#"{{"id": {i}, "tags": ["a, ]", "\"}}"], "n": {{"x": [1.5, null]}}}}, "#);
+            input.extend(text.bytes());
+        }
+        input.extend(b"true]\n");
+        for chunk_size in [10, 97, 1000, 1 << 20] {
+            assert!(!check(&input, chunk_size));
+        }
+        // cuts inside strings that look like the end of one
+        let strings = format!("[{}\"\"]", r#""\" , ", "#.repeat(400));
+        check(strings.as_bytes(), 50);
+    }
+
+    #[test]
+    fn should_fall_back_on_wrong_guess() {
+        let deep = format!("[[{}]]", "[1, 2], ".repeat(300) + "0");
+        assert!(check(deep.as_bytes(), 100));
+        let mut broken = br#"[{"a": 1}, "#.repeat(300);
+        broken.extend(br#"{"a": 1 "b"}]"#);
+        let (tokens, _) = ParallelArray::new()
+            .with_threads(4)
+            .with_chunk_size(100)
+            .speculate(&broken);
+        assert_eq!(tokens, JsonStreamReader::new().read(&broken));
+        assert!(tokens.is_err());
+        check(b"{\"a\": [1, 2, 3]}", 1);
+        check(b"[1, 2, 3", 1);
+    }
+}
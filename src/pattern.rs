@@ -0,0 +1,548 @@
+//! Regular expressions for the `pattern` keyword of JSON Schema.
+//!
+//! Covers the usual subset of ECMA-262 syntax: literals, `.`, classes such
+//! as `[^a-z\d]`, `\d`, `\w` and `\s`, groups, `|`, anchors and greedy or
+//! lazy quantifiers. Back-references, lookaround and word boundaries are
+//! rejected. Patterns are compiled to a program run as an NFA, in time linear
+//! in the length of the text.
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Mutex;
+
+// Counted repetitions are unrolled, up to this bound.
+const MAX_REPEAT: u32 = 1000;
+
+// Longest program, nested repetitions multiplying their bounds.
+const MAX_PROGRAM: usize = 100_000;
+
+// Deepest nesting of groups, the parser recurses once per group.
+const MAX_NESTING: usize = 100;
+
+#[derive(Debug)]
+pub(crate) struct Pattern {
+    source: String,
+    program: Vec<Inst>,
+    // the thread lists of the last text, to be reused
+    spare: Mutex<Option<(Threads, Threads)>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Class(Class),
+    Split(usize, usize),
+    Jump(usize),
+    Start,
+    End,
+    Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    negated: bool,
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+    // `.`, anything but a line terminator
+    Dot,
+}
+
+#[derive(Debug)]
+enum Node {
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+impl Pattern {
+    /// Compiles `source`, `None` if it is malformed or unsupported.
+    pub(crate) fn new(source: &str) -> Option<Pattern> {
+        let mut parser = Parser {
+            chars: source.chars().peekable(),
+            depth: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.chars.next().is_some() || size(&node) >= MAX_PROGRAM {
+            return None;
+        }
+        let mut program = vec![];
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Some(Pattern {
+            source: source.to_string(),
+            program,
+            spare: Mutex::new(None),
+        })
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern matches somewhere in `text`.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        // a pattern checking texts on several threads at once allocates
+        let spare = self
+            .spare
+            .try_lock()
+            .ok()
+            .and_then(|mut spare| spare.take());
+        let (mut current, mut next) = spare.unwrap_or_else(|| {
+            let len = self.program.len();
+            (Threads::new(len), Threads::new(len))
+        });
+        current.clear();
+        next.clear();
+        let found = self.run(text, &mut current, &mut next);
+        if let Ok(mut spare) = self.spare.try_lock() {
+            *spare = Some((current, next));
+        }
+        found
+    }
+
+    fn run(&self, text: &str, current: &mut Threads, next: &mut Threads) -> bool {
+        let count = text.chars().count();
+        for (at, ch) in text.chars().map(Some).chain([None]).enumerate() {
+            // a match may start anywhere
+            if self.add(current, 0, at, count) {
+                return true;
+            }
+            let Some(ch) = ch else {
+                break;
+            };
+            for i in 0..current.list.len() {
+                let pc = current.list[i];
+                if let Inst::Class(class) = &self.program[pc] {
+                    if class.matches(ch) && self.add(next, pc + 1, at + 1, count) {
+                        return true;
+                    }
+                }
+            }
+            std::mem::swap(current, next);
+            next.clear();
+        }
+        false
+    }
+
+    // Adds the thread at `pc` and the ones it leads to w/o reading a char,
+    // `at` chars into a text of `count`. Returns whether one matches. The
+    // instructions to visit are kept on a stack of their own, chains of
+    // splits are as long as the program.
+    fn add(&self, threads: &mut Threads, pc: usize, at: usize, count: usize) -> bool {
+        threads.stack.push(pc);
+        while let Some(pc) = threads.stack.pop() {
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+            match self.program[pc] {
+                Inst::Class(_) => threads.list.push(pc),
+                Inst::Split(a, b) => threads.stack.extend([b, a]),
+                Inst::Jump(to) => threads.stack.push(to),
+                Inst::Start if at == 0 => threads.stack.push(pc + 1),
+                Inst::End if at == count => threads.stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Match => {
+                    threads.stack.clear();
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl Clone for Pattern {
+    fn clone(&self) -> Self {
+        Pattern {
+            source: self.source.clone(),
+            program: self.program.clone(),
+            spare: Mutex::new(None),
+        }
+    }
+}
+
+// The instructions waiting for the next char.
+#[derive(Debug)]
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+    // instructions left to visit by `add`
+    stack: Vec<usize>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Threads {
+            list: vec![],
+            seen: vec![false; len],
+            stack: vec![],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+impl Class {
+    fn matches(&self, ch: char) -> bool {
+        let found = self.items.iter().any(|item| match *item {
+            Item::Range(from, to) => (from..=to).contains(&ch),
+            Item::Digit(negated) => ch.is_ascii_digit() != negated,
+            Item::Word(negated) => (ch.is_ascii_alphanumeric() || ch == '_') != negated,
+            Item::Space(negated) => ch.is_whitespace() != negated,
+            Item::Dot => !matches!(ch, '\n' | '\r' | '\u{2028}' | '\u{2029}'),
+        });
+        found != self.negated
+    }
+
+    fn of(item: Item) -> Class {
+        Class {
+            negated: false,
+            items: vec![item],
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    // groups open at the current char
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn alternation(&mut self) -> Option<Node> {
+        let mut branches = vec![self.concatenation()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            branches.push(self.concatenation()?);
+        }
+        Some(match branches.len() {
+            1 => branches.pop().unwrap(),
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> Option<Node> {
+        let mut nodes = vec![];
+        while let Some(&ch) = self.chars.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Some(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Option<Node> {
+        let node = match self.chars.next()? {
+            '(' => {
+                if self.chars.next_if_eq(&'?').is_some() {
+                    // only non-capturing groups
+                    self.chars.next_if_eq(&':')?;
+                }
+                if self.depth == MAX_NESTING {
+                    return None;
+                }
+                self.depth += 1;
+                let node = self.alternation()?;
+                self.depth -= 1;
+                self.chars.next_if_eq(&')')?;
+                node
+            }
+            '[' => Node::Class(self.class()?),
+            '.' => Node::Class(Class::of(Item::Dot)),
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => Node::Class(Class::of(self.escape()?)),
+            '*' | '+' | '?' | ')' => return None,
+            ch => Node::Class(Class::of(Item::Range(ch, ch))),
+        };
+        Some(node)
+    }
+
+    fn quantified(&mut self, node: Node) -> Option<Node> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.counts() {
+                Some(counts) => counts,
+                // a literal `{`
+                None => return Some(node),
+            },
+            _ => return Some(node),
+        };
+        self.chars.next();
+        if matches!(node, Node::Start | Node::End) || max.is_some_and(|max| max < min) {
+            return None;
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return None;
+        }
+        // lazy and greedy quantifiers find the same matches
+        self.chars.next_if_eq(&'?');
+        Some(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        })
+    }
+
+    // Reads `{n}`, `{n,}` or `{n,m}` up to the closing brace, which is left
+    // for the caller. Nothing is read if there is none.
+    fn counts(&mut self) -> Option<(u32, Option<u32>)> {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        let min = number(&mut ahead)?;
+        let max = match ahead.next_if_eq(&',') {
+            Some(_) if ahead.peek() == Some(&'}') => None,
+            Some(_) => Some(number(&mut ahead)?),
+            None => Some(min),
+        };
+        if ahead.peek() != Some(&'}') {
+            return None;
+        }
+        self.chars = ahead;
+        Some((min, max))
+    }
+
+    fn class(&mut self) -> Option<Class> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut items = vec![];
+        loop {
+            let from = match self.chars.next()? {
+                ']' => break,
+                '\\' => self.escape()?,
+                ch => Item::Range(ch, ch),
+            };
+            let Item::Range(from, _) = from else {
+                items.push(from);
+                continue;
+            };
+            let mut ahead = self.chars.clone();
+            let to = match (ahead.next(), ahead.peek()) {
+                (Some('-'), Some(&to)) if to != ']' => {
+                    ahead.next();
+                    self.chars = ahead;
+                    match to {
+                        '\\' => match self.escape()? {
+                            Item::Range(to, _) => to,
+                            _ => return None,
+                        },
+                        to => to,
+                    }
+                }
+                _ => from,
+            };
+            if to < from {
+                return None;
+            }
+            items.push(Item::Range(from, to));
+        }
+        Some(Class { negated, items })
+    }
+
+    // Reads what follows a `\`.
+    fn escape(&mut self) -> Option<Item> {
+        let ch = match self.chars.next()? {
+            'd' => return Some(Item::Digit(false)),
+            'D' => return Some(Item::Digit(true)),
+            'w' => return Some(Item::Word(false)),
+            'W' => return Some(Item::Word(true)),
+            's' => return Some(Item::Space(false)),
+            'S' => return Some(Item::Space(true)),
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            '0' => '\0',
+            'u' => {
+                let hex = (0..4)
+                    .map(|_| self.chars.next())
+                    .collect::<Option<String>>()?;
+                char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+            }
+            // back-references, word boundaries and other classes
+            ch if ch.is_ascii_alphanumeric() => return None,
+            ch => ch,
+        };
+        Some(Item::Range(ch, ch))
+    }
+}
+
+fn number(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits.parse().ok()
+}
+
+// The length of the program of `node`, saturating.
+fn size(node: &Node) -> usize {
+    match node {
+        Node::Class(_) | Node::Start | Node::End => 1,
+        Node::Concat(nodes) => nodes
+            .iter()
+            .fold(0, |sum, node| sum.saturating_add(size(node))),
+        Node::Alt(branches) => branches.iter().fold(0, |sum, branch| {
+            sum.saturating_add(size(branch)).saturating_add(2)
+        }),
+        Node::Repeat { node, min, max } => {
+            let size = size(node);
+            let optional = match max {
+                None => size.saturating_add(2),
+                Some(max) => size.saturating_add(1).saturating_mul((max - min) as usize),
+            };
+            size.saturating_mul(*min as usize).saturating_add(optional)
+        }
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program);
+            }
+        }
+        Node::Alt(branches) => {
+            let mut jumps = vec![];
+            for (i, branch) in branches.iter().enumerate() {
+                let split = program.len();
+                if i + 1 < branches.len() {
+                    program.push(Inst::Split(split + 1, 0));
+                }
+                compile(branch, program);
+                if i + 1 < branches.len() {
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+            }
+            patch(program, &jumps);
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program);
+                    }
+                    patch(program, &splits);
+                }
+            }
+        }
+    }
+}
+
+// Points the jumps and the second branch of the splits at `pcs` to the end
+// of the program.
+fn patch(program: &mut [Inst], pcs: &[usize]) {
+    let end = program.len();
+    for &pc in pcs {
+        program[pc] = match program[pc] {
+            Inst::Split(a, _) => Inst::Split(a, end),
+            _ => Inst::Jump(end),
+        };
+    }
+}
+
+#[cfg(test)]
+mod pattern_tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn should_match_like_ecma_regexps() {
+        assert!(matches("^[a-z]+-\\d{2,3}$", "abc-123"));
+        assert!(!matches("^[a-z]+-\\d{2,3}$", "abc-1234"));
+        assert!(matches("b+", "abbbc"));
+        assert!(!matches("^b+", "abbbc"));
+        assert!(matches("^(?:ab|cd)*e?$", "abcdab"));
+        assert!(!matches("^(ab|cd)*$", "abc"));
+        assert!(matches("^[^\\s@]+@[^\\s@]+\\.[a-z]{2,}$", "me@example.org"));
+        assert!(!matches("^[^\\s@]+@[^\\s@]+\\.[a-z]{2,}$", "me@example"));
+        assert!(matches("^\\u00e9.$", "éx"));
+        assert!(matches("x{,}", "x{,}"));
+        assert!(matches("^(a*)*$", &"a".repeat(10_000)));
+        assert!(!matches("^(a*)*$", &("a".repeat(10_000) + "b")));
+        assert!(matches("", "anything"));
+    }
+
+    #[test]
+    fn should_reject_unsupported_syntax() {
+        for pattern in [
+            "(a", "a)", "[a", "*a", "a{3,2}", "\\1", "\\b", "(?=a)", "[z-a]",
+        ] {
+            assert!(Pattern::new(pattern).is_none(), "{pattern}");
+        }
+        // the bound of a repetition is per quantifier, nested ones multiply
+        assert!(Pattern::new("(?:a{1000}){99}").is_some());
+        assert!(Pattern::new("(?:a{1000}){100}").is_none());
+        assert!(Pattern::new("(?:(?:(?:(?:a{1000}){1000}){1000}){1000}){1000}").is_none());
+    }
+
+    #[test]
+    fn should_not_overflow_a_thread_stack() {
+        let run = || {
+            let chained = Pattern::new("^(?:(?:){0,1000}){0,99}$").unwrap();
+            assert!(chained.is_match(""));
+            assert!(!chained.is_match("a"));
+            let nested = "(".repeat(50_000) + "a" + &")".repeat(50_000);
+            assert!(Pattern::new(&nested).is_none());
+            let deepest = "(".repeat(MAX_NESTING) + "a" + &")".repeat(MAX_NESTING);
+            assert!(Pattern::new(&deepest).unwrap().is_match("a"));
+        };
+        // the default stack of spawned threads
+        std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(run)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn should_reuse_threads_across_texts() {
+        let pattern = Pattern::new("^(ab|a)c$").unwrap();
+        for (text, expected) in [("abc", true), ("ac", true), ("abd", false), ("abc", true)] {
+            assert_eq!(pattern.is_match(text), expected, "{text}");
+        }
+        assert!(pattern.clone().is_match("ac"));
+    }
+}
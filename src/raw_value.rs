@@ -0,0 +1,53 @@
+//! Values kept as their JSON text.
+//!
+//! A `RawValue` holds the exact text of a value in the document, captured
+//! w/o tokenizing it by `TokenReader::next_raw`, so that a subtree can be
+//! forwarded as is with `JsonStreamWriter::raw_value`. With the `serde`
+//! feature a field of type `RawValue` is filled the same way.
+#[cfg(feature = "serde")]
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RawValue(Vec<u8>);
+
+impl RawValue {
+    /// Wraps text trusted to be a single JSON value.
+    pub fn new(raw: Vec<u8>) -> Self {
+        RawValue(raw)
+    }
+
+    pub fn get(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+// The name `de::Deserializer` recognizes, see `serde_json::value::RawValue`.
+#[cfg(feature = "serde")]
+pub(crate) const TOKEN: &str = "$json_stream_reader::RawValue";
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for RawValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(TOKEN, RawVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct RawVisitor;
+
+#[cfg(feature = "serde")]
+impl Visitor<'_> for RawVisitor {
+    type Value = RawValue;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a raw JSON value")
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, raw: Vec<u8>) -> Result<RawValue, E> {
+        Ok(RawValue(raw))
+    }
+}
@@ -0,0 +1,655 @@
+//! JSON Schema validation over the token stream.
+//!
+//! `Schema` holds a schema using a subset of draft 2020-12: `type`,
+//! `properties`, `required`, `additionalProperties`, `items`, `enum`,
+//! `const`, `minimum`, `maximum`, `minLength`, `maxLength`, `pattern`,
+//! `minItems` and `maxItems`, other keywords being ignored. `Validator`
+//! checks tokens as they come and reports a `Violation` for every failed
+//! keyword. It keeps one frame per open container, whatever the length of
+//! arrays, and builds a value in memory only where `enum` or `const` have
+//! to compare a whole object or array.
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_node::JsonNode;
+use crate::json_pointer::{JsonPointer, PathTracker};
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+use crate::pattern::Pattern;
+use crate::token_reader::TokenReader;
+use crate::tree_builder::TreeBuilder;
+
+/// A compiled schema, see the module docs.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    // the `false` schema
+    never: bool,
+    types: Option<Vec<Type>>,
+    properties: Vec<(String, Schema)>,
+    required: Vec<String>,
+    additional: Option<Box<Schema>>,
+    items: Option<Box<Schema>>,
+    enumeration: Option<Vec<JsonNode>>,
+    constant: Option<JsonNode>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Pattern>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    String,
+    Integer,
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Type> {
+        let ty = match name {
+            "null" => Type::Null,
+            "boolean" => Type::Boolean,
+            "object" => Type::Object,
+            "array" => Type::Array,
+            "number" => Type::Number,
+            "string" => Type::String,
+            "integer" => Type::Integer,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Type::Null => "null",
+            Type::Boolean => "boolean",
+            Type::Object => "object",
+            Type::Array => "array",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Integer => "integer",
+        }
+    }
+
+    // Whether a value of type `found`, `Integer` for whole numbers, is one of
+    // this type.
+    fn accepts(self, found: Type) -> bool {
+        self == found || (self == Type::Number && found == Type::Integer)
+    }
+}
+
+impl Schema {
+    /// Compiles a schema. Fails with `InvalidSchema` if a keyword of the
+    /// subset has a value of the wrong kind, or an unsupported `pattern`.
+    pub fn from_node(node: &JsonNode) -> Result<Schema> {
+        let members = match node {
+            JsonNode::Bool(valid) => {
+                return Ok(Schema {
+                    never: !valid,
+                    ..Schema::default()
+                })
+            }
+            JsonNode::Object(members) => members,
+            _ => return Err(invalid_schema()),
+        };
+        let mut schema = Schema::default();
+        for (keyword, value) in members {
+            match keyword.as_str() {
+                "type" => {
+                    let names = match value {
+                        JsonNode::Array(names) => names.iter().collect(),
+                        name => vec![name],
+                    };
+                    let types = names
+                        .into_iter()
+                        .map(|name| name.as_str().and_then(Type::parse))
+                        .collect::<Option<_>>();
+                    schema.types = Some(types.ok_or_else(invalid_schema)?);
+                }
+                "properties" => {
+                    let properties = value.as_object().ok_or_else(invalid_schema)?;
+                    schema.properties = properties
+                        .iter()
+                        .map(|(name, value)| Ok((name.clone(), Schema::from_node(value)?)))
+                        .collect::<Result<_>>()?;
+                }
+                "required" => {
+                    let names = value.as_array().ok_or_else(invalid_schema)?;
+                    schema.required = names
+                        .iter()
+                        .map(|name| name.as_str().map(str::to_string))
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid_schema)?;
+                }
+                "additionalProperties" => {
+                    schema.additional = Some(Box::new(Schema::from_node(value)?));
+                }
+                "items" => schema.items = Some(Box::new(Schema::from_node(value)?)),
+                "enum" => {
+                    let values = value.as_array().ok_or_else(invalid_schema)?;
+                    schema.enumeration = Some(values.clone());
+                }
+                "const" => schema.constant = Some(value.clone()),
+                "minimum" => schema.minimum = Some(value.as_f64().ok_or_else(invalid_schema)?),
+                "maximum" => schema.maximum = Some(value.as_f64().ok_or_else(invalid_schema)?),
+                "minLength" => schema.min_length = Some(count(value)?),
+                "maxLength" => schema.max_length = Some(count(value)?),
+                "minItems" => schema.min_items = Some(count(value)?),
+                "maxItems" => schema.max_items = Some(count(value)?),
+                "pattern" => {
+                    let source = value.as_str().ok_or_else(invalid_schema)?;
+                    schema.pattern = Some(Pattern::new(source).ok_or_else(invalid_schema)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Reads a document from `source` and returns all the violations found
+    /// in it, located at the byte that completed the token they were found
+    /// at. Fails if the document is not valid JSON.
+    pub fn validate<R: Read>(&self, source: R) -> Result<Vec<Violation>> {
+        let mut tokens = TokenReader::new(source);
+        let mut validator = Validator::new(self);
+        let mut found = vec![];
+        while let Some(token) = tokens.next_token()? {
+            found.extend_from_slice(validator.push(&token, tokens.token_offset()));
+        }
+        if !validator.is_done() {
+            return Err(tokens.error(ErrorCode::UnexpectedEof));
+        }
+        Ok(found)
+    }
+
+    // Whether the whole value is needed.
+    fn needs_value(&self) -> bool {
+        self.enumeration.is_some() || self.constant.is_some()
+    }
+}
+
+impl FromStr for Schema {
+    type Err = Error;
+
+    fn from_str(schema: &str) -> Result<Self> {
+        Schema::from_node(&JsonNode::from_reader(schema.as_bytes())?)
+    }
+}
+
+fn invalid_schema() -> Error {
+    Error {
+        code: ErrorCode::InvalidSchema,
+        column: 0,
+    }
+}
+
+// Any integral number from 0 on, `1.0` and `1e1` included. Counts too
+// large for a `usize` can't be reached and saturate.
+fn count(value: &JsonNode) -> Result<usize> {
+    let JsonNode::Number(n) = value else {
+        return Err(invalid_schema());
+    };
+    if let Ok(count) = n.parse() {
+        return Ok(count);
+    }
+    match n.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+        _ => Err(invalid_schema()),
+    }
+}
+
+/// A value failing a keyword of the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Path of the value.
+    pub pointer: JsonPointer,
+    /// Where it was found, see `Validator::push`.
+    pub offset: usize,
+    /// The keyword that failed, `false` for a value the schema rejects
+    /// altogether.
+    pub keyword: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' at byte {}: {} ({})",
+            self.pointer, self.offset, self.message, self.keyword
+        )
+    }
+}
+
+/// Checks a token stream against a schema.
+#[derive(Debug)]
+pub struct Validator<'s> {
+    root: &'s Schema,
+    path: PathTracker,
+    frames: Vec<Frame<'s>>,
+    // the key of the next member
+    key: Option<String>,
+    string: Option<Fragments<'s>>,
+    // whether the top level value started
+    started: bool,
+    found: Vec<Violation>,
+}
+
+// An open container.
+#[derive(Debug)]
+struct Frame<'s> {
+    schema: Option<&'s Schema>,
+    object: bool,
+    start: usize,
+    count: usize,
+    // which of the required properties were seen
+    seen: Vec<bool>,
+    tree: Option<TreeBuilder>,
+    value: Option<JsonNode>,
+}
+
+// A string read in fragments.
+#[derive(Debug)]
+struct Fragments<'s> {
+    schema: &'s Schema,
+    start: usize,
+    chars: usize,
+    // kept only if needed
+    text: Option<String>,
+}
+
+impl<'s> Validator<'s> {
+    pub fn new(schema: &'s Schema) -> Self {
+        Validator {
+            root: schema,
+            path: PathTracker::new(),
+            frames: vec![],
+            key: None,
+            string: None,
+            started: false,
+            found: vec![],
+        }
+    }
+
+    /// Takes the next token into account and returns the violations found
+    /// with it. `offset` locates the token in the document: a value is
+    /// reported at its first token, a failed `required`, `minItems` or
+    /// `maxItems` at its last. Members named by a `KnownKey` are not
+    /// checked.
+    pub fn push(&mut self, token: &JsonToken, offset: usize) -> &[Violation] {
+        self.found.clear();
+        for frame in &mut self.frames {
+            if let Some(tree) = &mut frame.tree {
+                if let Ok(Some(value)) = tree.push(token.clone()) {
+                    frame.value = Some(value);
+                }
+            }
+        }
+        self.path.push(token);
+        match token {
+            JsonToken::Key(key) => self.key = Some(key.to_string()),
+            JsonToken::KnownKey(_) => self.key = None,
+            JsonToken::ObjBeg | JsonToken::ArrBeg => {
+                let object = *token == JsonToken::ObjBeg;
+                let schema = self.next_schema(offset);
+                if let Some(schema) = schema {
+                    let ty = if object { Type::Object } else { Type::Array };
+                    self.check_type(schema, ty, offset);
+                }
+                let tree = schema.filter(|schema| schema.needs_value()).map(|_| {
+                    let mut tree = TreeBuilder::new();
+                    tree.push(token.clone()).unwrap_or_default();
+                    tree
+                });
+                self.frames.push(Frame {
+                    schema,
+                    object,
+                    start: offset,
+                    count: 0,
+                    seen: vec![false; schema.map_or(0, |schema| schema.required.len())],
+                    tree,
+                    value: None,
+                });
+            }
+            JsonToken::ObjEnd | JsonToken::ArrEnd => {
+                if let Some(frame) = self.frames.pop() {
+                    self.check_container(frame, offset);
+                }
+            }
+            JsonToken::Val(value) => {
+                if let Some(schema) = self.next_schema(offset) {
+                    self.check_scalar(schema, value, offset);
+                }
+            }
+            JsonToken::StrBegin => {
+                let schema = self.next_schema(offset);
+                if let Some(schema) = schema {
+                    self.check_type(schema, Type::String, offset);
+                }
+                self.string = schema.map(|schema| Fragments {
+                    schema,
+                    start: offset,
+                    chars: 0,
+                    text: (schema.pattern.is_some() || schema.needs_value()).then(String::new),
+                });
+            }
+            JsonToken::StrChunk(chunk) => {
+                if let Some(string) = &mut self.string {
+                    string.chars += chunk.chars().count();
+                    if let Some(text) = &mut string.text {
+                        text.push_str(chunk);
+                    }
+                }
+            }
+            JsonToken::StrEnd => {
+                if let Some(string) = self.string.take() {
+                    let text = string.text.as_deref();
+                    self.check_string(string.schema, string.chars, text, string.start);
+                    if let Some(text) = string.text {
+                        let value = JsonNode::String(text);
+                        self.check_value(string.schema, &value, string.start);
+                    }
+                }
+            }
+            // captured values are not checked
+            JsonToken::Raw(_) => {
+                self.next_schema(offset);
+            }
+        }
+        &self.found
+    }
+
+    /// Whether a whole value was pushed.
+    pub fn is_done(&self) -> bool {
+        self.started && self.frames.is_empty() && self.string.is_none()
+    }
+
+    // The schema of the value starting now, `None` if anything goes.
+    fn next_schema(&mut self, offset: usize) -> Option<&'s Schema> {
+        let Some(frame) = self.frames.last_mut() else {
+            self.started = true;
+            return Some(self.root);
+        };
+        frame.count += 1;
+        let schema = frame.schema?;
+        if !frame.object {
+            return schema.items.as_deref();
+        }
+        let key = self.key.take()?;
+        if let Some(i) = schema.required.iter().position(|name| *name == key) {
+            frame.seen[i] = true;
+        }
+        if let Some((_, property)) = schema.properties.iter().find(|(name, _)| *name == key) {
+            return Some(property);
+        }
+        match schema.additional.as_deref() {
+            Some(additional) if additional.never => {
+                let message = format!("property \"{}\" is not allowed", key);
+                self.report("additionalProperties", message, offset);
+                None
+            }
+            additional => additional,
+        }
+    }
+
+    fn report(&mut self, keyword: &'static str, message: String, offset: usize) {
+        self.found.push(Violation {
+            pointer: self.path.pointer(),
+            offset,
+            keyword,
+            message,
+        });
+    }
+
+    fn check_type(&mut self, schema: &Schema, found: Type, offset: usize) {
+        if schema.never {
+            self.report("false", "no value is allowed".to_string(), offset);
+        }
+        let Some(types) = &schema.types else {
+            return;
+        };
+        if !types.iter().any(|ty| ty.accepts(found)) {
+            let expected = types.iter().map(|ty| ty.name()).collect::<Vec<_>>();
+            let found = match found {
+                Type::Integer => Type::Number,
+                found => found,
+            };
+            let message = format!("expected {}, found {}", expected.join(" or "), found.name());
+            self.report("type", message, offset);
+        }
+    }
+
+    fn check_scalar(&mut self, schema: &Schema, value: &JsonValue, offset: usize) {
+        let ty = match value {
+            JsonValue::Null => Type::Null,
+            JsonValue::Bool(_) => Type::Boolean,
+            JsonValue::String(_) => Type::String,
+            JsonValue::Number(text) => match text.parse::<f64>() {
+                Ok(n) if n.fract() == 0.0 => Type::Integer,
+                _ => Type::Number,
+            },
+        };
+        self.check_type(schema, ty, offset);
+        match value {
+            JsonValue::String(text) => {
+                self.check_string(schema, text.chars().count(), Some(text), offset)
+            }
+            JsonValue::Number(text) => {
+                let n = text.parse::<f64>().unwrap_or(f64::NAN);
+                if let Some(minimum) = schema.minimum.filter(|&minimum| n < minimum) {
+                    let message = format!("{} is less than {}", text, minimum);
+                    self.report("minimum", message, offset);
+                }
+                if let Some(maximum) = schema.maximum.filter(|&maximum| n > maximum) {
+                    let message = format!("{} is greater than {}", text, maximum);
+                    self.report("maximum", message, offset);
+                }
+            }
+            _ => {}
+        }
+        if schema.needs_value() {
+            self.check_value(schema, &JsonNode::from(value.clone()), offset);
+        }
+    }
+
+    fn check_string(&mut self, schema: &Schema, chars: usize, text: Option<&str>, offset: usize) {
+        if let Some(min) = schema.min_length.filter(|&min| chars < min) {
+            let message = format!("string of {} characters is shorter than {}", chars, min);
+            self.report("minLength", message, offset);
+        }
+        if let Some(max) = schema.max_length.filter(|&max| chars > max) {
+            let message = format!("string of {} characters is longer than {}", chars, max);
+            self.report("maxLength", message, offset);
+        }
+        if let (Some(pattern), Some(text)) = (&schema.pattern, text) {
+            if !pattern.is_match(text) {
+                let message = format!("does not match \"{}\"", pattern.as_str());
+                self.report("pattern", message, offset);
+            }
+        }
+    }
+
+    fn check_container(&mut self, frame: Frame, offset: usize) {
+        let Some(schema) = frame.schema else {
+            return;
+        };
+        for (name, seen) in schema.required.iter().zip(frame.seen) {
+            if !seen {
+                let message = format!("missing property \"{}\"", name);
+                self.report("required", message, offset);
+            }
+        }
+        if !frame.object {
+            if let Some(min) = schema.min_items.filter(|&min| frame.count < min) {
+                let message = format!("array of {} items is shorter than {}", frame.count, min);
+                self.report("minItems", message, offset);
+            }
+            if let Some(max) = schema.max_items.filter(|&max| frame.count > max) {
+                let message = format!("array of {} items is longer than {}", frame.count, max);
+                self.report("maxItems", message, offset);
+            }
+        }
+        if let Some(value) = &frame.value {
+            self.check_value(schema, value, frame.start);
+        }
+    }
+
+    // Checks `enum` and `const`.
+    fn check_value(&mut self, schema: &Schema, value: &JsonNode, offset: usize) {
+        if let Some(values) = &schema.enumeration {
+            if !values.iter().any(|allowed| same(allowed, value)) {
+                let message = "not one of the allowed values".to_string();
+                self.report("enum", message, offset);
+            }
+        }
+        if let Some(constant) = &schema.constant {
+            if !same(constant, value) {
+                self.report("const", "not the constant value".to_string(), offset);
+            }
+        }
+    }
+}
+
+// Equality as in JSON Schema: numbers by value, members in any order.
+fn same(a: &JsonNode, b: &JsonNode) -> bool {
+    match (a, b) {
+        (JsonNode::Number(a), JsonNode::Number(b)) => {
+            a == b || matches!((a.parse::<f64>(), b.parse::<f64>()), (Ok(a), Ok(b)) if a == b)
+        }
+        (JsonNode::Array(a), JsonNode::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (JsonNode::Object(a), JsonNode::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.iter().any(|(other, b)| key == other && same(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use super::*;
+    use crate::json_stream_reader::JsonStreamReader;
+
+    const SCHEMA: &str = r#"{
+        "type": "object",
+        "properties": {
+            "id": {"type": "integer", "minimum": 1},
+            "name": {"type": "string", "minLength": 2, "maxLength": 8, "pattern": "^[a-z]+$"},
+            "tags": {"type": "array", "maxItems": 2, "items": {"enum": ["a", "b", 3]}},
+            "point": {"const": {"x": 1, "y": [2]}},
+            "ratio": {"type": ["number", "null"], "maximum": 0.5},
+            "kind": true
+        },
+        "required": ["id", "name", "kind"],
+        "additionalProperties": false
+    }"#;
+
+    fn violations(doc: &str) -> Vec<(String, &'static str)> {
+        let schema = SCHEMA.parse::<Schema>().unwrap();
+        let found = schema.validate(doc.as_bytes()).unwrap();
+        found
+            .into_iter()
+            .map(|violation| (violation.pointer.to_string(), violation.keyword))
+            .collect()
+    }
+
+    #[test]
+    fn should_report_violations_with_pointers() {
+        let valid = r#"{"id": 2, "name": "ab", "kind": 0, "tags": ["b", 3.0], "point": {"y": [2], "x": 1.0}, "ratio": null}"#;
+        assert_eq!(violations(valid), []);
+        let invalid = r#"{"id": 1.5, "name": "Abcdefghi", "tags": ["a", "c", "b"], "point": {"x": 1}, "ratio": 1, "extra": {"id": 0}}"#;
+        let expected = [
+            ("/id", "type"),
+            ("/name", "maxLength"),
+            ("/name", "pattern"),
+            ("/tags/1", "enum"),
+            ("/tags", "maxItems"),
+            ("/point", "const"),
+            ("/ratio", "maximum"),
+            ("/extra", "additionalProperties"),
+            ("", "required"),
+        ];
+        let expected = expected.map(|(pointer, keyword)| (pointer.to_string(), keyword));
+        assert_eq!(violations(invalid), expected);
+    }
+
+    #[test]
+    fn should_locate_violations() {
+        let schema = r#"{"items": {"type": "string"}, "minItems": 3}"#.parse::<Schema>().unwrap();
+        let found = schema.validate(&b"[\"a\", 12, \"b\"]  "[..]).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].offset, 8);
+        assert_eq!(found[0].message, "expected string, found number");
+        assert_eq!(
+            found[0].to_string(),
+            "'/1' at byte 8: expected string, found number (type)"
+        );
+        let found = schema.validate(&b"[\"a\"]"[..]).unwrap();
+        assert_eq!(found[0].keyword, "minItems");
+        assert_eq!(found[0].offset, 4);
+    }
+
+    #[test]
+    fn should_check_strings_read_in_fragments() {
+        let schema = r#"{"items": {"maxLength": 5, "pattern": "^x+$"}}"#.parse::<Schema>().unwrap();
+        let mut reader = JsonStreamReader::new().with_string_fragments(2);
+        let mut validator = Validator::new(&schema);
+        let mut found = vec![];
+        for part in [&br#"["xxx", "xx"#[..], b"xxxx", br#"", "xxy"]"#] {
+            for token in reader.read(part).unwrap() {
+                found.extend_from_slice(validator.push(&token, 0));
+            }
+        }
+        assert!(validator.is_done());
+        let keywords = found
+            .iter()
+            .map(|violation| violation.keyword)
+            .collect::<Vec<_>>();
+        assert_eq!(keywords, ["maxLength", "pattern"]);
+        assert_eq!(found[0].pointer.to_string(), "/1");
+    }
+
+    #[test]
+    fn should_accept_integral_counts_in_any_notation() {
+        for (count, expected) in [
+            ("2", 2),
+            ("2.0", 2),
+            ("2e0", 2),
+            ("0.2e1", 2),
+            ("-0", 0),
+            ("1e30", usize::MAX),
+        ] {
+            let schema = format!(r#"{{"minLength": {count}, "maxItems": {count}}}"#);
+            let schema = schema.parse::<Schema>().unwrap();
+            assert_eq!(schema.min_length, Some(expected), "{count}");
+            assert_eq!(schema.max_items, Some(expected), "{count}");
+        }
+    }
+
+    #[test]
+    fn should_reject_invalid_schema() {
+        for schema in [
+            r#"{"type": "text"}"#,
+            r#"{"minLength": -1}"#,
+            r#"{"maxItems": 1.5}"#,
+            r#"{"minItems": 1e400}"#,
+            r#"{"pattern": "(a"}"#,
+            "[]",
+        ] {
+            let err = schema.parse::<Schema>().unwrap_err();
+            assert_eq!(err.code, ErrorCode::InvalidSchema, "{schema}");
+        }
+    }
+}
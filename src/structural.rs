@@ -0,0 +1,561 @@
+//! Two-stage parsing of a document that is entirely in memory.
+//!
+//! Stage one looks at the input 64 bytes at a time, classifying them with
+//! SIMD instructions, and records the position of every structural
+//! character, quote and start of a scalar outside of strings. Stage two runs
+//! the state machine of `JsonStreamReader` on those positions only, so
+//! whitespace and string bodies are never visited byte by byte.
+//!
+//! The tokens, their positions and the errors are the same as the ones of
+//! `JsonStreamReader` followed by an `UnexpectedEof` check.
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+
+/// Reads a whole document held in memory.
+pub fn tokenize(buf: &[u8]) -> Result<Vec<JsonToken>> {
+    let mut tokens = vec![];
+    tokenize_to(buf, |token, _| tokens.push(token))?;
+    Ok(tokens)
+}
+
+/// Reads a whole document and passes every token to `emit` together with
+/// the index of the byte that completed it.
+pub(crate) fn tokenize_to<F>(buf: &[u8], mut emit: F) -> Result<()>
+where
+    F: FnMut(JsonToken, usize),
+{
+    let mut reader = JsonStreamReader::new();
+    if buf.len() > u32::MAX as usize {
+        // positions do not fit the index, read byte by byte instead
+        reader.read_to(buf, &mut emit)?;
+        if reader.depth() > 0 {
+            return Err(Error {
+                code: ErrorCode::UnexpectedEof,
+                column: buf.len(),
+            });
+        }
+        return Ok(());
+    }
+    let index = StructuralIndex::new(buf);
+    reader.read_indexed(buf, &index.positions, |token, i| emit(token.to_token(), i))
+}
+
+/// Positions of the bytes stage two has to look at: structural characters
+/// and starts of scalars outside of strings, and unescaped quotes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuralIndex {
+    positions: Vec<u32>,
+}
+
+impl StructuralIndex {
+    /// Indexes `buf` with the best instructions the CPU supports.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is longer than `u32::MAX` bytes.
+    pub fn new(buf: &[u8]) -> Self {
+        StructuralIndex::with_backend(buf, Backend::detect())
+    }
+
+    /// Indexes `buf` with the given instructions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is longer than `u32::MAX` bytes or if the CPU does not
+    /// support `backend`.
+    pub fn with_backend(buf: &[u8], backend: Backend) -> Self {
+        assert!(buf.len() <= u32::MAX as usize, "buffer too large to index");
+        assert!(backend.is_supported(), "{:?} is not supported", backend);
+        let mut index = StructuralIndex {
+            positions: Vec::with_capacity(buf.len() / 8),
+        };
+        let mut carry = Carry::default();
+        let mut blocks = buf.chunks_exact(64);
+        for (n, block) in blocks.by_ref().enumerate() {
+            let masks = backend.classify(block.try_into().unwrap());
+            index.add_block(n * 64, masks, &mut carry);
+        }
+        let tail = blocks.remainder();
+        if !tail.is_empty() {
+            // padded with whitespace, which is never indexed
+            let mut block = [b' '; 64];
+            block[..tail.len()].copy_from_slice(tail);
+            index.add_block(buf.len() - tail.len(), backend.classify(&block), &mut carry);
+        }
+        index
+    }
+
+    pub fn positions(&self) -> &[u32] {
+        &self.positions
+    }
+
+    fn add_block(&mut self, base: usize, masks: Masks, carry: &mut Carry) {
+        let escaped = find_escaped(masks.backslash, &mut carry.escaped);
+        let quotes = masks.quote & !escaped;
+        // set from an opening quote up to the byte before the closing one
+        let in_string = prefix_xor(quotes) ^ carry.in_string;
+        carry.in_string = ((in_string as i64) >> 63) as u64;
+        let other = !(masks.ops | masks.ws | masks.quote);
+        let word_starts = other & !(other << 1 | carry.other);
+        carry.other = other >> 63;
+        let mut bits = (masks.ops | word_starts) & !in_string | quotes;
+        while bits != 0 {
+            self.positions
+                .push((base + bits.trailing_zeros() as usize) as u32);
+            bits &= bits - 1;
+        }
+    }
+}
+
+// State carried from one block to the next, each is 0 or 1 but `in_string`
+// which is 0 or all ones.
+#[derive(Debug, Default)]
+struct Carry {
+    // the first byte of the block is escaped
+    escaped: u64,
+    // the block starts inside a string
+    in_string: u64,
+    // the last byte of the previous block is part of a scalar
+    other: u64,
+}
+
+/// Bitmaps of a 64 byte block, bit `n` stands for byte `n`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Masks {
+    quote: u64,
+    backslash: u64,
+    ws: u64,
+    // `{`, `}`, `[`, `]`, `:` and `,`
+    ops: u64,
+}
+
+// Finds the bytes escaped by a backslash, i.e. preceded by an odd sequence
+// of backslashes. A sequence can run over from the previous block.
+fn find_escaped(backslash: u64, prev_escaped: &mut u64) -> u64 {
+    const EVEN_BITS: u64 = 0x5555_5555_5555_5555;
+    // an escaped backslash does not escape anything
+    let backslash = backslash & !*prev_escaped;
+    let follows_escape = backslash << 1 | *prev_escaped;
+    // odd sequences starting on an odd bit end on an even bit, or the other
+    // way around, which the carry of the addition tells apart
+    let odd_sequence_starts = backslash & !EVEN_BITS & !follows_escape;
+    let (sequences_starting_on_even_bits, overflow) =
+        odd_sequence_starts.overflowing_add(backslash);
+    *prev_escaped = overflow as u64;
+    let invert_mask = sequences_starting_on_even_bits << 1;
+    (EVEN_BITS ^ invert_mask) & follows_escape
+}
+
+// Sets every bit that has an odd number of set bits at or below it.
+fn prefix_xor(mut bits: u64) -> u64 {
+    bits ^= bits << 1;
+    bits ^= bits << 2;
+    bits ^= bits << 4;
+    bits ^= bits << 8;
+    bits ^= bits << 16;
+    bits ^= bits << 32;
+    bits
+}
+
+/// Instructions used to classify the bytes of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Sse2,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+}
+
+impl Backend {
+    /// Returns the fastest backend the CPU supports.
+    pub fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Backend::Avx2;
+            }
+            Backend::Sse2
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            Backend::Neon
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            Backend::Scalar
+        }
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => is_x86_feature_detected!("avx2"),
+            _ => true,
+        }
+    }
+
+    fn classify(self, block: &[u8; 64]) -> Masks {
+        match self {
+            Backend::Scalar => classify_scalar(block),
+            // SAFETY: SSE2 is part of the x86_64 baseline.
+            #[cfg(target_arch = "x86_64")]
+            Backend::Sse2 => unsafe { x86::classify_sse2(block) },
+            // SAFETY: the index is only built with a supported backend.
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => unsafe { x86::classify_avx2(block) },
+            // SAFETY: NEON is part of the aarch64 baseline.
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => unsafe { arm::classify_neon(block) },
+        }
+    }
+}
+
+fn classify_scalar(block: &[u8; 64]) -> Masks {
+    let mut masks = Masks::default();
+    for (n, &ch) in block.iter().enumerate() {
+        let bit = 1 << n;
+        match ch {
+            b'"' => masks.quote |= bit,
+            b'\\' => masks.backslash |= bit,
+            b' ' | b'\t' | b'\n' | b'\r' => masks.ws |= bit,
+            b'{' | b'}' | b'[' | b']' | b':' | b',' => masks.ops |= bit,
+            _ => {}
+        }
+    }
+    masks
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::Masks;
+
+    // `[` and `]` differ from `{` and `}` by the 0x20 bit only
+    macro_rules! classify {
+        ($v:expr, $set1:ident, $eq:ident, $or:ident, $movemask:ident, $ty:ty) => {{
+            let v = $v;
+            let eq = |ch: u8| $eq(v, $set1(ch as i8));
+            let braces = $or(v, $set1(0x20));
+            let ops = $or(
+                $or(
+                    $eq(braces, $set1(b'{' as i8)),
+                    $eq(braces, $set1(b'}' as i8)),
+                ),
+                $or(eq(b':'), eq(b',')),
+            );
+            let ws = $or($or(eq(b' '), eq(b'\t')), $or(eq(b'\n'), eq(b'\r')));
+            (
+                $movemask(eq(b'"')) as $ty as u64,
+                $movemask(eq(b'\\')) as $ty as u64,
+                $movemask(ws) as $ty as u64,
+                $movemask(ops) as $ty as u64,
+            )
+        }};
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn classify_sse2(block: &[u8; 64]) -> Masks {
+        let mut masks = Masks::default();
+        for n in 0..4 {
+            let v = _mm_loadu_si128(block.as_ptr().add(n * 16) as *const __m128i);
+            let (quote, backslash, ws, ops) = classify!(
+                v,
+                _mm_set1_epi8,
+                _mm_cmpeq_epi8,
+                _mm_or_si128,
+                _mm_movemask_epi8,
+                u16
+            );
+            masks.quote |= quote << (n * 16);
+            masks.backslash |= backslash << (n * 16);
+            masks.ws |= ws << (n * 16);
+            masks.ops |= ops << (n * 16);
+        }
+        masks
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn classify_avx2(block: &[u8; 64]) -> Masks {
+        let mut masks = Masks::default();
+        for n in 0..2 {
+            let v = _mm256_loadu_si256(block.as_ptr().add(n * 32) as *const __m256i);
+            let (quote, backslash, ws, ops) = classify!(
+                v,
+                _mm256_set1_epi8,
+                _mm256_cmpeq_epi8,
+                _mm256_or_si256,
+                _mm256_movemask_epi8,
+                u32
+            );
+            masks.quote |= quote << (n * 32);
+            masks.backslash |= backslash << (n * 32);
+            masks.ws |= ws << (n * 32);
+            masks.ops |= ops << (n * 32);
+        }
+        masks
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use std::arch::aarch64::*;
+
+    use super::Masks;
+
+    // Packs the high bits of the 16 lanes into an integer.
+    #[target_feature(enable = "neon")]
+    unsafe fn movemask(v: uint8x16_t) -> u64 {
+        const WEIGHTS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+        let bits = vandq_u8(v, vld1q_u8(WEIGHTS.as_ptr()));
+        let low = vaddv_u8(vget_low_u8(bits)) as u64;
+        let high = vaddv_u8(vget_high_u8(bits)) as u64;
+        low | high << 8
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn classify_neon(block: &[u8; 64]) -> Masks {
+        let mut masks = Masks::default();
+        for n in 0..4 {
+            let v = vld1q_u8(block.as_ptr().add(n * 16));
+            let eq = |ch: u8| vceqq_u8(v, vdupq_n_u8(ch));
+            // `[` and `]` differ from `{` and `}` by the 0x20 bit only
+            let braces = vorrq_u8(v, vdupq_n_u8(0x20));
+            let ops = vorrq_u8(
+                vorrq_u8(
+                    vceqq_u8(braces, vdupq_n_u8(b'{')),
+                    vceqq_u8(braces, vdupq_n_u8(b'}')),
+                ),
+                vorrq_u8(eq(b':'), eq(b',')),
+            );
+            let ws = vorrq_u8(
+                vorrq_u8(eq(b' '), eq(b'\t')),
+                vorrq_u8(eq(b'\n'), eq(b'\r')),
+            );
+            masks.quote |= movemask(eq(b'"')) << (n * 16);
+            masks.backslash |= movemask(eq(b'\\')) << (n * 16);
+            masks.ws |= movemask(ws) << (n * 16);
+            masks.ops |= movemask(ops) << (n * 16);
+        }
+        masks
+    }
+}
+
+#[cfg(test)]
+mod structural_tests {
+    use super::*;
+
+    type Positioned = Result<Vec<(JsonToken, usize)>>;
+
+    // xorshift, good enough to shuffle test input around
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick(&mut self, choices: &[&'static str]) -> &'static str {
+            choices[self.below(choices.len())]
+        }
+    }
+
+    fn backends() -> Vec<Backend> {
+        let all = [
+            Backend::Scalar,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Sse2,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2,
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon,
+        ];
+        all.into_iter().filter(|b| b.is_supported()).collect()
+    }
+
+    fn streamed(buf: &[u8]) -> Positioned {
+        let mut reader = JsonStreamReader::new();
+        let mut tokens = vec![];
+        reader.read_to(buf, |token, i| tokens.push((token, i)))?;
+        if reader.depth() > 0 {
+            return Err(Error {
+                code: ErrorCode::UnexpectedEof,
+                column: buf.len(),
+            });
+        }
+        Ok(tokens)
+    }
+
+    fn indexed(buf: &[u8], backend: Backend) -> Positioned {
+        let index = StructuralIndex::with_backend(buf, backend);
+        let mut tokens = vec![];
+        JsonStreamReader::new().read_indexed(buf, index.positions(), |token, i| {
+            tokens.push((token.to_token(), i))
+        })?;
+        Ok(tokens)
+    }
+
+    fn assert_same_as_streamed(buf: &[u8]) {
+        let expected = streamed(buf);
+        for backend in backends() {
+            assert_eq!(
+                indexed(buf, backend),
+                expected,
+                "{:?} on {}",
+                backend,
+                String::from_utf8_lossy(buf)
+            );
+        }
+    }
+
+    fn document(rng: &mut Rng, depth: usize, out: &mut String) {
+        let scalars = [
+            "null",
+            "true",
+            "false",
+            "0",
+            "-12.5",
+            "3e-7",
+            "1E+2",
+            "\"\"",
+            "\"abc\"",
+            "\"a\\\"b\"",
+            "\"\\\\\"",
+            "\"\\\\\\\\\\\"\"",
+            "\"caf\\u00e9 \\ud83d\\ude00\"",
+            "\"{[:,]}\"",
+            "\"                                                  long\"",
+        ];
+        let blanks = ["", " ", "\n  ", "\t"];
+        if depth == 0 || rng.below(3) == 0 && depth < 4 {
+            let object = rng.below(2) == 0;
+            out.push(if object { '{' } else { '[' });
+            for n in 0..rng.below(5) {
+                if n > 0 {
+                    out.push(',');
+                }
+                out.push_str(rng.pick(&blanks));
+                if object {
+                    out.push_str(rng.pick(&["\"k\"", "\"k\\\"ey\"", "\"\\\\\""]));
+                    out.push_str(rng.pick(&blanks));
+                    out.push(':');
+                    out.push_str(rng.pick(&blanks));
+                }
+                document(rng, depth + 1, out);
+                out.push_str(rng.pick(&blanks));
+            }
+            out.push(if object { '}' } else { ']' });
+        } else {
+            out.push_str(rng.pick(&scalars));
+        }
+    }
+
+    #[test]
+    fn should_classify_alike_with_every_backend() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let alphabet = b"\"\\ \t\n\r{}[]:,ax0\x00\x7f\xff";
+        for _ in 0..1000 {
+            let mut block = [0; 64];
+            for byte in block.iter_mut() {
+                *byte = alphabet[rng.below(alphabet.len())];
+            }
+            let expected = classify_scalar(&block);
+            for backend in backends() {
+                assert_eq!(backend.classify(&block), expected, "{:?}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn should_index_structurals_quotes_and_scalars() {
+        let buf = br#"{"a\"b": [1, true], "c": "\\"}"#;
+        //            0123 45678901234567890123 45678
+        let index = StructuralIndex::new(buf);
+        assert_eq!(
+            index.positions(),
+            &[0, 1, 6, 7, 9, 10, 11, 13, 17, 18, 20, 22, 23, 25, 28, 29]
+        );
+    }
+
+    #[test]
+    fn should_carry_escapes_and_strings_across_blocks() {
+        // backslash runs and quotes end up on both sides of block boundaries
+        for pad in 0..70 {
+            for run in 0..5 {
+                let doc = format!(
+                    r#"["{}{}\"", "x", "{}", 1]"#,
+                    " ".repeat(pad),
+                    "\\".repeat(run * 2),
+                    "y".repeat(pad)
+                );
+                assert_same_as_streamed(doc.as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn should_match_streaming_reader_on_documents() {
+        let mut rng = Rng(42);
+        for _ in 0..500 {
+            let mut doc = String::new();
+            document(&mut rng, 0, &mut doc);
+            let tokens = tokenize(doc.as_bytes());
+            assert!(tokens.is_ok(), "{}", doc);
+            assert_same_as_streamed(doc.as_bytes());
+        }
+    }
+
+    #[test]
+    fn should_match_streaming_reader_on_broken_input() {
+        let mut rng = Rng(7);
+        let pieces = [
+            "{",
+            "}",
+            "[",
+            "]",
+            ":",
+            ",",
+            "\"",
+            "\\",
+            " ",
+            "\n",
+            "1",
+            "-",
+            ".",
+            "e",
+            "x",
+            "null",
+            "true",
+            "fals",
+            "\"k\"",
+            "12",
+            "\"\\u12\"",
+        ];
+        for _ in 0..5000 {
+            let mut doc = String::from(rng.pick(&["{", "[", " [", "{\"a\":"]));
+            for _ in 0..rng.below(40) {
+                doc.push_str(rng.pick(&pieces));
+            }
+            assert_same_as_streamed(doc.as_bytes());
+        }
+    }
+
+    #[test]
+    fn should_report_unexpected_eof() {
+        let res = tokenize(br#"{"a": [1, "#);
+        assert_eq!(
+            res,
+            Err(Error {
+                code: ErrorCode::UnexpectedEof,
+                column: 10
+            })
+        );
+    }
+}
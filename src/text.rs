@@ -0,0 +1,218 @@
+//! The text of keys and values in tokens.
+//!
+//! Most keys, numbers and string values of a document are short. `Text`
+//! keeps up to `INLINE` bytes in place and allocates only for longer texts,
+//! which is what `JsonStreamReader::read` spends most of its time on
+//! otherwise. Keys from a `KeyInterner` share its copy instead. It derefs to
+//! `str`, and compares, orders and hashes as one.
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::str;
+use std::sync::Arc;
+
+/// Texts up to this many bytes long are not allocated.
+pub const INLINE: usize = 22;
+
+#[derive(Clone)]
+pub struct Text(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    // the first `len` bytes of `bytes` are a `str`
+    Inline { len: u8, bytes: [u8; INLINE] },
+    Heap(Box<str>),
+    Shared(Arc<str>),
+}
+
+impl Text {
+    pub fn new(text: &str) -> Self {
+        if text.len() > INLINE {
+            return Text(Repr::Heap(text.into()));
+        }
+        let mut bytes = [0; INLINE];
+        bytes[..text.len()].copy_from_slice(text.as_bytes());
+        Text(Repr::Inline {
+            len: text.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // SAFETY: inline bytes are only written by `new`, from a `str`
+            Repr::Inline { len, bytes } => unsafe {
+                str::from_utf8_unchecked(&bytes[..*len as usize])
+            },
+            Repr::Heap(text) => text,
+            Repr::Shared(text) => text,
+        }
+    }
+
+    /// Whether the text is kept in place rather than allocated.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    pub fn into_string(self) -> String {
+        match self.0 {
+            Repr::Heap(text) => text.into_string(),
+            Repr::Inline { .. } | Repr::Shared(_) => self.as_str().to_string(),
+        }
+    }
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Text::new("")
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Text {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Text {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Text) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Text {}
+
+impl PartialOrd for Text {
+    fn partial_cmp(&self, other: &Text) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Text {
+    fn cmp(&self, other: &Text) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for Text {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for Text {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Text {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Text {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Text> for str {
+    fn eq(&self, other: &Text) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Text> for &str {
+    fn eq(&self, other: &Text) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Text> for String {
+    fn eq(&self, other: &Text) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        Text::new(text)
+    }
+}
+
+impl From<String> for Text {
+    fn from(text: String) -> Self {
+        match text.len() > INLINE {
+            true => Text(Repr::Heap(text.into_boxed_str())),
+            false => Text::new(&text),
+        }
+    }
+}
+
+impl From<Arc<str>> for Text {
+    fn from(text: Arc<str>) -> Self {
+        Text(Repr::Shared(text))
+    }
+}
+
+impl From<Text> for String {
+    fn from(text: Text) -> Self {
+        text.into_string()
+    }
+}
+
+#[cfg(test)]
+mod text_tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_short_texts_in_place() {
+        let short = "x".repeat(INLINE);
+        let long = "x".repeat(INLINE + 1);
+        assert!(Text::new(&short).is_inline());
+        assert!(!Text::new(&long).is_inline());
+        assert!(!Text::from(long.clone()).is_inline());
+        for text in ["", "é€😀", &short, &long] {
+            assert_eq!(Text::new(text), text);
+            assert_eq!(Text::from(text.to_string()).into_string(), text);
+        }
+        assert_eq!(format!("{:?}", Text::new("a\"b")), r#""a\"b""#);
+        assert!(Text::new("a") < Text::new(&long));
+        let shared: Arc<str> = Arc::from("a");
+        let text = Text::from(shared.clone());
+        assert!(!text.is_inline());
+        assert_eq!(text.as_ptr(), shared.as_ptr());
+        assert_eq!(text, Text::new("a"));
+        assert_eq!(std::mem::size_of::<Text>(), 24);
+    }
+}
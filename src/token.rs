@@ -2,21 +2,163 @@
 //!
 //! JSON data can be represented as a stack of tokens.
 //!
-//! Those tokens can store an intermediate state between re-buffering.
-//!
-//! Also tokens are useful for a state machine.
-#[derive(Debug, PartialEq)]
+//! Only containers are kept on the stack, everything else the reader needs
+//! between re-buffering is described by a `State` and a `Lexeme`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum Token {
     Obj,
     Arr,
-    Key(Vec<u8>),
+}
+
+/// The stack of open containers, one bit per level: set for an object,
+/// clear for an array.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Nesting {
+    bits: Vec<u64>,
+    depth: usize,
+}
+
+impl Nesting {
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.depth == 0
+    }
+
+    pub(crate) fn push(&mut self, token: Token) {
+        let (word, bit) = (self.depth / 64, self.depth % 64);
+        if word == self.bits.len() {
+            self.bits.push(0);
+        }
+        match token {
+            Token::Obj => self.bits[word] |= 1 << bit,
+            Token::Arr => self.bits[word] &= !(1 << bit),
+        }
+        self.depth += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Token> {
+        let token = self.last()?;
+        self.depth -= 1;
+        // words above the current depth are dropped
+        self.bits.truncate(self.depth.div_ceil(64));
+        Some(token)
+    }
+
+    pub(crate) fn last(&self) -> Option<Token> {
+        let level = self.depth.checked_sub(1)?;
+        match self.bits[level / 64] >> (level % 64) & 1 {
+            1 => Some(Token::Obj),
+            _ => Some(Token::Arr),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bits.clear();
+        self.depth = 0;
+    }
+
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    // The nesting described by `words`, one per 64 levels.
+    pub(crate) fn from_words(bits: Vec<u64>, depth: usize) -> Nesting {
+        debug_assert_eq!(bits.len(), depth.div_ceil(64));
+        Nesting { bits, depth }
+    }
+
+    // The bits of word `n` below the depth, the others being left over from
+    // popped levels.
+    fn live_word(&self, n: usize) -> u64 {
+        match self.depth - n * 64 {
+            levels if levels >= 64 => self.bits[n],
+            levels => self.bits[n] & ((1 << levels) - 1),
+        }
+    }
+}
+
+impl PartialEq for Nesting {
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth
+            && (0..self.bits.len()).all(|n| self.live_word(n) == other.live_word(n))
+    }
+}
+
+/// Where the reader is in between tokens. Indexes the transition table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum State {
+    // before the top level container
+    Start,
+    // after `{`
+    ObjFirst,
+    // after a comma in an object
+    ObjNext,
     AfterKey,
-    Colon,
-    Null(Vec<u8>),
-    True(Vec<u8>),
-    False(Vec<u8>),
-    Number(Vec<u8>),
-    String(Vec<u8>),
-    Comma,
+    // after a colon
+    Value,
+    // after `[`
+    ArrFirst,
+    // after a comma in an array
+    ArrNext,
+    // after a complete value, inside a container
+    AfterValue,
+    // after the top level container
+    End,
+}
+
+impl State {
+    pub(crate) const STRUCTURAL: usize = State::End as usize + 1;
+}
+
+/// A token being read, possibly across several buffers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Lexeme {
     None,
+    // `escaped` is set when the buffer ended right after a backslash
+    Key { escaped: bool },
+    Str { escaped: bool },
+    // a string value emitted in fragments, see `JsonToken::StrBegin`
+    Fragments { escaped: bool },
+    Number { dot: bool },
+    Literal { word: &'static [u8], pos: usize },
+    // a value captured as is, see `JsonToken::Raw`
+    Raw(RawScan),
+}
+
+/// How far a value captured as is has been scanned. Only its strings and
+/// the nesting of its containers are followed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct RawScan {
+    // containers open in the value
+    pub(crate) depth: usize,
+    pub(crate) string: bool,
+    // right after a backslash in a string
+    pub(crate) escaped: bool,
+}
+
+#[cfg(test)]
+mod nesting_tests {
+    use super::*;
+
+    #[test]
+    fn should_push_and_pop_containers() {
+        let mut nesting = Nesting::default();
+        let tokens: Vec<_> = (0..200)
+            .map(|i| if i % 3 == 0 { Token::Obj } else { Token::Arr })
+            .collect();
+        for &token in &tokens {
+            nesting.push(token);
+        }
+        assert_eq!(nesting.depth(), 200);
+        assert_eq!(nesting.bits.len(), 4);
+        for &token in tokens.iter().rev() {
+            assert_eq!(nesting.pop(), Some(token));
+        }
+        assert!(nesting.is_empty());
+        assert!(nesting.bits.is_empty());
+        assert_eq!(nesting.pop(), None);
+    }
 }
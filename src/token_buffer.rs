@@ -0,0 +1,254 @@
+//! Reusable storage for the tokens of a chunk.
+//!
+//! `JsonStreamReader::read_into` fills a `TokenBuffer` instead of building a
+//! `Vec<JsonToken>`. Tokens are kept in fixed size slots, and the text of
+//! keys, strings and numbers goes into a single byte arena the slots point
+//! into, so clearing the buffer keeps all of its memory for the next chunk.
+use std::ops::Range;
+use std::str;
+
+use crate::json_token::TokenRef;
+use crate::json_value::ValueRef;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Slot {
+    ObjBeg,
+    ObjEnd,
+    ArrBeg,
+    ArrEnd,
+    Null,
+    Bool(bool),
+    KnownKey(u32),
+    StrBegin,
+    StrEnd,
+    // ranges of the arena
+    Key(Range<usize>),
+    String(Range<usize>),
+    Number(Range<usize>),
+    StrChunk(Range<usize>),
+    Raw(Range<usize>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenBuffer {
+    slots: Vec<Slot>,
+    arena: Vec<u8>,
+}
+
+impl TokenBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes room for `tokens` tokens holding `bytes` bytes of text in total.
+    pub fn with_capacity(tokens: usize, bytes: usize) -> Self {
+        TokenBuffer {
+            slots: Vec::with_capacity(tokens),
+            arena: Vec::with_capacity(bytes),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Removes all tokens, keeping the memory.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.arena.clear();
+    }
+
+    pub fn get(&self, index: usize) -> Option<TokenRef<'_>> {
+        self.slots.get(index).map(|slot| self.token(slot))
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            buffer: self,
+            slots: self.slots.iter(),
+        }
+    }
+
+    pub fn push(&mut self, token: TokenRef<'_>) {
+        let slot = match token {
+            TokenRef::ObjBeg => Slot::ObjBeg,
+            TokenRef::ObjEnd => Slot::ObjEnd,
+            TokenRef::ArrBeg => Slot::ArrBeg,
+            TokenRef::ArrEnd => Slot::ArrEnd,
+            TokenRef::Key(key) => Slot::Key(self.store(key)),
+            TokenRef::KnownKey(id) => Slot::KnownKey(id),
+            TokenRef::Val(ValueRef::Null) => Slot::Null,
+            TokenRef::Val(ValueRef::Bool(b)) => Slot::Bool(b),
+            TokenRef::Val(ValueRef::String(s)) => Slot::String(self.store(s)),
+            TokenRef::Val(ValueRef::Number(n)) => Slot::Number(self.store(n)),
+            TokenRef::StrBegin => Slot::StrBegin,
+            TokenRef::StrChunk(text) => Slot::StrChunk(self.store(text)),
+            TokenRef::StrEnd => Slot::StrEnd,
+            TokenRef::Raw(raw) => Slot::Raw(self.store_bytes(raw)),
+        };
+        self.slots.push(slot);
+    }
+
+    fn store(&mut self, text: &str) -> Range<usize> {
+        self.store_bytes(text.as_bytes())
+    }
+
+    fn store_bytes(&mut self, bytes: &[u8]) -> Range<usize> {
+        let start = self.arena.len();
+        self.arena.extend_from_slice(bytes);
+        start..self.arena.len()
+    }
+
+    fn text(&self, range: &Range<usize>) -> &str {
+        // SAFETY: text ranges are only made by `store`, they cover exactly
+        // the bytes of a `str`
+        unsafe { str::from_utf8_unchecked(&self.arena[range.clone()]) }
+    }
+
+    fn token(&self, slot: &Slot) -> TokenRef<'_> {
+        match slot {
+            Slot::ObjBeg => TokenRef::ObjBeg,
+            Slot::ObjEnd => TokenRef::ObjEnd,
+            Slot::ArrBeg => TokenRef::ArrBeg,
+            Slot::ArrEnd => TokenRef::ArrEnd,
+            Slot::Null => TokenRef::Val(ValueRef::Null),
+            Slot::Bool(b) => TokenRef::Val(ValueRef::Bool(*b)),
+            Slot::KnownKey(id) => TokenRef::KnownKey(*id),
+            Slot::Key(range) => TokenRef::Key(self.text(range)),
+            Slot::String(range) => TokenRef::Val(ValueRef::String(self.text(range))),
+            Slot::Number(range) => TokenRef::Val(ValueRef::Number(self.text(range))),
+            Slot::StrBegin => TokenRef::StrBegin,
+            Slot::StrChunk(range) => TokenRef::StrChunk(self.text(range)),
+            Slot::StrEnd => TokenRef::StrEnd,
+            Slot::Raw(range) => TokenRef::Raw(&self.arena[range.clone()]),
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    buffer: &'a TokenBuffer,
+    slots: std::slice::Iter<'a, Slot>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = TokenRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.next().map(|slot| self.buffer.token(slot))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.slots.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a TokenBuffer {
+    type Item = TokenRef<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod token_buffer_tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::json_stream_reader::JsonStreamReader;
+    use crate::json_token::JsonToken;
+
+    // counts the allocations made by the current thread
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    const DOC: &[u8] =
+        r#"{"id": 1, "name": "café", "tags": ["a\"b", true, null], "n": -2.5e3}"#.as_bytes();
+
+    #[test]
+    fn should_hold_same_tokens_as_read() {
+        let mut reader = JsonStreamReader::new();
+        let mut expected = vec![];
+        for chunk in DOC.chunks(7) {
+            expected.extend(reader.read(chunk).unwrap());
+        }
+        let mut reader = JsonStreamReader::new();
+        let mut out = TokenBuffer::new();
+        let mut tokens = vec![];
+        for chunk in DOC.chunks(7) {
+            reader.read_into(chunk, &mut out).unwrap();
+            tokens.extend(out.iter().map(|token| token.to_token()));
+        }
+        assert_eq!(tokens, expected);
+        assert_eq!(out.get(out.len() - 1), Some(TokenRef::ObjEnd));
+        assert_eq!(out.get(out.len()), None);
+    }
+
+    #[test]
+    fn should_replace_tokens_of_previous_chunk() {
+        let mut reader = JsonStreamReader::new();
+        let mut out = TokenBuffer::new();
+        reader.read_into(br#"["foo", "#, &mut out).unwrap();
+        reader.read_into(br#""bar"]"#, &mut out).unwrap();
+        let tokens: Vec<_> = out.iter().collect();
+        assert_eq!(
+            tokens,
+            vec![TokenRef::Val(ValueRef::String("bar")), TokenRef::ArrEnd]
+        );
+        assert_eq!(out.arena, b"bar");
+    }
+
+    #[test]
+    fn should_not_allocate_once_warmed_up() {
+        let mut doc = b"[".to_vec();
+        for _ in 0..50 {
+            doc.extend_from_slice(DOC);
+            doc.push(b',');
+        }
+        doc.extend_from_slice(b"{}]");
+        let mut reader = JsonStreamReader::new();
+        let mut out = TokenBuffer::new();
+        let read_all = |reader: &mut JsonStreamReader, out: &mut TokenBuffer| {
+            for chunk in doc.chunks(64) {
+                reader.read_into(chunk, out).unwrap();
+            }
+            reader.clear();
+        };
+        read_all(&mut reader, &mut out);
+        let before = ALLOCATIONS.with(Cell::get);
+        read_all(&mut reader, &mut out);
+        assert_eq!(ALLOCATIONS.with(Cell::get), before);
+        let last = out.iter().last().map(|token| token.to_token());
+        assert_eq!(last, Some(JsonToken::ArrEnd));
+    }
+}
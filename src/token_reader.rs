@@ -0,0 +1,643 @@
+//! Pull-based access to the token stream.
+//!
+//! `JsonStreamReader::read` returns all tokens found in a chunk at once.
+//! `TokenReader` pulls chunks from a `std::io::Read` on demand and hands the
+//! tokens out one by one, keeping track of where each of them came from.
+use std::borrow::BorrowMut;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+use crate::checkpoint::Checkpoint;
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_pointer::JsonPointer;
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::json_value::JsonValue;
+use crate::token::{Lexeme, Nesting, State, Token};
+
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+pub struct TokenReader<R, J = JsonStreamReader> {
+    source: R,
+    reader: J,
+    // the last chunk read, w/ the bytes since the last token handed out
+    // before it in `raw_values` mode, so that the tokens after it can be
+    // read again
+    buf: Vec<u8>,
+    // absolute position of the first byte of `buf`
+    buf_start: usize,
+    chunk_size: usize,
+    raw_values: bool,
+    // tokens read but not handed out yet w/ their absolute positions
+    pending: VecDeque<(JsonToken, usize)>,
+    // number of bytes consumed from the source
+    offset: usize,
+    // absolute position of the last token handed out
+    position: usize,
+    // containers open after the last token handed out
+    nesting: Nesting,
+    // where reading continues right after the last token handed out, and
+    // the state of the reader there, unless inside a string
+    resume: usize,
+    resume_state: Option<State>,
+    // number of tokens handed out
+    count: usize,
+    eof: bool,
+    // the stream can't be continued after a syntax or io error
+    error: Option<Error>,
+}
+
+impl<R: Read> TokenReader<R> {
+    pub fn new(source: R) -> Self {
+        TokenReader::with_reader(source, JsonStreamReader::new())
+    }
+}
+
+impl<R: Read, J: BorrowMut<JsonStreamReader>> TokenReader<R, J> {
+    /// Creates a token reader that drives the given `JsonStreamReader`, which
+    /// may be owned or borrowed.
+    ///
+    /// A reader made by `JsonStreamReader::resume` carries on from its
+    /// checkpoint, `source` being the input from the checkpoint offset on.
+    pub fn with_reader(source: R, reader: J) -> Self {
+        let at = reader.borrow().checkpoint();
+        TokenReader {
+            source,
+            reader,
+            buf: vec![],
+            buf_start: at.offset,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            raw_values: false,
+            pending: VecDeque::new(),
+            offset: at.offset,
+            position: at.offset,
+            resume: at.offset,
+            resume_state: at.is_between_tokens().then_some(at.state),
+            nesting: at.stack,
+            count: 0,
+            eof: false,
+            error: None,
+        }
+    }
+
+    /// Sets the number of bytes requested from the source at once.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Keeps the bytes read since the last returned token, for `next_raw`
+    /// to read them again. A token cut by the end of a chunk is then held
+    /// twice, here and in the `JsonStreamReader`.
+    pub fn with_raw_values(mut self) -> Self {
+        self.raw_values = true;
+        self
+    }
+
+    /// Number of bytes consumed from the source so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Absolute offset of the byte that completed the last returned token.
+    pub fn token_offset(&self) -> usize {
+        self.position
+    }
+
+    /// Number of objects and arrays opened but not closed yet by the tokens
+    /// returned so far.
+    pub fn depth(&self) -> usize {
+        self.nesting.depth()
+    }
+
+    /// Number of tokens returned so far.
+    pub fn token_count(&self) -> usize {
+        self.count
+    }
+
+    /// A checkpoint right after the last returned token, for a reader made
+    /// by `JsonStreamReader::resume` to return the tokens that follow it.
+    /// `None` in the middle of a string read in fragments.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        Some(Checkpoint {
+            offset: self.resume,
+            stack: self.nesting.clone(),
+            state: self.resume_state?,
+            lexeme: Lexeme::None,
+            scratch: vec![],
+            capture: false,
+        })
+    }
+
+    // Where a reader resumed right after the last returned token continues.
+    pub(crate) fn resume_offset(&self) -> usize {
+        self.resume
+    }
+
+    /// Whether reading failed, every following call returns the same error.
+    pub fn is_failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Returns the next token, or `None` once the source is exhausted.
+    pub fn next_token(&mut self) -> Result<Option<JsonToken>> {
+        if !self.fill()? {
+            return Ok(None);
+        }
+        let (token, position) = self.pending.pop_front().unwrap();
+        self.position = position;
+        self.count += 1;
+        match token {
+            JsonToken::ObjBeg => self.nesting.push(Token::Obj),
+            JsonToken::ArrBeg => self.nesting.push(Token::Arr),
+            JsonToken::ObjEnd | JsonToken::ArrEnd => {
+                self.nesting.pop();
+            }
+            _ => {}
+        }
+        self.mark_resume(&token, position);
+        Ok(Some(token))
+    }
+
+    /// Returns the next value as its exact text in the document, w/o
+    /// tokenizing it, see `JsonStreamReader::capture_next_value`. Returns
+    /// `None` if the container ends first, leaving its end as the next
+    /// token. Fails with `InvalidFormat` where a key comes next, or if the
+    /// reader was not made `with_raw_values`.
+    pub fn next_raw(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        if !self.raw_values {
+            return Err(self.error(ErrorCode::InvalidFormat));
+        }
+        let state = match self.resume_state {
+            Some(State::ObjFirst) => None,
+            Some(State::AfterValue) if self.nesting.last() == Some(Token::Obj) => None,
+            state => state,
+        };
+        let state = state.ok_or_else(|| self.error(ErrorCode::InvalidFormat))?;
+        // the tokens read ahead are read again
+        self.pending.clear();
+        let reader = self.reader.borrow_mut();
+        reader.restart(self.nesting.clone(), state, self.resume);
+        reader.capture_next_value();
+        let res = self.feed(self.resume - self.buf_start);
+        if let Err(err) = &res {
+            self.error = Some(err.clone());
+        }
+        res?;
+        match self.peek_token()? {
+            Some(JsonToken::Raw(_)) => {}
+            Some(_) => return Ok(None),
+            None => return Err(self.error(ErrorCode::UnexpectedEof)),
+        }
+        let Some(JsonToken::Raw(raw)) = self.next_token()? else {
+            unreachable!()
+        };
+        Ok(Some(raw))
+    }
+
+    /// Returns the next token without consuming it.
+    pub fn peek_token(&mut self) -> Result<Option<&JsonToken>> {
+        if !self.fill()? {
+            return Ok(None);
+        }
+        Ok(self.pending.front().map(|(token, _)| token))
+    }
+
+    /// Name of a key returned as `JsonToken::KnownKey`.
+    pub fn known_key(&self, id: u32) -> Option<&str> {
+        self.reader.borrow().known_keys()?.name(id)
+    }
+
+    // Turns a known key back into a plain key, for consumers that need names.
+    pub(crate) fn named(&self, token: JsonToken) -> JsonToken {
+        match token {
+            JsonToken::KnownKey(id) => match self.known_key(id) {
+                Some(key) => JsonToken::Key(key.into()),
+                None => token,
+            },
+            token => token,
+        }
+    }
+
+    // Same as `named`, also joining the fragments of a long string, for
+    // consumers that need whole values.
+    #[cfg(feature = "serde")]
+    pub(crate) fn whole(&mut self, token: JsonToken) -> Result<JsonToken> {
+        if token != JsonToken::StrBegin {
+            return Ok(self.named(token));
+        }
+        let mut text = String::new();
+        loop {
+            match self.next_token()? {
+                Some(JsonToken::StrChunk(chunk)) => text.push_str(&chunk),
+                Some(JsonToken::StrEnd) => {
+                    return Ok(JsonToken::Val(crate::json_value::JsonValue::String(
+                        text.into(),
+                    )))
+                }
+                Some(_) => return Err(self.error(ErrorCode::InvalidFormat)),
+                None => return Err(self.error(ErrorCode::UnexpectedEof)),
+            }
+        }
+    }
+
+    /// Skips the next value, including everything nested in it.
+    pub fn skip_value(&mut self) -> Result<()> {
+        let depth = self.depth();
+        loop {
+            match self.next_token()? {
+                None => return Err(self.error(ErrorCode::UnexpectedEof)),
+                Some(
+                    JsonToken::Key(_)
+                    | JsonToken::KnownKey(_)
+                    | JsonToken::StrBegin
+                    | JsonToken::StrChunk(_),
+                ) => {}
+                Some(_) if self.depth() == depth => return Ok(()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Skips tokens until the nesting level drops back to `depth`.
+    pub fn skip_to_depth(&mut self, depth: usize) -> Result<()> {
+        while self.depth() > depth {
+            if self.next_token()?.is_none() {
+                return Err(self.error(ErrorCode::UnexpectedEof));
+            }
+        }
+        Ok(())
+    }
+
+    /// Consumes tokens up to the value referenced by `pointer`, so that the
+    /// next token returned is the beginning of that value.
+    pub fn seek(&mut self, pointer: &JsonPointer) -> Result<()> {
+        for segment in pointer.segments() {
+            match self.next_token()? {
+                Some(JsonToken::ObjBeg) => loop {
+                    match self.next_token()? {
+                        Some(JsonToken::Key(key)) if key == *segment => break,
+                        Some(JsonToken::KnownKey(id))
+                            if self.known_key(id) == Some(segment.as_str()) =>
+                        {
+                            break
+                        }
+                        Some(JsonToken::Key(_) | JsonToken::KnownKey(_)) => self.skip_value()?,
+                        Some(_) => return Err(self.error(ErrorCode::PointerNotFound)),
+                        None => return Err(self.error(ErrorCode::UnexpectedEof)),
+                    }
+                },
+                Some(JsonToken::ArrBeg) => {
+                    let index = segment
+                        .parse::<usize>()
+                        .map_err(|_| self.error(ErrorCode::PointerNotFound))?;
+                    for i in 0..=index {
+                        if let Some(JsonToken::ArrEnd) = self.peek_token()? {
+                            return Err(self.error(ErrorCode::PointerNotFound));
+                        }
+                        if i < index {
+                            self.skip_value()?;
+                        }
+                    }
+                }
+                Some(_) => return Err(self.error(ErrorCode::PointerNotFound)),
+                None => return Err(self.error(ErrorCode::UnexpectedEof)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates an error located at the last returned token.
+    pub fn error(&self, code: ErrorCode) -> Error {
+        Error {
+            code,
+            column: self.position,
+        }
+    }
+
+    // Reads chunks until there is a pending token. Returns `false` at the end
+    // of the source.
+    fn fill(&mut self) -> Result<bool> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        let res = self.fill_pending();
+        if let Err(err) = &res {
+            self.error = Some(err.clone());
+        }
+        res
+    }
+
+    fn fill_pending(&mut self) -> Result<bool> {
+        while self.pending.is_empty() {
+            if self.eof {
+                return Ok(false);
+            }
+            let keep = match self.raw_values {
+                true => self.resume,
+                false => self.offset,
+            };
+            self.buf.drain(..keep - self.buf_start);
+            self.buf_start = keep;
+            let from = self.buf.len();
+            self.buf.resize(from + self.chunk_size, 0);
+            let size = loop {
+                match self.source.read(&mut self.buf[from..]) {
+                    Ok(size) => break size,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        return Err(Error {
+                            code: ErrorCode::Io(err.kind()),
+                            column: self.offset,
+                        })
+                    }
+                }
+            };
+            self.buf.truncate(from + size);
+            if size == 0 {
+                self.eof = true;
+                if !self.nesting.is_empty() {
+                    return Err(Error {
+                        code: ErrorCode::UnexpectedEof,
+                        column: self.offset,
+                    });
+                }
+                continue;
+            }
+            self.feed(from)?;
+            self.offset += size;
+        }
+        Ok(true)
+    }
+
+    // Reads the bytes of `buf` from `from` on.
+    fn feed(&mut self, from: usize) -> Result<()> {
+        let base = self.buf_start + from;
+        let pending = &mut self.pending;
+        self.reader
+            .borrow_mut()
+            .read_to(&self.buf[from..], |token, i| {
+                pending.push_back((token, base + i))
+            })
+            .map_err(|err| Error {
+                code: err.code,
+                column: base + err.column,
+            })
+    }
+
+    // Records where reading would continue right after `token`.
+    fn mark_resume(&mut self, token: &JsonToken, position: usize) {
+        self.resume_state = match token {
+            JsonToken::ObjBeg => Some(State::ObjFirst),
+            JsonToken::ArrBeg => Some(State::ArrFirst),
+            JsonToken::Key(_) | JsonToken::KnownKey(_) => Some(State::AfterKey),
+            JsonToken::StrBegin | JsonToken::StrChunk(_) => None,
+            _ if self.nesting.is_empty() => Some(State::End),
+            _ => Some(State::AfterValue),
+        };
+        // numbers are complete at their delimiter, which is left to the
+        // next token
+        let delimited = match token {
+            JsonToken::Val(JsonValue::Number(_)) => true,
+            JsonToken::Raw(raw) => !matches!(raw[0], b'"' | b'{' | b'['),
+            _ => false,
+        };
+        self.resume = position + usize::from(!delimited);
+    }
+}
+
+impl<R: Read, J: BorrowMut<JsonStreamReader>> Iterator for TokenReader<R, J> {
+    type Item = Result<JsonToken>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().transpose()
+    }
+}
+
+#[cfg(test)]
+mod token_reader_tests {
+    use super::*;
+    use crate::json_value::JsonValue;
+    use crate::keys::KnownKeys;
+
+    #[test]
+    fn should_read_tokens_across_chunks() {
+        let buf = r#"{"foo": [1, "bar", true]}"#.as_bytes();
+        let tokens = TokenReader::new(buf)
+            .with_chunk_size(3)
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            tokens.unwrap(),
+            vec![
+                JsonToken::ObjBeg,
+                JsonToken::Key("foo".into()),
+                JsonToken::ArrBeg,
+                JsonToken::Val(JsonValue::Number("1".into())),
+                JsonToken::Val(JsonValue::String("bar".into())),
+                JsonToken::Val(JsonValue::Bool(true)),
+                JsonToken::ArrEnd,
+                JsonToken::ObjEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_track_positions_and_depth() {
+        let buf = r#"{"foo": [1]}"#.as_bytes();
+        //           0123456789012
+        let mut tokens = TokenReader::new(buf).with_chunk_size(4);
+        tokens.next_token().unwrap();
+        assert_eq!((tokens.token_offset(), tokens.depth()), (0, 1));
+        tokens.next_token().unwrap();
+        assert_eq!((tokens.token_offset(), tokens.depth()), (5, 1));
+        tokens.next_token().unwrap();
+        assert_eq!((tokens.token_offset(), tokens.depth()), (8, 2));
+    }
+
+    #[test]
+    fn should_skip_value() {
+        let buf = r#"[{"a": [1, 2]}, 3]"#.as_bytes();
+        let mut tokens = TokenReader::new(buf);
+        tokens.next_token().unwrap();
+        tokens.skip_value().unwrap();
+        assert_eq!(
+            tokens.next_token().unwrap(),
+            Some(JsonToken::Val(JsonValue::Number("3".into())))
+        );
+    }
+
+    #[test]
+    fn should_seek_pointer() {
+        let buf = r#"{"meta": {"rows": 0}, "data": [{"rows": [1]}, {"rows": [2, 3]}]}"#;
+        let mut tokens = TokenReader::new(buf.as_bytes());
+        tokens
+            .seek(&JsonPointer::parse("/data/1/rows").unwrap())
+            .unwrap();
+        assert_eq!(tokens.next_token().unwrap(), Some(JsonToken::ArrBeg));
+        assert_eq!(
+            tokens.next_token().unwrap(),
+            Some(JsonToken::Val(JsonValue::Number("2".into())))
+        );
+    }
+
+    #[test]
+    fn should_seek_and_skip_known_keys() {
+        let buf = r#"{"a": {"b": 1}, "c": [true]}"#.as_bytes();
+        let reader = JsonStreamReader::new().with_known_keys(KnownKeys::new(["a", "c"]));
+        let mut tokens = TokenReader::with_reader(buf, reader);
+        tokens.seek(&JsonPointer::parse("/c/0").unwrap()).unwrap();
+        assert_eq!(
+            tokens.next_token().unwrap(),
+            Some(JsonToken::Val(JsonValue::Bool(true)))
+        );
+        assert_eq!(tokens.known_key(1), Some("c"));
+    }
+
+    #[test]
+    fn should_return_pointer_not_found() {
+        let buf = r#"{"data": [1, 2]}"#;
+        let mut tokens = TokenReader::new(buf.as_bytes());
+        let res = tokens.seek(&JsonPointer::parse("/data/2").unwrap());
+        assert_eq!(res.unwrap_err().code, ErrorCode::PointerNotFound);
+    }
+
+    #[test]
+    fn should_read_raw_values() {
+        let buf = r#"{"id": 1, "payload" : { "x": [1, 2, "}\"" ] },
+            "n": 12, "s": "a\"b", "t": true, "arr": [ 3 , [4] ], "last": {}}"#;
+        let raw = |text: &str| Ok(Some(text.as_bytes().to_vec()));
+        for size in 1..=buf.len() {
+            let mut tokens = TokenReader::new(buf.as_bytes())
+                .with_chunk_size(size)
+                .with_raw_values();
+            let mut keys = vec![];
+            while let Some(token) = tokens.next_token().unwrap() {
+                let JsonToken::Key(key) = token else {
+                    continue;
+                };
+                match key.as_str() {
+                    "payload" => assert_eq!(tokens.next_raw(), raw(r#"{ "x": [1, 2, "}\"" ] }"#)),
+                    "n" => assert_eq!(tokens.next_raw(), raw("12")),
+                    "s" => assert_eq!(tokens.next_raw(), raw(r#""a\"b""#)),
+                    "t" => assert_eq!(tokens.next_raw(), raw("true")),
+                    "arr" => {
+                        assert_eq!(tokens.next_token(), Ok(Some(JsonToken::ArrBeg)));
+                        assert_eq!(tokens.next_raw(), raw("3"));
+                        assert_eq!(tokens.next_raw(), raw("[4]"));
+                        assert_eq!(tokens.next_raw(), Ok(None));
+                        assert_eq!(tokens.next_token(), Ok(Some(JsonToken::ArrEnd)));
+                    }
+                    _ => {}
+                }
+                keys.push(key);
+            }
+            assert_eq!(keys, ["id", "payload", "n", "s", "t", "arr", "last"]);
+            assert_eq!(tokens.depth(), 0);
+        }
+    }
+
+    #[test]
+    fn should_hold_one_chunk_unless_reading_raw_values() {
+        let doc = format!(r#"["{}", 1]"#, "x".repeat(100_000));
+        let mut tokens = TokenReader::new(doc.as_bytes()).with_chunk_size(1000);
+        while tokens.next_token().unwrap().is_some() {
+            assert!(tokens.buf.len() <= 1000);
+        }
+        let mut tokens = TokenReader::new(doc.as_bytes());
+        tokens.next_token().unwrap();
+        assert_eq!(
+            tokens.next_raw().unwrap_err().code,
+            ErrorCode::InvalidFormat
+        );
+    }
+
+    #[test]
+    fn should_not_read_raw_key() {
+        let mut tokens = TokenReader::new(r#"{"a": 1}"#.as_bytes()).with_raw_values();
+        tokens.next_token().unwrap();
+        assert_eq!(
+            tokens.next_raw().unwrap_err().code,
+            ErrorCode::InvalidFormat
+        );
+        assert_eq!(tokens.next_token(), Ok(Some(JsonToken::Key("a".into()))));
+        let mut tokens = TokenReader::new(r#"{"a": "#.as_bytes()).with_raw_values();
+        tokens.next_token().unwrap();
+        tokens.next_token().unwrap();
+        assert_eq!(
+            tokens.next_raw().unwrap_err().code,
+            ErrorCode::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn should_report_absolute_error_column() {
+        let buf = r#"{"foo": 1, ?}"#.as_bytes();
+        let res = TokenReader::new(buf)
+            .with_chunk_size(4)
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            res,
+            Err(Error {
+                column: 11,
+                code: ErrorCode::ExpectedAnyTerm
+            })
+        );
+    }
+
+    #[test]
+    fn should_report_unexpected_eof() {
+        let buf = r#"{"foo": [1, 2"#.as_bytes();
+        let res = TokenReader::new(buf).collect::<Result<Vec<_>>>();
+        assert_eq!(
+            res,
+            Err(Error {
+                column: 13,
+                code: ErrorCode::UnexpectedEof
+            })
+        );
+    }
+
+    #[test]
+    fn should_resume_after_last_token() {
+        let doc = br#"[{"id": 1, "tags": ["a", "b"]}, {"id": 22}, 3.5, "end"]"#;
+        let all: Vec<_> = TokenReader::new(&doc[..])
+            .with_chunk_size(5)
+            .map(|token| token.unwrap())
+            .collect();
+        for count in 0..all.len() {
+            let mut tokens = TokenReader::new(&doc[..]).with_chunk_size(5);
+            for _ in 0..count {
+                tokens.next_token().unwrap();
+            }
+            let checkpoint = tokens.checkpoint().unwrap();
+            let reader = JsonStreamReader::resume(&checkpoint);
+            let mut rest = TokenReader::with_reader(&doc[checkpoint.offset()..], reader);
+            assert_eq!(rest.depth(), tokens.depth());
+            let mut resumed = vec![];
+            while let Some(token) = rest.next_token().unwrap() {
+                resumed.push((token, rest.token_offset()));
+            }
+            let mut expected = vec![];
+            while let Some(token) = tokens.next_token().unwrap() {
+                expected.push((token, tokens.token_offset()));
+            }
+            assert_eq!(resumed, expected, "after {} tokens", count);
+        }
+    }
+
+    #[test]
+    fn should_not_checkpoint_inside_fragmented_string() {
+        let reader = JsonStreamReader::new().with_string_fragments(2);
+        let mut tokens = TokenReader::with_reader(&br#"["abcdef"]"#[..], reader).with_chunk_size(4);
+        tokens.next_token().unwrap();
+        assert_eq!(tokens.next_token().unwrap(), Some(JsonToken::StrBegin));
+        assert_eq!(tokens.checkpoint(), None);
+    }
+}
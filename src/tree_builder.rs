@@ -0,0 +1,372 @@
+//! Builds `JsonNode` trees out of `JsonToken`s.
+//!
+//! Tokens can come from any number of `read` calls, a node is returned as
+//! soon as its last token has been pushed.
+use std::borrow::BorrowMut;
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::json_node::JsonNode;
+use crate::json_stream_reader::JsonStreamReader;
+use crate::json_token::JsonToken;
+use crate::token_reader::TokenReader;
+
+/// What to do when an object has the same key more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    /// Keep the first member, ignore the following ones.
+    FirstWins,
+    /// Keep the position of the first member but the value of the last one.
+    #[default]
+    LastWins,
+    /// Fail with `ErrorCode::DuplicateKey`.
+    Error,
+    /// Keep every member.
+    KeepAll,
+}
+
+#[derive(Debug)]
+enum Frame {
+    Arr(Vec<JsonNode>),
+    Obj {
+        members: Vec<(String, JsonNode)>,
+        // member index by key, not used when all members are kept
+        index: HashMap<String, usize>,
+        key: Option<String>,
+    },
+    // text of a string read in fragments
+    Str(String),
+}
+
+#[derive(Debug, Default)]
+pub struct TreeBuilder {
+    stack: Vec<Frame>,
+    duplicate_keys: DuplicateKeys,
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        TreeBuilder::default()
+    }
+
+    pub fn with_duplicate_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    /// Whether a node is being built.
+    pub fn is_building(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    /// Adds the next token. Returns the node it completes, if any.
+    ///
+    /// A scalar pushed outside of any container is a complete node by itself,
+    /// which allows building subtrees picked out of a larger stream.
+    pub fn push(&mut self, token: JsonToken) -> Result<Option<JsonNode>> {
+        let node = match token {
+            JsonToken::ObjBeg => {
+                self.stack.push(Frame::Obj {
+                    members: vec![],
+                    index: HashMap::new(),
+                    key: None,
+                });
+                return Ok(None);
+            }
+            JsonToken::ArrBeg => {
+                self.stack.push(Frame::Arr(vec![]));
+                return Ok(None);
+            }
+            JsonToken::Key(key) => {
+                return match self.stack.last_mut() {
+                    Some(Frame::Obj {
+                        key: slot @ None, ..
+                    }) => {
+                        *slot = Some(key.into_string());
+                        Ok(None)
+                    }
+                    _ => Err(invalid_format()),
+                };
+            }
+            JsonToken::ObjEnd => match self.stack.pop() {
+                Some(Frame::Obj {
+                    members, key: None, ..
+                }) => JsonNode::Object(members),
+                _ => return Err(invalid_format()),
+            },
+            JsonToken::ArrEnd => match self.stack.pop() {
+                Some(Frame::Arr(items)) => JsonNode::Array(items),
+                _ => return Err(invalid_format()),
+            },
+            // known keys have no name here, raw values are not parsed
+            JsonToken::KnownKey(_) | JsonToken::Raw(_) => return Err(invalid_format()),
+            JsonToken::Val(value) => JsonNode::from(value),
+            JsonToken::StrBegin => {
+                self.stack.push(Frame::Str(String::new()));
+                return Ok(None);
+            }
+            JsonToken::StrChunk(text) => {
+                return match self.stack.last_mut() {
+                    Some(Frame::Str(s)) => {
+                        s.push_str(&text);
+                        Ok(None)
+                    }
+                    _ => Err(invalid_format()),
+                };
+            }
+            JsonToken::StrEnd => match self.stack.pop() {
+                Some(Frame::Str(s)) => JsonNode::String(s),
+                _ => return Err(invalid_format()),
+            },
+        };
+        self.add(node)
+    }
+
+    /// Adds all tokens returned by a `read` call. Returns the nodes they
+    /// complete.
+    pub fn extend<I>(&mut self, tokens: I) -> Result<Vec<JsonNode>>
+    where
+        I: IntoIterator<Item = JsonToken>,
+    {
+        let mut nodes = vec![];
+        for token in tokens {
+            if let Some(node) = self.push(token)? {
+                nodes.push(node);
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// Builds the next value read from `tokens`. Errors are located at the
+    /// offending token.
+    pub fn build_next<R, J>(&mut self, tokens: &mut TokenReader<R, J>) -> Result<JsonNode>
+    where
+        R: Read,
+        J: BorrowMut<JsonStreamReader>,
+    {
+        loop {
+            let token = match tokens.next_token()? {
+                Some(token) => tokens.named(token),
+                None => return Err(tokens.error(ErrorCode::UnexpectedEof)),
+            };
+            match self.push(token) {
+                Ok(Some(node)) => return Ok(node),
+                Ok(None) => {}
+                Err(err) => {
+                    self.clear();
+                    return Err(tokens.error(err.code));
+                }
+            }
+        }
+    }
+
+    // Puts a complete node into its parent.
+    fn add(&mut self, node: JsonNode) -> Result<Option<JsonNode>> {
+        match self.stack.last_mut() {
+            None => Ok(Some(node)),
+            Some(Frame::Arr(items)) => {
+                items.push(node);
+                Ok(None)
+            }
+            Some(Frame::Obj {
+                members,
+                index,
+                key,
+            }) => {
+                let key = key.take().ok_or_else(invalid_format)?;
+                if self.duplicate_keys == DuplicateKeys::KeepAll {
+                    members.push((key, node));
+                    return Ok(None);
+                }
+                match index.get(&key) {
+                    None => {
+                        index.insert(key.clone(), members.len());
+                        members.push((key, node));
+                    }
+                    Some(&i) => match self.duplicate_keys {
+                        DuplicateKeys::LastWins => members[i].1 = node,
+                        DuplicateKeys::Error => {
+                            return Err(Error {
+                                code: ErrorCode::DuplicateKey(key),
+                                column: 0,
+                            })
+                        }
+                        _ => {}
+                    },
+                }
+                Ok(None)
+            }
+            Some(Frame::Str(_)) => Err(invalid_format()),
+        }
+    }
+}
+
+fn invalid_format() -> Error {
+    Error {
+        code: ErrorCode::InvalidFormat,
+        column: 0,
+    }
+}
+
+impl JsonNode {
+    /// Reads a whole document into a tree. Meant for small documents.
+    pub fn from_reader<R: Read>(source: R) -> Result<JsonNode> {
+        let mut tokens = TokenReader::new(source);
+        TreeBuilder::new().build_next(&mut tokens)
+    }
+}
+
+#[cfg(test)]
+mod tree_builder_tests {
+    use super::*;
+    use crate::json_value::JsonValue;
+    use crate::keys::KnownKeys;
+
+    fn num(n: &str) -> JsonNode {
+        JsonNode::Number(n.to_string())
+    }
+
+    #[test]
+    fn should_build_tree_across_reads() {
+        let mut reader = JsonStreamReader::new();
+        let mut builder = TreeBuilder::new();
+        let first = builder
+            .extend(reader.read(br#"{"a": [1, {"b"#).unwrap())
+            .unwrap();
+        assert!(first.is_empty());
+        let second = builder
+            .extend(reader.read(br#"": null}], "c": true}"#).unwrap())
+            .unwrap();
+        assert_eq!(
+            second,
+            vec![JsonNode::Object(vec![
+                (
+                    "a".to_string(),
+                    JsonNode::Array(vec![
+                        num("1"),
+                        JsonNode::Object(vec![("b".to_string(), JsonNode::Null)])
+                    ])
+                ),
+                ("c".to_string(), JsonNode::Bool(true)),
+            ])]
+        );
+    }
+
+    #[test]
+    fn should_apply_duplicate_key_policy() {
+        let doc = br#"{"a": 1, "b": 2, "a": 3}"#;
+        let build = |policy| {
+            let tokens = JsonStreamReader::new().read(doc).unwrap();
+            TreeBuilder::new()
+                .with_duplicate_keys(policy)
+                .extend(tokens)
+                .map(|mut nodes| nodes.pop().unwrap())
+        };
+        let members = |members: &[(&str, &str)]| {
+            JsonNode::Object(
+                members
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), num(v)))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            build(DuplicateKeys::FirstWins),
+            Ok(members(&[("a", "1"), ("b", "2")]))
+        );
+        assert_eq!(
+            build(DuplicateKeys::LastWins),
+            Ok(members(&[("a", "3"), ("b", "2")]))
+        );
+        assert_eq!(
+            build(DuplicateKeys::KeepAll),
+            Ok(members(&[("a", "1"), ("b", "2"), ("a", "3")]))
+        );
+        assert_eq!(
+            build(DuplicateKeys::Error).unwrap_err().code,
+            ErrorCode::DuplicateKey("a".to_string())
+        );
+    }
+
+    #[test]
+    fn should_build_scalar_subtree() {
+        let mut builder = TreeBuilder::new();
+        let node = builder.push(JsonToken::Val(JsonValue::Bool(false)));
+        assert_eq!(node, Ok(Some(JsonNode::Bool(false))));
+    }
+
+    #[test]
+    fn should_reject_invalid_token_order() {
+        let mut builder = TreeBuilder::new();
+        builder.push(JsonToken::ObjBeg).unwrap();
+        let res = builder.push(JsonToken::Val(JsonValue::Null));
+        assert_eq!(res, Err(invalid_format()));
+    }
+
+    #[test]
+    fn should_locate_errors_when_reading() {
+        let doc = br#"{"a": 1, "a": 2}"#;
+        //           0123456789012345
+        let mut tokens = TokenReader::new(&doc[..]);
+        let res = TreeBuilder::new()
+            .with_duplicate_keys(DuplicateKeys::Error)
+            .build_next(&mut tokens);
+        assert_eq!(
+            res,
+            Err(Error {
+                code: ErrorCode::DuplicateKey("a".to_string()),
+                column: 15
+            })
+        );
+    }
+
+    #[test]
+    fn should_read_node_from_reader() {
+        let node = JsonNode::from_reader(&br#"[true, "x"]"#[..]).unwrap();
+        assert_eq!(
+            node,
+            JsonNode::Array(vec![
+                JsonNode::Bool(true),
+                JsonNode::String("x".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn should_join_string_fragments() {
+        let mut reader = JsonStreamReader::new().with_string_fragments(2);
+        let mut builder = TreeBuilder::new();
+        let mut nodes = builder.extend(reader.read(br#"["abc"#).unwrap()).unwrap();
+        nodes.extend(
+            builder
+                .extend(reader.read(br#"def", "x"]"#).unwrap())
+                .unwrap(),
+        );
+        assert_eq!(
+            nodes,
+            vec![JsonNode::Array(vec![
+                JsonNode::String("abcdef".to_string()),
+                JsonNode::String("x".to_string())
+            ])]
+        );
+    }
+
+    #[test]
+    fn should_name_known_keys_when_reading() {
+        let reader = JsonStreamReader::new().with_known_keys(KnownKeys::new(["a"]));
+        let mut tokens = TokenReader::with_reader(&br#"{"a": 1, "b": 2}"#[..], reader);
+        let node = TreeBuilder::new().build_next(&mut tokens).unwrap();
+        assert_eq!(
+            node,
+            JsonNode::Object(vec![
+                ("a".to_string(), num("1")),
+                ("b".to_string(), num("2"))
+            ])
+        );
+    }
+}
@@ -1,77 +1,114 @@
-use crate::token::Token;
+/// Appends the decoded raw content of a string literal (without the quotes)
+/// to `out`, which is left for the caller to check as UTF-8.
+///
+/// Returns `None` if the data contains an invalid escape sequence.
+pub(crate) fn unescape_into(data: &[u8], out: &mut Vec<u8>) -> Option<()> {
+    (unescape_prefix(data, out)? == data.len()).then_some(())
+}
 
-pub(crate) fn squash(tokens: &mut Vec<Token>) {
-    while let Some(token) = tokens.last() {
-        match token {
-            Token::Colon => break,
-            Token::Arr => break,
-            _ => {}
+/// Same as `unescape_into` for the first part of a string literal: stops
+/// before an escape sequence cut short by the end of `data`. Returns the
+/// number of bytes decoded.
+pub(crate) fn unescape_prefix(data: &[u8], out: &mut Vec<u8>) -> Option<usize> {
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != b'\\' {
+            out.push(data[i]);
+            i += 1;
+            continue;
         }
-        tokens.pop();
-    }
-    if let Some(token) = tokens.last() {
-        if let Token::Colon = token {
-            while let Some(token) = tokens.pop() {
-                if let Token::Key(_) = token {
-                    break;
+        let start = i;
+        let Some(&escaped) = data.get(i + 1) else {
+            return Some(start);
+        };
+        i += 2;
+        match escaped {
+            b'"' => out.push(b'"'),
+            b'\\' => out.push(b'\\'),
+            b'/' => out.push(b'/'),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'u' => {
+                let Some(digits) = data.get(i..i + 4) else {
+                    return Some(start);
+                };
+                let mut code = hex4(digits)?;
+                i += 4;
+                if (0xd800..0xdc00).contains(&code) {
+                    if data.len() < i + 6 {
+                        return Some(start);
+                    }
+                    // a high surrogate must be followed by an escaped low one
+                    if data.get(i..i + 2)? != b"\\u" {
+                        return None;
+                    }
+                    let low = hex4(data.get(i + 2..i + 6)?)?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return None;
+                    }
+                    i += 6;
+                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
                 }
+                let ch = char::from_u32(code)?;
+                out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
             }
+            _ => return None,
         }
     }
-    if tokens.len() > 0 {
-        tokens.push(Token::None);
-    }
+    Some(i)
+}
+
+fn hex4(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0, |code, &ch| {
+        let digit = (ch as char).to_digit(16)?;
+        Some(code * 16 + digit)
+    })
 }
 
 #[cfg(test)]
-mod squash_tests {
+mod unescape_tests {
     use super::*;
 
-    #[test]
-    fn should_squash_simple_obj() {
-        let mut tokens = vec![
-            Token::Obj,
-            Token::Key("foo".as_bytes().to_vec()),
-            Token::Colon,
-            Token::String("bar".as_bytes().to_vec()),
-        ];
-
-        squash(&mut tokens);
-        assert_eq!(tokens, vec![Token::Obj, Token::None]);
+    fn unescape(data: &[u8]) -> Option<String> {
+        let mut out = vec![];
+        unescape_into(data, &mut out)?;
+        String::from_utf8(out).ok()
     }
 
     #[test]
-    fn should_squash_key_val() {
-        let mut tokens = vec![
-            Token::Key("foo".as_bytes().to_vec()),
-            Token::Colon,
-            Token::String("bar".as_bytes().to_vec()),
-        ];
-
-        squash(&mut tokens);
-        assert_eq!(tokens, vec![]);
+    fn should_decode_simple_escapes() {
+        assert_eq!(
+            unescape(br#"a\"b\\c\/d\n\t"#),
+            Some("a\"b\\c/d\n\t".to_string())
+        );
     }
 
     #[test]
-    fn should_squash_key_val_list() {
-        let mut tokens = vec![
-            Token::Arr,
-            Token::String("foo".as_bytes().to_vec()),
-            Token::Comma,
-            Token::String("bar".as_bytes().to_vec()),
-            Token::Comma,
-            Token::String("zar".as_bytes().to_vec()),
-        ];
-
-        squash(&mut tokens);
-        assert_eq!(tokens, vec![Token::Arr, Token::None]);
+    fn should_decode_unicode_escapes() {
+        assert_eq!(unescape(br"caf\u00e9"), Some("caf\u{e9}".to_string()));
+        assert_eq!(unescape(br"\ud83d\ude00"), Some("\u{1f600}".to_string()));
     }
 
     #[test]
-    fn should_ignore_empty_list() {
-        let mut tokens = vec![];
+    fn should_stop_before_cut_escapes() {
+        for (data, decoded) in [
+            (&br"ab\"[..], 2),
+            (br"ab\u00", 2),
+            (br"ab\ud83d\ude0", 2),
+            (br"ab\n", 4),
+        ] {
+            let mut out = vec![];
+            assert_eq!(unescape_prefix(data, &mut out), Some(decoded));
+        }
+    }
 
-        squash(&mut tokens);
-        assert_eq!(tokens, vec![]);
+    #[test]
+    fn should_reject_invalid_escapes() {
+        assert_eq!(unescape(br"\x"), None);
+        assert_eq!(unescape(br"\u12"), None);
+        assert_eq!(unescape(br"\ud83d"), None);
     }
 }
@@ -0,0 +1,49 @@
+//! Exit statuses of the command line tool, which pipelines rely on.
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_json_stream_reader"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn should_exit_with_status_of_outcome() {
+    let ok = run(&["tokens"], r#"{"a": [1, true]}"#);
+    assert_eq!(ok.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(ok.stdout).unwrap(),
+        "{\nkey \"a\"\n[\nnumber 1\ntrue\n]\n}\n"
+    );
+
+    let invalid = run(&["validate"], r#"{"a": [1, }"#);
+    assert_eq!(invalid.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(invalid.stderr).unwrap(),
+        "<stdin>: expected a value at column 10\n"
+    );
+
+    let usage = run(&["validate", "--frob"], "");
+    assert_eq!(usage.status.code(), Some(2));
+    assert!(String::from_utf8(usage.stderr)
+        .unwrap()
+        .starts_with("error: unknown option --frob\n"));
+
+    let missing = run(&["validate", "no/such/file.json"], "");
+    assert_eq!(missing.status.code(), Some(3));
+    assert!(String::from_utf8(missing.stderr)
+        .unwrap()
+        .starts_with("no/such/file.json: "));
+}